@@ -0,0 +1,66 @@
+#!/usr/bin/env python3
+# A stand-in UCI engine for testing the tournament runner. It forwards every
+# command to a real engine and can be told to misbehave after a number of its
+# own moves, so forfeits can be exercised:
+#
+#   stand_in_engine.py <engine> [--crash-after n] [--stall-after n] [--illegal-after n]
+#
+# e.g. rustChess tournament \
+#   --engine cmd=target/release/rustChess,name=good \
+#   --engine cmd=scripts/stand_in_engine.py,arg=target/release/rustChess,arg=--illegal-after,arg=5,name=bad
+
+import subprocess
+import sys
+import time
+
+
+def main():
+    args = sys.argv[1:]
+    if not args:
+        sys.exit("usage: stand_in_engine.py <engine> [--crash-after n] [--stall-after n] [--illegal-after n]")
+    engine_path, faults = args[0], {}
+    for flag, value in zip(args[1::2], args[2::2]):
+        faults[flag.lstrip("-")] = int(value)
+
+    engine = subprocess.Popen([engine_path], stdin=subprocess.PIPE, stdout=subprocess.PIPE, text=True, bufsize=1)
+    moves = 0
+
+    def forward(command):
+        engine.stdin.write(command + "\n")
+        engine.stdin.flush()
+
+    for line in sys.stdin:
+        command = line.strip()
+        if command == "quit":
+            break
+        if command.startswith("go"):
+            if moves == faults.get("crash-after", -1):
+                engine.kill()
+                sys.exit(1)
+            if moves == faults.get("stall-after", -1):
+                time.sleep(3600)
+        forward(command)
+
+        # relay the engine's answer for commands that expect one
+        expected = {"uci": "uciok", "isready": "readyok"}.get(command.split(" ")[0])
+        if command.startswith("go"):
+            expected = "bestmove"
+        while expected:
+            answer = engine.stdout.readline()
+            if not answer:
+                sys.exit(1)
+            if answer.startswith("bestmove"):
+                if moves == faults.get("illegal-after", -1):
+                    answer = "bestmove a1a1\n"
+                moves += 1
+            sys.stdout.write(answer)
+            sys.stdout.flush()
+            if answer.split(" ")[0].strip() == expected:
+                break
+
+    forward("quit")
+    engine.wait()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use self::stats::{Sprt, SprtResult, Tally};
+use crate::{
+    board::{fen::Epd, Board},
+    datagen::play_opening,
+    defs::Colors,
+    movegen::defs::Move,
+    moves::AttackTable,
+    pgn::{writer::{write_game, ExportOptions}, Game, Node},
+    rng::Rng,
+    search::{defs::SearchLimits, Search},
+    uci::{time_budget, UciOptions},
+};
+
+pub mod stats;
+
+#[derive(Debug)]
+pub enum MatchError {
+    Io(String),
+    BadOption(String),
+    BadOpening(usize, String),
+    Engine(String),
+}
+
+impl Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error writing match games: {e}"),
+            Self::BadOption(o) => write!(f, "Error in match options: {o}"),
+            Self::BadOpening(line, e) => write!(f, "Error in openings line {line}: {e}"),
+            Self::Engine(e) => write!(f, "Error in engine options: {e}"),
+        }
+    }
+}
+
+// base time and increment in milliseconds
+#[derive(Debug, Clone, Copy)]
+pub struct TimeControl {
+    pub base: u64,
+    pub inc: u64,
+}
+
+impl TimeControl {
+    // seconds, optionally with an increment: "10", "10+0.1"
+    pub fn parse(s: &str) -> Option<TimeControl> {
+        let (base, inc) = s.split_once('+').unwrap_or((s, "0"));
+        let ms = |v: &str| v.parse::<f64>().ok().filter(|v| *v >= 0.0).map(|v| (v * 1000.0).round() as u64);
+        Some(TimeControl { base: ms(base)?, inc: ms(inc)? })
+    }
+
+    // the PGN TimeControl tag
+    pub fn as_tag(&self) -> String {
+        let seconds = |ms: u64| if ms.is_multiple_of(1000) { (ms / 1000).to_string() } else { format!("{}", ms as f64 / 1000.0) };
+        if self.inc == 0 { seconds(self.base) } else { format!("{}+{}", seconds(self.base), seconds(self.inc)) }
+    }
+}
+
+pub struct MatchOptions {
+    // setoption name/value pairs of each engine
+    pub engine_a: Vec<(String, String)>,
+    pub engine_b: Vec<(String, String)>,
+    pub name_a: String,
+    pub name_b: String,
+    // EPD or FEN lines, played in order, each one twice with colours swapped
+    pub openings: Option<String>,
+    // random plies after the opening; 0 with an opening file, 8 without
+    pub random_plies: Option<usize>,
+    pub games: usize,
+    pub time_control: Option<TimeControl>,
+    pub nodes: Option<u64>,
+    // a side scoring at least resign_score for resign_plies consecutive plies wins
+    pub resign_score: i32,
+    pub resign_plies: usize,
+    // from draw_after plies on, |score| <= draw_score for draw_plies plies is a draw
+    pub draw_after: usize,
+    pub draw_score: i32,
+    pub draw_plies: usize,
+    pub max_plies: usize,
+    pub sprt: Option<Sprt>,
+    pub pgn: String,
+    pub pgn_format: ExportOptions,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl Default for MatchOptions {
+    fn default() -> MatchOptions {
+        MatchOptions {
+            engine_a: Vec::new(),
+            engine_b: Vec::new(),
+            name_a: String::from("A"),
+            name_b: String::from("B"),
+            openings: None,
+            random_plies: None,
+            games: 100,
+            time_control: None,
+            nodes: None,
+            resign_score: 1000,
+            resign_plies: 4,
+            draw_after: 80,
+            draw_score: 10,
+            draw_plies: 8,
+            max_plies: 400,
+            sprt: None,
+            pgn: String::from("match.pgn"),
+            pgn_format: ExportOptions::default(),
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: 1,
+        }
+    }
+}
+
+impl MatchOptions {
+    // rustChess match [--a Name=Value]... [--b Name=Value]... [--name-a s] [--name-b s]
+    //   [--openings file] [--random-plies n] [--games n] [--tc seconds+inc] [--nodes n]
+    //   [--resign-score cp] [--resign-plies n] [--draw-after n] [--draw-score cp] [--draw-plies n]
+    //   [--max-plies n] [--sprt elo0,elo1] [--alpha f] [--beta f] [--pgn file] [--pgn-format export|reduced]
+    //   [--threads n] [--seed n]
+    pub fn parse(args: &[String]) -> Result<MatchOptions, MatchError> {
+        let mut options = MatchOptions::default();
+        let (mut alpha, mut beta) = (0.05, 0.05);
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = iter.next().ok_or_else(|| MatchError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || MatchError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--a" | "--b" => {
+                    let (name, v) = value.split_once('=').ok_or_else(bad)?;
+                    let engine = if arg == "--a" { &mut options.engine_a } else { &mut options.engine_b };
+                    engine.push((name.to_string(), v.to_string()));
+                }
+                "--name-a" => options.name_a = value.clone(),
+                "--name-b" => options.name_b = value.clone(),
+                "--openings" => options.openings = Some(value.clone()),
+                "--random-plies" => options.random_plies = Some(value.parse().map_err(|_| bad())?),
+                "--games" => options.games = value.parse().map_err(|_| bad())?,
+                "--tc" => options.time_control = Some(TimeControl::parse(value).ok_or_else(bad)?),
+                "--nodes" => options.nodes = Some(value.parse().map_err(|_| bad())?),
+                "--resign-score" => options.resign_score = value.parse().map_err(|_| bad())?,
+                "--resign-plies" => options.resign_plies = value.parse().map_err(|_| bad())?,
+                "--draw-after" => options.draw_after = value.parse().map_err(|_| bad())?,
+                "--draw-score" => options.draw_score = value.parse().map_err(|_| bad())?,
+                "--draw-plies" => options.draw_plies = value.parse().map_err(|_| bad())?,
+                "--max-plies" => options.max_plies = value.parse().map_err(|_| bad())?,
+                "--sprt" => {
+                    let (elo0, elo1) = value.split_once(',').ok_or_else(bad)?;
+                    let elo0 = elo0.trim().parse().map_err(|_| bad())?;
+                    let elo1 = elo1.trim().parse().map_err(|_| bad())?;
+                    options.sprt = Some(Sprt { elo0, elo1, alpha, beta });
+                }
+                "--alpha" => alpha = value.parse().map_err(|_| bad())?,
+                "--beta" => beta = value.parse().map_err(|_| bad())?,
+                "--pgn" => options.pgn = value.clone(),
+                "--pgn-format" => options.pgn_format = ExportOptions::from_name(value).ok_or_else(bad)?,
+                "--threads" => options.threads = value.parse().map_err(|_| bad())?,
+                "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+                _ => return Err(MatchError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if let Some(sprt) = &mut options.sprt {
+            sprt.alpha = alpha;
+            sprt.beta = beta;
+            if sprt.elo0 >= sprt.elo1 || !(alpha > 0.0 && alpha < 0.5) || !(beta > 0.0 && beta < 0.5) {
+                return Err(MatchError::BadOption(String::from("sprt needs elo0 < elo1 and alpha, beta strictly between 0 and 0.5")));
+            }
+        }
+        if options.time_control.is_none() && options.nodes.is_none() {
+            options.nodes = Some(5000);
+        }
+        if options.threads == 0 || options.games == 0 || options.nodes == Some(0) {
+            return Err(MatchError::BadOption(String::from("threads, games and nodes must be positive")));
+        }
+        Ok(options)
+    }
+}
+
+// the position of every EPD line, or of a full FEN with move counters
+pub fn load_openings(path: &str) -> Result<Vec<Board>, MatchError> {
+    let text = fs::read_to_string(path).map_err(|e| MatchError::Io(format!("{path}: {e}")))?;
+    let mut openings = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let epd = Epd::parse(line).map_err(|e| MatchError::BadOpening(n + 1, e.to_string()))?;
+        openings.push(epd.board);
+    }
+    if openings.is_empty() {
+        return Err(MatchError::BadOpening(0, String::from("no positions")));
+    }
+    Ok(openings)
+}
+
+fn engine_options(settings: &[(String, String)]) -> Result<UciOptions, MatchError> {
+    let mut options = UciOptions::new();
+    for (name, value) in settings {
+        options.set(name, value).map_err(MatchError::Engine)?;
+    }
+    Ok(options)
+}
+
+// a finished game as written to PGN
+pub struct GameRecord {
+    pub event: String,
+    pub round: usize,
+    pub white: String,
+    pub black: String,
+    pub start: Board,
+    pub moves: Vec<Move>,
+    // from white's point of view
+    pub result: f64,
+    pub time_control: Option<TimeControl>,
+    // the PGN Termination tag and a closing comment
+    pub termination: &'static str,
+    pub reason: String,
+}
+
+struct Player<'a> {
+    search: Search<'a>,
+    // remaining clock time in milliseconds
+    clock: i64,
+}
+
+fn play_game(
+    at: &AttackTable,
+    options: &MatchOptions,
+    players: [&mut Player; 2],
+    mut board: Board,
+    mut keys: Vec<u64>,
+) -> (Vec<Move>, f64, &'static str, String) {
+    let [white, black] = players;
+    let mut moves = Vec::new();
+    let (mut white_wins, mut black_wins, mut quiet_plies) = (0, 0, 0);
+    if let Some(tc) = options.time_control {
+        white.clock = tc.base as i64;
+        black.clock = tc.base as i64;
+    }
+
+    for ply in 0..options.max_plies {
+        let white_to_move = board.side_to_move() == Colors::WHITE as usize;
+        let side = if white_to_move { "White" } else { "Black" };
+        // claimable draws are claimed straight away
+        let status = board.status(at, &keys);
+        if let Some(result) = status.result() {
+            return (moves, result, "normal", String::from(status.as_string()));
+        }
+
+        let player = if white_to_move { &mut *white } else { &mut *black };
+        let mut limits = SearchLimits { nodes: options.nodes, ..Default::default() };
+        if let Some(tc) = options.time_control {
+            limits.time = Some(time_budget(player.clock.max(0) as u64, tc.inc, None));
+        }
+        let started = Instant::now();
+        let found = player.search.go(&board, &keys, limits);
+        if let Some(tc) = options.time_control {
+            player.clock -= started.elapsed().as_millis() as i64;
+            if player.clock < 0 {
+                let result = if white_to_move { 0.0 } else { 1.0 };
+                return (moves, result, "time forfeit", format!("{side} loses on time"));
+            }
+            player.clock += tc.inc as i64;
+        }
+
+        let white_score = if white_to_move { found.score } else { -found.score };
+        white_wins = if white_score >= options.resign_score { white_wins + 1 } else { 0 };
+        black_wins = if white_score <= -options.resign_score { black_wins + 1 } else { 0 };
+        quiet_plies = if ply >= options.draw_after && white_score.abs() <= options.draw_score { quiet_plies + 1 } else { 0 };
+        if white_wins >= options.resign_plies {
+            return (moves, 1.0, "adjudication", String::from("Black resigns"));
+        }
+        if black_wins >= options.resign_plies {
+            return (moves, 0.0, "adjudication", String::from("White resigns"));
+        }
+        if quiet_plies >= options.draw_plies {
+            return (moves, 0.5, "adjudication", String::from("Draw by adjudication"));
+        }
+
+        moves.push(found.best_move);
+        keys.push(board.key);
+        board.make_move(found.best_move);
+    }
+    (moves, 0.5, "adjudication", String::from("Draw by move limit"))
+}
+
+pub fn result_string(result: f64) -> &'static str {
+    match result {
+        r if r > 0.75 => "1-0",
+        r if r < 0.25 => "0-1",
+        _ => "1/2-1/2",
+    }
+}
+
+// today's date as YYYY.MM.DD, from days since the epoch to the civil calendar
+pub fn today() -> String {
+    let days = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() / 86400).unwrap_or(0) as i64;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+impl GameRecord {
+    pub fn as_game(&self, date: &str) -> Game {
+        let mut game = Game::new(self.start);
+        let tc = self.time_control.map(|tc| tc.as_tag()).unwrap_or_else(|| String::from("-"));
+        for (name, value) in [
+            ("Event", self.event.clone()),
+            ("Site", String::from("?")),
+            ("Date", date.to_string()),
+            ("Round", self.round.to_string()),
+            ("White", self.white.clone()),
+            ("Black", self.black.clone()),
+            ("TimeControl", tc),
+            ("Termination", self.termination.to_string()),
+        ] {
+            game.set_tag(name, &value);
+        }
+        game.result = result_string(self.result).to_string();
+        game.moves = self.moves.iter().map(|&mv| Node { mv, ..Node::default() }).collect();
+        match game.moves.last_mut() {
+            Some(last) => last.comments.push(self.reason.clone()),
+            None => game.comments.push(self.reason.clone()),
+        }
+        game
+    }
+}
+
+pub fn write_pgn(out: &mut impl Write, game: &GameRecord, date: &str, at: &AttackTable, format: &ExportOptions) -> std::io::Result<()> {
+    write_game(out, &game.as_game(date), at, format)
+}
+
+struct Progress<W: Write> {
+    pgn: W,
+    // from engine A's point of view
+    tally: Tally,
+    // A's score in the pairs with one game finished, by pair
+    unpaired: HashMap<usize, f64>,
+    verdict: SprtResult,
+}
+
+fn report(options: &MatchOptions, tally: &Tally) -> String {
+    let mut line = format!("Games {}: +{} ={} -{}", tally.games(), tally.wins, tally.draws, tally.losses);
+    if tally.pairs.iter().any(|&n| n > 0) {
+        line.push_str(&format!(", pairs {:?}", tally.pairs));
+    }
+    match tally.elo() {
+        Some((elo, margin)) => line.push_str(&format!(", Elo {elo:.1} +/- {margin:.1}")),
+        None => line.push_str(", Elo -"),
+    }
+    if let Some(sprt) = &options.sprt {
+        let (lower, upper) = sprt.bounds();
+        line.push_str(&format!(", LLR {:.2} ({lower:.2}, {upper:.2})", tally.llr(sprt.elo0, sprt.elo1)));
+    }
+    line
+}
+
+// plays engine A against engine B, A with white in even rounds, until the
+// game count is reached or the SPRT accepts a hypothesis
+pub fn run_match(at: &AttackTable, options: &MatchOptions) -> Result<(), MatchError> {
+    let engine_a = engine_options(&options.engine_a)?;
+    let engine_b = engine_options(&options.engine_b)?;
+    let openings = match &options.openings {
+        Some(path) => load_openings(path)?,
+        None => Vec::new(),
+    };
+    let random_plies = options.random_plies.unwrap_or(if openings.is_empty() { 8 } else { 0 });
+    let file = File::create(&options.pgn).map_err(|e| MatchError::Io(format!("{}: {e}", options.pgn)))?;
+    let progress = Mutex::new(Progress {
+        pgn: BufWriter::new(file),
+        tally: Tally::default(),
+        unpaired: HashMap::new(),
+        verdict: SprtResult::Continue,
+    });
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let date = today();
+
+    thread::scope(|s| -> Result<(), MatchError> {
+        let workers: Vec<_> = (0..options.threads.min(options.games))
+            .map(|_| {
+                s.spawn(|| -> Result<(), MatchError> {
+                    let mut a = Player { search: Search::new(at, engine_a.hash_mb), clock: 0 };
+                    let mut b = Player { search: Search::new(at, engine_b.hash_mb), clock: 0 };
+                    engine_a.configure(&mut a.search);
+                    engine_b.configure(&mut b.search);
+                    loop {
+                        let index = next_game.fetch_add(1, Ordering::Relaxed);
+                        if index >= options.games || stop.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                        // both games of a pair start from the same position
+                        let pair = index / 2;
+                        let mut rng = Rng::new(options.seed.wrapping_mul(0x9e3779b97f4a7c15) ^ pair as u64);
+                        let book = if openings.is_empty() { &openings[..] } else { &openings[pair % openings.len()..][..1] };
+                        let Some((start, keys)) = play_opening(at, book, random_plies, &mut rng) else { continue };
+
+                        a.search.clear();
+                        b.search.clear();
+                        let a_white = index.is_multiple_of(2);
+                        let players = if a_white { [&mut a, &mut b] } else { [&mut b, &mut a] };
+                        let (moves, result, termination, reason) = play_game(at, options, players, start, keys);
+                        let (white, black) = if a_white { (&options.name_a, &options.name_b) } else { (&options.name_b, &options.name_a) };
+                        let game = GameRecord {
+                            event: String::from("rustChess match"),
+                            round: index + 1,
+                            white: white.clone(),
+                            black: black.clone(),
+                            start,
+                            moves,
+                            result,
+                            time_control: options.time_control,
+                            termination,
+                            reason,
+                        };
+
+                        let mut progress = progress.lock().expect("match progress lock poisoned");
+                        write_pgn(&mut progress.pgn, &game, &date, at, &options.pgn_format).map_err(|e| MatchError::Io(e.to_string()))?;
+                        progress.pgn.flush().map_err(|e| MatchError::Io(e.to_string()))?;
+                        let score = if a_white { result } else { 1.0 - result };
+                        progress.tally.add(score);
+                        match progress.unpaired.remove(&pair) {
+                            Some(first) => progress.tally.add_pair(first, score),
+                            None => {
+                                progress.unpaired.insert(pair, score);
+                            }
+                        }
+                        println!("{}", report(options, &progress.tally));
+                        if let Some(sprt) = &options.sprt {
+                            let verdict = sprt.test(&progress.tally);
+                            if verdict != SprtResult::Continue && progress.verdict == SprtResult::Continue {
+                                progress.verdict = verdict;
+                                stop.store(true, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().expect("match thread panicked")?;
+        }
+        Ok(())
+    })?;
+
+    let progress = progress.into_inner().expect("match progress lock poisoned");
+    println!("{} vs {}: {}", options.name_a, options.name_b, report(options, &progress.tally));
+    match progress.verdict {
+        SprtResult::H0 => println!("SPRT: H0 accepted"),
+        SprtResult::H1 => println!("SPRT: H1 accepted"),
+        SprtResult::Continue => (),
+    }
+    println!("Games written to {}", options.pgn);
+    Ok(())
+}
@@ -0,0 +1,188 @@
+// match statistics from one player's point of view. games played as pairs
+// from the same opening with colours swapped are counted by pair score too
+// (the pentanomial model), since the two games of a pair aren't independent;
+// without finished pairs every game is treated as an independent trial
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    // finished pairs by their total score: 0, 1/2, 1, 3/2 and 2 points
+    pub pairs: [u32; 5],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtResult {
+    // the first hypothesis, elo0, is accepted
+    H0,
+    H1,
+    Continue,
+}
+
+impl Tally {
+    pub fn add(&mut self, score: f64) {
+        match score {
+            s if s > 0.75 => self.wins += 1,
+            s if s < 0.25 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+    }
+
+    // both games of a pair, each already counted with add
+    pub fn add_pair(&mut self, first: f64, second: f64) {
+        self.pairs[((first + second) * 2.0).round() as usize] += 1;
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // number of samples, their mean score per game and the variance of one
+    // sample: pairs when any have finished, single games otherwise
+    fn samples(&self) -> (f64, f64, f64) {
+        let pairs: u32 = self.pairs.iter().sum();
+        let (counts, scores): (&[u32], &[f64]) = if pairs > 0 {
+            (&self.pairs, &[0.0, 0.25, 0.5, 0.75, 1.0])
+        } else {
+            (&[self.losses, self.draws, self.wins], &[0.0, 0.5, 1.0])
+        };
+        let n = counts.iter().sum::<u32>() as f64;
+        if n == 0.0 {
+            return (0.0, 0.5, 0.0);
+        }
+        let mean = counts.iter().zip(scores).map(|(&c, s)| c as f64 * s).sum::<f64>() / n;
+        let variance = counts.iter().zip(scores).map(|(&c, s)| c as f64 * (s - mean).powi(2)).sum::<f64>() / n;
+        (n, mean, variance)
+    }
+
+    // elo difference and the half width of its 95% confidence interval, None
+    // while every game has been won or every game lost
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (n, p, variance) = self.samples();
+        if n == 0.0 || p <= 0.0 || p >= 1.0 {
+            return None;
+        }
+        let margin = 1.96 * (variance / n).sqrt();
+        let lo = score_to_elo((p - margin).max(1e-6));
+        let hi = score_to_elo((p + margin).min(1.0 - 1e-6));
+        Some((score_to_elo(p), (hi - lo) / 2.0))
+    }
+
+    // log-likelihood ratio of elo1 against elo0 in the normal approximation
+    pub fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let (n, p, variance) = self.samples();
+        if n == 0.0 || variance <= 0.0 {
+            return 0.0;
+        }
+        let s0 = elo_to_score(elo0);
+        let s1 = elo_to_score(elo1);
+        n * (s1 - s0) * (2.0 * p - s0 - s1) / (2.0 * variance)
+    }
+}
+
+impl Sprt {
+    // lower and upper LLR bounds
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    pub fn test(&self, tally: &Tally) -> SprtResult {
+        let llr = tally.llr(self.elo0, self.elo1);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtResult::H0
+        } else if llr >= upper {
+            SprtResult::H1
+        } else {
+            SprtResult::Continue
+        }
+    }
+}
+
+pub fn score_to_elo(score: f64) -> f64 {
+    // written so an even score gives 0 rather than -0
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+pub fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tally(wins: u32, draws: u32, losses: u32) -> Tally {
+        Tally { wins, draws, losses, ..Default::default() }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn sprt_bounds_and_llr() {
+        let sprt = Sprt { elo0: 0.0, elo1: 5.0, alpha: 0.05, beta: 0.05 };
+        let (lower, upper) = sprt.bounds();
+        assert!(close(lower, -2.94443897916644) && close(upper, 2.94443897916644));
+
+        let t = tally(1200, 1600, 1000);
+        assert!(close(t.llr(0.0, 5.0), 4.312191439770868), "{}", t.llr(0.0, 5.0));
+        assert_eq!(sprt.test(&t), SprtResult::H1);
+        assert_eq!(sprt.test(&tally(1000, 1600, 1200)), SprtResult::H0);
+        assert_eq!(sprt.test(&tally(120, 160, 100)), SprtResult::Continue);
+        assert_eq!(tally(0, 0, 0).llr(0.0, 5.0), 0.0);
+        // all draws has no variance to work with
+        assert_eq!(tally(0, 10, 0).llr(0.0, 5.0), 0.0);
+    }
+
+    #[test]
+    fn elo_with_error_bars() {
+        let (elo, margin) = tally(60, 20, 20).elo().unwrap();
+        assert!(close(elo, 147.19071411783773) && close(margin, 66.01463862816014), "{elo} {margin}");
+        let (elo, margin) = tally(1200, 1600, 1000).elo().unwrap();
+        assert!(close(elo, 18.30299622427002) && close(margin, 8.410210352419636), "{elo} {margin}");
+        assert_eq!(tally(10, 10, 10).elo().unwrap().0, 0.0);
+        assert!(tally(3, 0, 0).elo().is_none());
+        assert!(tally(0, 0, 0).elo().is_none());
+        assert!(close(score_to_elo(elo_to_score(-123.0)), -123.0));
+    }
+
+    #[test]
+    fn pentanomial_pairs() {
+        let mut t = Tally::default();
+        for (first, second, n) in [(0.0, 0.0, 10), (0.0, 0.5, 60), (0.5, 0.5, 100), (1.0, 0.0, 100), (1.0, 0.5, 90), (1.0, 1.0, 20)] {
+            for _ in 0..n {
+                t.add(first);
+                t.add(second);
+                t.add_pair(first, second);
+            }
+        }
+        assert_eq!(t.pairs, [10, 60, 200, 90, 20]);
+        assert_eq!((t.wins, t.draws, t.losses), (230, 350, 180));
+        assert!(close(t.llr(0.0, 5.0), 1.84883639427628), "{}", t.llr(0.0, 5.0));
+        let (elo, margin) = t.elo().unwrap();
+        assert!(close(elo, 22.89066806382982) && close(margin, 14.61454171926631), "{elo} {margin}");
+
+        // a win and a loss from the same opening is one even pair, which
+        // says less than two independent decisive games
+        let mut split = Tally::default();
+        split.add(1.0);
+        split.add(0.0);
+        split.add_pair(1.0, 0.0);
+        split.add(1.0);
+        split.add(0.5);
+        split.add_pair(1.0, 0.5);
+        let games = tally(split.wins, split.draws, split.losses);
+        assert!(split.elo().unwrap().1 < games.elo().unwrap().1);
+    }
+}
@@ -1,15 +1,17 @@
 pub mod bitboard;
-mod fen;
-
-use std::fmt;
+pub mod fen;
+pub mod playmove;
+pub mod status;
+pub mod zobrist;
 
 use self::{
-    fen::FenResult,
-    bitboard::{Bitboard, print_bitboard, get_bit, set_bit, clear_bit, pop_bit, count_bits, bitboard_to_array},
-
+    bitboard::{Bitboard, print_bitboard, get_lsb},
 };
 
-use crate::defs::{Colors, Color, Pieces, BB_SQUARES, EMPTY, NrOf, Piece, Square, FEN_START_POSITION};
+use crate::defs::{Colors, Color, Pieces, BB_SQUARES, EMPTY, NrOf, Piece, Square, Variant};
+
+// h1, a1, h8 and a8
+pub const STANDARD_CASTLING_ROOKS: [u8; 4] = [7, 0, 63, 56];
 
 // create a struct to represent the board with bitboards
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -17,10 +19,20 @@ pub struct Board {
     pub pieces: [Bitboard; NrOf::PIECE_TYPES],
     pub color: [Bitboard; NrOf::COLORS],
     pub castling: u8,
+    // rook squares for the rights WK, WQ, BK and BQ, in bit order
+    pub castling_rooks: [u8; 4],
+    // Chess960 rules for notation: X-FEN castling and king takes rook in UCI
+    pub chess960: bool,
+    pub variant: Variant,
+    // crazyhouse pieces in hand by colour and piece type
+    pub pockets: [[u8; NrOf::PIECE_TYPES]; NrOf::COLORS],
+    // crazyhouse pieces that were pawns, which go back into a pocket as pawns
+    pub promoted: Bitboard,
     pub en_passant: Option<u8>,
     pub half_move: u8,
     pub turn: bool,
-    pub full_move: u8,
+    pub full_move: u16,
+    pub key: u64,
 }
 
 impl Board {
@@ -29,13 +41,20 @@ impl Board {
             pieces: [EMPTY; NrOf::PIECE_TYPES],
             color: [EMPTY; NrOf::COLORS],
             castling: 0,
+            castling_rooks: STANDARD_CASTLING_ROOKS,
+            chess960: false,
+            variant: Variant::Standard,
+            pockets: [[0; NrOf::PIECE_TYPES]; NrOf::COLORS],
+            promoted: EMPTY,
             en_passant: None,
             half_move: 0,
             turn: true,
             full_move: 0,
+            key: 0,
         }
     }
 
+    #[allow(dead_code)]
     pub fn print_bitboards(&self) {
         for i in 0..NrOf::PIECE_TYPES {
             print!("{}: ", i);
@@ -61,6 +80,23 @@ impl Board {
         self.color[Colors::WHITE as Color] | self.color[Colors::BLACK as Color]
     }
 
+    // piece type on `sq`, Pieces::NONE if the square is empty
+    #[inline(always)]
+    pub fn piece_on(&self, square: Square) -> Piece {
+        let bb = BB_SQUARES[square];
+        for piece in 0..NrOf::PIECE_TYPES {
+            if self.pieces[piece] & bb != EMPTY {
+                return piece;
+            }
+        }
+        Pieces::NONE as Piece
+    }
+
+    #[inline(always)]
+    pub fn king_square(&self, color: Color) -> Square {
+        get_lsb(self.get_pieces(Pieces::KING as usize, color))
+    }
+
     #[inline(always)]
     pub fn remove_piece(&mut self, piece: Piece, square: Square, color: usize) {
         self.pieces[piece] ^= BB_SQUARES[square];
@@ -74,6 +110,7 @@ impl Board {
     }
 
     #[inline(always)]
+    #[allow(dead_code)]
     pub fn move_piece(&mut self, piece: Piece, from: Square, to: Square) {
         let color = if self.color[Colors::WHITE as Color] & BB_SQUARES[from] != 0 {
             Colors::WHITE as Color
@@ -84,16 +121,23 @@ impl Board {
         self.add_piece(piece, to, color);
     }
 
-    //TODO: implement init
-    pub fn init(&mut self) {}
+    pub fn init(&mut self) {
+        self.key = self.compute_key();
+    }
 
     pub fn reset(&mut self) {
         self.pieces = [EMPTY; NrOf::PIECE_TYPES];
         self.color = [EMPTY; NrOf::COLORS];
         self.castling = 0;
+        self.castling_rooks = STANDARD_CASTLING_ROOKS;
+        self.chess960 = false;
+        self.variant = Variant::Standard;
+        self.pockets = [[0; NrOf::PIECE_TYPES]; NrOf::COLORS];
+        self.promoted = EMPTY;
         self.en_passant = None;
         self.half_move = 0;
         self.turn = true;
         self.full_move = 0;
+        self.key = 0;
     }
 }
@@ -2,6 +2,7 @@ use crate::defs::{NrOf, Square};
 
 pub type Bitboard = u64;
 
+#[allow(dead_code)]
 pub fn print_bitboard(bb: Bitboard) {
     println!();
     for r in 0..NrOf::RANKS {
@@ -31,17 +32,24 @@ pub fn get_lsb(bb: Bitboard) -> Square {
     bb.trailing_zeros() as Square
 }
 
+#[inline(always)]
+pub fn get_msb(bb: Bitboard) -> Square {
+    63 - bb.leading_zeros() as Square
+}
+
 #[inline(always)]
 pub fn get_bit(bb: Bitboard, sq: Square) -> bool {
     bb & (1 << sq) != 0
 }
 
 #[inline(always)]
+#[allow(dead_code)]
 pub fn set_bit(bb: &mut Bitboard, sq: Square) {
     *bb |= 1 << sq;
 }
 
 #[inline(always)]
+#[allow(dead_code)]
 pub fn clear_bit(bb: &mut Bitboard, sq: Square) {
     *bb &= !(1 << sq);
 }
@@ -53,10 +61,11 @@ pub fn pop_bit(bb: &mut Bitboard, sq: Square) {
     };
 }
 
+#[allow(dead_code)]
 pub fn bitboard_to_array(bb: Bitboard) -> [bool; 64] {
     let mut array = [false; 64];
-    for i in 0..64 {
-        array[i] = get_bit(bb, i);
+    for (i, b) in array.iter_mut().enumerate() {
+        *b = get_bit(bb, i);
     }
     array
 }
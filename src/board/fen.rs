@@ -1,22 +1,27 @@
 use strum::IntoEnumIterator;
 use crate::{
     board::{Board},
-    defs::{Castling, Square, FEN_START_POSITION, MAX_MOVES, Colors, Color, Pieces, ALL_SQUARES, EMPTY},
+    defs::{Castling, Square, FEN_START_POSITION, MAX_MOVES, Colors, Color, NrOf, Pieces, Variant, ALL_SQUARES},
+    movegen::{defs::Move, san::{move_to_san, parse_san, SanError}},
+    moves::AttackTable,
 };
 
 use if_chain::if_chain;
 use std::fmt::{self, Display};
+use std::mem;
 use std::ops::RangeInclusive;
 
 const FEN_NR_OF_PARTS: usize = 6;
 const LIST_OF_PIECES: &str = "kqrbnpKQRBNP";
 const EP_SQUARES_WHITE: RangeInclusive<Square> = ALL_SQUARES::A3 as Square..=ALL_SQUARES::H3 as Square;
 const EP_SQUARES_BLACK: RangeInclusive<Square> = ALL_SQUARES::A6 as Square..=ALL_SQUARES::H6 as Square;
-const WHITE_OR_BLACK: &str = "wb";
 const SPLITTER: char = '/';
 const DASH: char = '-';
 const EM_DASH: char = '–';
 const SPACE: char = ' ';
+const PROMOTED: char = '~';
+// pieces in hand are listed in this order, white's first
+const POCKET_PIECES: [Pieces; 5] = [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT, Pieces::PAWN];
 
 #[derive(Debug)]
 pub enum FenError {
@@ -53,14 +58,14 @@ impl Board {
         let parts = split_fen_string(fen_string)?;
         let parsers = create_part_parsers();
 
-        let mut temp = self.clone();
+        let mut temp = *self;
         temp.reset();
 
         let mut i: usize = 0;
         while i < FEN_NR_OF_PARTS {
             let parser = parsers[i];
             let part = &parts[i];
-            parser(&mut temp, &part);
+            parser(&mut temp, part)?;
             i += 1;
         }
 
@@ -71,6 +76,122 @@ impl Board {
     }
 }
 
+impl Board {
+    pub fn as_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let square = rank * 8 + file;
+                let piece = self.piece_on(square);
+                if piece == Pieces::NONE as usize {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                let c = Pieces::from_index(piece).as_char();
+                let white = self.color[Colors::WHITE as Color] & (1 << square) != 0;
+                fen.push(if white { c } else { c.to_ascii_lowercase() });
+                if self.variant == Variant::Crazyhouse && self.promoted & (1 << square) != 0 {
+                    fen.push(PROMOTED);
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push(SPLITTER);
+            }
+        }
+        if self.variant == Variant::Crazyhouse {
+            fen.push('[');
+            for color in [Colors::WHITE, Colors::BLACK] {
+                for piece in POCKET_PIECES {
+                    let c = piece.as_char();
+                    let c = if color == Colors::WHITE { c } else { c.to_ascii_lowercase() };
+                    for _ in 0..self.pockets[color as Color][piece as usize] {
+                        fen.push(c);
+                    }
+                }
+            }
+            fen.push(']');
+        }
+
+        fen.push(SPACE);
+        fen.push(if self.turn { 'w' } else { 'b' });
+        fen.push(SPACE);
+        if self.castling == 0 {
+            fen.push(DASH);
+        }
+        for (right, c) in [(Castling::WK, 'K'), (Castling::WQ, 'Q'), (Castling::BK, 'k'), (Castling::BQ, 'q')] {
+            if self.castling & right != 0 {
+                fen.push(self.castling_char(right, c));
+            }
+        }
+        fen.push(SPACE);
+        match self.en_passant {
+            Some(sq) => fen.push_str(ALL_SQUARES::iter().nth(sq as usize).map_or("-", |s| s.as_string())),
+            None => fen.push(DASH),
+        }
+        fen.push_str(&format!(" {} {}", self.half_move, self.full_move));
+        fen
+    }
+}
+
+impl Board {
+    // X-FEN: the usual letter unless another rook stands further out on the
+    // same side in Chess960, then the rook's file
+    fn castling_char(&self, right: u8, c: char) -> char {
+        let rook = self.castling_rooks[right.trailing_zeros() as usize] as Square;
+        let white = c.is_ascii_uppercase();
+        let color = if white { Colors::WHITE } else { Colors::BLACK } as Color;
+        let back_rank = rook - rook % 8;
+        let outer = if c.eq_ignore_ascii_case(&'k') { rook + 1..back_rank + 8 } else { back_rank..rook };
+        let rooks = self.get_pieces(Pieces::ROOK as usize, color);
+        if !self.chess960 || outer.into_iter().all(|sq| rooks & (1 << sq) == 0) {
+            return c;
+        }
+        let file = (b'a' + (rook % 8) as u8) as char;
+        if white { file.to_ascii_uppercase() } else { file }
+    }
+}
+
+// the Chess960 start position with Scharnagl number `n`, 518 being the
+// usual one: bishops, queen and knights go on the free squares from left to
+// right as the number's digits say, and rook, king, rook on the three left over
+pub fn chess960_fen(n: usize) -> Option<String> {
+    if n >= 960 {
+        return None;
+    }
+    let mut rank = [None; 8];
+    let mut n = n;
+    rank[2 * (n % 4) + 1] = Some('B');
+    n /= 4;
+    rank[2 * (n % 4)] = Some('B');
+    n /= 4;
+    place(&mut rank, n % 6, 'Q');
+    n /= 6;
+    // the ten ways to put two knights on five squares, in order
+    let (first, second) = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)][n];
+    place(&mut rank, second, 'N');
+    place(&mut rank, first, 'N');
+    for piece in ['R', 'K', 'R'] {
+        place(&mut rank, 0, piece);
+    }
+    let white: String = rank.iter().flatten().collect();
+    Some(format!("{}/pppppppp/8/8/8/8/PPPPPPPP/{white} w KQkq - 0 1", white.to_ascii_lowercase()))
+}
+
+// puts `piece` on the empty square with index `i` among the empty ones
+fn place(rank: &mut [Option<char>; 8], i: usize, piece: char) {
+    if let Some(square) = rank.iter_mut().filter(|s| s.is_none()).nth(i) {
+        *square = Some(piece);
+    }
+}
+
 fn split_fen_string(fen_string: Option<&str>) -> SplitResult {
     const SHORT_FEN_LENGTH: usize = 4;
 
@@ -105,7 +226,21 @@ fn create_part_parsers() -> [FenPartParser; FEN_NR_OF_PARTS] {
     ]
 }
 
+// crazyhouse adds the pieces in hand, either as [Qp] after the board or as a
+// ninth rank, and marks promoted pieces with a ~. the pockets come first so
+// a ~ without them is refused
 fn pieces(board: &mut Board, part: &str) -> FenResult {
+    let (part, pocket) = match part.split_once('[') {
+        Some((placement, rest)) => (placement, Some(rest.strip_suffix(']').ok_or(FenError::Part1)?)),
+        None => match part.match_indices(SPLITTER).nth(7) {
+            Some((i, _)) => (&part[..i], Some(&part[i + 1..])),
+            None => (part, None),
+        },
+    };
+    if let Some(pocket) = pocket {
+        pockets(board, pocket)?;
+    }
+
     let mut rank: u8 = 7;
     let mut file: u8 = 0;
 
@@ -165,6 +300,12 @@ fn pieces(board: &mut Board, part: &str) -> FenResult {
                     file += n as u8;
                 }
             }
+            PROMOTED => {
+                if board.variant != Variant::Crazyhouse || file == 0 || board.get_all_pieces() & (1 << (square - 1)) == 0 {
+                    return Err(FenError::Part1);
+                }
+                board.promoted |= 1 << (square - 1);
+            }
             SPLITTER => {
                 if file != 8 {
                     return Err(FenError::Part1);
@@ -182,6 +323,22 @@ fn pieces(board: &mut Board, part: &str) -> FenResult {
     Ok(())
 }
 
+fn pockets(board: &mut Board, part: &str) -> FenResult {
+    board.variant = Variant::Crazyhouse;
+    for c in part.chars().filter(|&c| c != DASH) {
+        let color = if c.is_ascii_uppercase() { Colors::WHITE } else { Colors::BLACK } as Color;
+        let piece = POCKET_PIECES
+            .into_iter()
+            .find(|p| p.as_char() == c.to_ascii_uppercase())
+            .ok_or(FenError::Part1)? as usize;
+        board.pockets[color][piece] += 1;
+        if board.pockets[color][piece] as usize > NrOf::POCKET {
+            return Err(FenError::Part1);
+        }
+    }
+    Ok(())
+}
+
 fn color(board: &mut Board, part: &str) -> FenResult {
     if part.len() == 1 {
         if part == "w" {
@@ -195,21 +352,50 @@ fn color(board: &mut Board, part: &str) -> FenResult {
     Err(FenError::Part2)
 }
 
+// KQkq, X-FEN which names a rook by its file when it isn't the outermost one
+// on its side, or Shredder-FEN with files only (HAha). a right without a king
+// and rook on the back rank to go with it is dropped
 fn castling(board: &mut Board, part: &str) -> FenResult {
-    if (1..=4).contains(&part.len()) {
-        for c in part.chars() {
-            match c {
-                'K' => board.castling |= Castling::WK,
-                'Q' => board.castling |= Castling::WQ,
-                'k' => board.castling |= Castling::BK,
-                'q' => board.castling |= Castling::BQ,
-                '-' => (),
-                _ => return Err(FenError::Part3),
+    if !(1..=4).contains(&part.len()) {
+        return Err(FenError::Part3);
+    }
+    for c in part.chars() {
+        if c == DASH {
+            continue;
+        }
+        let color = if c.is_ascii_uppercase() { Colors::WHITE } else { Colors::BLACK } as Color;
+        let back_rank = if color == Colors::WHITE as Color { 0 } else { 56 };
+        let rooks: Vec<Square> = (back_rank..back_rank + 8)
+            .filter(|&sq| board.get_pieces(Pieces::ROOK as usize, color) & (1 << sq) != 0)
+            .collect();
+        let king = board.get_pieces(Pieces::KING as usize, color);
+        if king == 0 || king.trailing_zeros() as Square / 8 != back_rank / 8 {
+            continue;
+        }
+        let king = king.trailing_zeros() as Square;
+        let rook = match c.to_ascii_lowercase() {
+            'k' => rooks.iter().copied().filter(|&r| r > king).max(),
+            'q' => rooks.iter().copied().filter(|&r| r < king).min(),
+            f @ 'a'..='h' => {
+                board.chess960 = true;
+                rooks.iter().copied().find(|&r| r == back_rank + (f as u8 - b'a') as Square)
             }
+            _ => return Err(FenError::Part3),
+        };
+        let Some(rook) = rook else { continue };
+        let right = match (color == Colors::WHITE as Color, rook > king) {
+            (true, true) => Castling::WK,
+            (true, false) => Castling::WQ,
+            (false, true) => Castling::BK,
+            (false, false) => Castling::BQ,
+        };
+        board.castling |= right;
+        board.castling_rooks[right.trailing_zeros() as usize] = rook as u8;
+        if king % 8 != 4 || !matches!(rook % 8, 0 | 7) {
+            board.chess960 = true;
         }
-        return Ok(());
     }
-    Err(FenError::Part3)
+    Ok(())
 }
 
 //CHECK: does this work?
@@ -252,9 +438,8 @@ fn half_move_clock(board: &mut Board, part: &str) -> FenResult {
 
 fn full_move_number(board: &mut Board, part: &str) -> FenResult {
     if_chain! {
-        if (1..=3).contains(&part.len());
-        if let Ok(n) = part.parse::<u8>();
-        if n <= MAX_MOVES;
+        if (1..=4).contains(&part.len());
+        if let Ok(n) = part.parse::<u16>();
         then {
             board.full_move = n;
             return Ok(());
@@ -262,3 +447,345 @@ fn full_move_number(board: &mut Board, part: &str) -> FenResult {
     }
     Err(FenError::Part6)
 }
+
+#[derive(Debug)]
+pub enum EpdError {
+    Fen(FenError),
+    Syntax(String),
+    Operand(String, String),
+    Move(SanError),
+}
+
+impl Display for EpdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fen(e) => write!(f, "{e}"),
+            Self::Syntax(s) => write!(f, "Error in EPD operations: {s}"),
+            Self::Operand(opcode, s) => write!(f, "Error in EPD opcode {opcode}: bad operand {s}"),
+            Self::Move(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+// a position with its EPD operations, kept in order with raw operands so
+// unknown opcodes survive a round trip. moves are SAN and only resolved
+// against the board when asked for
+#[derive(Debug, Clone)]
+pub struct Epd {
+    pub board: Board,
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    // four fields and operations, each ended by a semicolon. a full FEN with
+    // move counters is accepted as well, hmvc and fmvn override them
+    pub fn parse(line: &str) -> Result<Epd, EpdError> {
+        let line = line.trim();
+        let mut fields = Vec::new();
+        let mut rest = line;
+        while fields.len() < 4 {
+            let field = rest.split_whitespace().next().ok_or(EpdError::Fen(FenError::IncorrectLength))?;
+            fields.push(field);
+            rest = rest.trim_start()[field.len()..].trim_start();
+        }
+        let mut counters = vec![String::from("0"), String::from("1")];
+        let numbers: Vec<&str> = rest.split_whitespace().take(2).collect();
+        if numbers.len() == 2 && numbers.iter().all(|n| n.parse::<u16>().is_ok()) {
+            counters = numbers.iter().map(|n| n.to_string()).collect();
+            for n in numbers {
+                rest = rest.trim_start()[n.len()..].trim_start();
+            }
+        }
+
+        let mut epd = Epd { board: Board::new(), operations: parse_operations(rest)? };
+        // the typed accessors read only the first operand, so numbers take exactly one
+        let numbers = [
+            ("acd", epd.depth().is_some()),
+            ("ce", epd.eval().is_some()),
+            ("hmvc", epd.half_move_clock().is_some()),
+            ("fmvn", epd.full_move_number().is_some()),
+        ];
+        for (opcode, valid) in numbers {
+            match epd.operands(opcode) {
+                Some([_]) if valid => (),
+                Some(operands) => return Err(EpdError::Operand(opcode.to_string(), operands.join(" "))),
+                None => (),
+            }
+        }
+        if let Some(n) = epd.half_move_clock() {
+            counters[0] = n.to_string();
+        }
+        if let Some(n) = epd.full_move_number() {
+            counters[1] = n.to_string();
+        }
+
+        let fen = format!("{} {}", fields.join(" "), counters.join(" "));
+        epd.board.parse_fen(Some(&fen)).map_err(EpdError::Fen)?;
+        Ok(epd)
+    }
+
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations.iter().find(|(o, _)| o == opcode).map(|(_, v)| v.as_slice())
+    }
+
+    fn string(&self, opcode: &str) -> Option<&str> {
+        self.operands(opcode).and_then(|v| v.first()).map(|s| s.as_str())
+    }
+
+    // each operand of a move opcode such as bm or am, as a move in this position
+    fn moves(&self, at: &AttackTable, opcode: &str) -> Result<Vec<Move>, EpdError> {
+        let operands = self.operands(opcode).unwrap_or_default();
+        operands.iter().map(|san| parse_san(&self.board, at, san).map_err(EpdError::Move)).collect()
+    }
+
+    pub fn best_moves(&self, at: &AttackTable) -> Result<Vec<Move>, EpdError> {
+        self.moves(at, "bm")
+    }
+
+    pub fn avoid_moves(&self, at: &AttackTable) -> Result<Vec<Move>, EpdError> {
+        self.moves(at, "am")
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.string("id")
+    }
+
+    // replaces the operands of an existing opcode or appends a new one
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|(o, _)| o == opcode) {
+            Some((_, v)) => *v = operands,
+            None => self.operations.push((opcode.to_string(), operands)),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&self, opcode: &str) -> Option<T> {
+        self.string(opcode).and_then(|s| s.parse().ok())
+    }
+
+    // analysis count depth
+    pub fn depth(&self) -> Option<u32> {
+        self.number("acd")
+    }
+
+    // centipawn evaluation from the side to move's point of view
+    pub fn eval(&self) -> Option<i32> {
+        self.number("ce")
+    }
+
+    pub fn half_move_clock(&self) -> Option<u8> {
+        self.number("hmvc")
+    }
+
+    pub fn full_move_number(&self) -> Option<u16> {
+        self.number("fmvn")
+    }
+
+    pub fn set_best_moves(&mut self, at: &AttackTable, moves: &[Move]) {
+        let sans = moves.iter().map(|&m| move_to_san(&self.board, at, m)).collect();
+        self.set("bm", sans);
+    }
+
+    pub fn set_avoid_moves(&mut self, at: &AttackTable, moves: &[Move]) {
+        let sans = moves.iter().map(|&m| move_to_san(&self.board, at, m)).collect();
+        self.set("am", sans);
+    }
+
+    pub fn set_pv(&mut self, at: &AttackTable, pv: &[Move]) {
+        let mut board = self.board;
+        let mut sans = Vec::new();
+        for &m in pv {
+            sans.push(move_to_san(&board, at, m));
+            board.make_move(m);
+        }
+        self.set("pv", sans);
+    }
+
+    pub fn set_id(&mut self, id: &str) {
+        self.set("id", vec![id.to_string()]);
+    }
+
+    pub fn set_depth(&mut self, depth: u32) {
+        self.set("acd", vec![depth.to_string()]);
+    }
+
+    pub fn set_eval(&mut self, eval: i32) {
+        self.set("ce", vec![eval.to_string()]);
+    }
+
+    pub fn as_epd(&self) -> String {
+        let mut epd = self.board.as_epd();
+        for (opcode, operands) in &self.operations {
+            epd.push(SPACE);
+            epd.push_str(opcode);
+            for operand in operands {
+                epd.push(SPACE);
+                if is_string_opcode(opcode) || operand.is_empty() || operand.contains([SPACE, ';', '"', '\\']) {
+                    epd.push_str(&format!("\"{}\"", operand.replace('\\', "\\\\").replace('"', "\\\"")));
+                } else {
+                    epd.push_str(operand);
+                }
+            }
+            epd.push(';');
+        }
+        epd
+    }
+}
+
+impl Board {
+    // the first four fields of the FEN
+    pub fn as_epd(&self) -> String {
+        self.as_fen().split(SPACE).take(4).collect::<Vec<&str>>().join(" ")
+    }
+}
+
+fn is_string_opcode(opcode: &str) -> bool {
+    opcode == "id" || (opcode.len() == 2 && opcode.starts_with('c') && opcode.ends_with(|c: char| c.is_ascii_digit()))
+}
+
+// opcode operand operand; with operands in double quotes allowed to hold
+// spaces and semicolons, and \" or \\ for a quote or backslash
+fn parse_operations(text: &str) -> Result<Vec<(String, Vec<String>)>, EpdError> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            None => break,
+            Some(';') => {
+                if words.is_empty() {
+                    return Err(EpdError::Syntax(String::from("empty operation")));
+                }
+                let opcode = words.remove(0);
+                operations.push((opcode, mem::take(&mut words)));
+            }
+            Some('"') => {
+                if words.is_empty() {
+                    return Err(EpdError::Syntax(String::from("quoted opcode")));
+                }
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // any other backslash is kept as it is
+                        Some('\\') => word.push(chars.next_if(|c| matches!(c, '"' | '\\')).unwrap_or('\\')),
+                        Some(c) => word.push(c),
+                        None => return Err(EpdError::Syntax(String::from("unterminated string"))),
+                    }
+                }
+                words.push(word);
+            }
+            Some(c) => {
+                let mut word = String::from(c);
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ';' && *c != '"') {
+                    word.push(c);
+                }
+                words.push(word);
+            }
+        }
+    }
+    // the last semicolon is often left out
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push((opcode, words));
+    }
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::with_table;
+
+    // white mates with Qxf7 and should keep the queen off h5
+    const SCHOLAR: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq -";
+
+    fn round_trip(epd: &Epd) -> Epd {
+        let again = Epd::parse(&epd.as_epd()).unwrap();
+        assert_eq!(again.as_epd(), epd.as_epd());
+        assert_eq!(again.operations, epd.operations);
+        assert_eq!(again.board, epd.board);
+        again
+    }
+
+    #[test]
+    fn moves_resolve_from_san() {
+        with_table(|at| {
+            let mut epd = Epd::parse(&format!("{SCHOLAR} bm Qxf7; am Qf3-h5 Nh3;")).unwrap();
+            let uci = |moves: Vec<Move>| moves.iter().map(|m| m.as_string()).collect::<Vec<String>>();
+            assert_eq!(uci(epd.best_moves(at).unwrap()), ["f3f7"]);
+            assert_eq!(uci(epd.avoid_moves(at).unwrap()), ["f3h5", "g1h3"]);
+
+            // written back in the engine's own SAN
+            let best = epd.best_moves(at).unwrap();
+            let avoid = epd.avoid_moves(at).unwrap();
+            epd.set_best_moves(at, &best);
+            epd.set_avoid_moves(at, &avoid);
+            assert_eq!(epd.as_epd(), format!("{SCHOLAR} bm Qxf7#; am Qh5 Nh3;"));
+            let again = round_trip(&epd);
+            assert_eq!(again.best_moves(at).unwrap(), best);
+
+            epd.set_pv(at, &best);
+            assert_eq!(round_trip(&epd).operands("pv").unwrap(), ["Qxf7#"]);
+            assert!(Epd::parse(&format!("{SCHOLAR} bm Qa8;")).unwrap().best_moves(at).is_err());
+        });
+    }
+
+    #[test]
+    fn strings_are_quoted() {
+        let mut epd = Epd::parse(&format!("{SCHOLAR} id \"WAC.001\"; c0 \"mate; in one\";")).unwrap();
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.operands("c0").unwrap(), ["mate; in one"]);
+
+        epd.set_id(r#"the "scholar's" mate \ 1"#);
+        epd.set("c0", vec![String::from("one")]);
+        epd.set_depth(12);
+        epd.set_eval(-35);
+        assert_eq!(
+            epd.as_epd(),
+            format!(r#"{SCHOLAR} id "the \"scholar's\" mate \\ 1"; c0 "one"; acd 12; ce -35;"#)
+        );
+        let again = round_trip(&epd);
+        assert_eq!(again.id(), Some(r#"the "scholar's" mate \ 1"#));
+        assert_eq!((again.depth(), again.eval()), (Some(12), Some(-35)));
+        // a lone backslash is not an escape
+        assert_eq!(Epd::parse(&format!(r#"{SCHOLAR} c1 "a\b";"#)).unwrap().operands("c1").unwrap(), [r"a\b"]);
+    }
+
+    #[test]
+    fn move_counters() {
+        let epd = Epd::parse(&format!("{SCHOLAR} hmvc 12; fmvn 40;")).unwrap();
+        assert_eq!((epd.board.half_move, epd.board.full_move), (12, 40));
+        assert_eq!((epd.half_move_clock(), epd.full_move_number()), (Some(12), Some(40)));
+        round_trip(&epd);
+
+        // counters after the fields count unless an opcode overrides them
+        let epd = Epd::parse(&format!("{SCHOLAR} 3 7 hmvc 9;")).unwrap();
+        assert_eq!((epd.board.half_move, epd.board.full_move), (9, 7));
+        let epd = Epd::parse(SCHOLAR).unwrap();
+        assert_eq!((epd.board.half_move, epd.board.full_move), (0, 1));
+
+        for bad in ["acd x;", "ce 1 2;", "hmvc 300;", "fmvn;"] {
+            assert!(matches!(Epd::parse(&format!("{SCHOLAR} {bad}")), Err(EpdError::Operand(..))), "{bad}");
+        }
+    }
+
+    #[test]
+    fn last_semicolon_is_optional() {
+        let with = Epd::parse(&format!("{SCHOLAR} id \"x\"; bm Qxf7")).unwrap();
+        let without = Epd::parse(&format!("{SCHOLAR} id \"x\"; bm Qxf7;")).unwrap();
+        assert_eq!(with.operations, without.operations);
+        assert_eq!(with.as_epd(), without.as_epd());
+        assert!(with.as_epd().ends_with("bm Qxf7;"));
+        assert!(matches!(Epd::parse(&format!("{SCHOLAR} bm Qxf7;;")), Err(EpdError::Syntax(_))));
+        assert!(matches!(Epd::parse(&format!("{SCHOLAR} id \"x;")), Err(EpdError::Syntax(_))));
+    }
+
+    #[test]
+    fn promoted_marker_needs_crazyhouse() {
+        let mut board = Board::new();
+        assert!(board.parse_fen(Some("Q~3k3/8/8/8/8/8/8/4K3 b - - 0 1")).is_err());
+        board.parse_fen(Some("Q~3k3/8/8/8/8/8/8/4K3[] b - - 0 1")).unwrap();
+        assert_eq!(board.promoted, 1 << 56);
+    }
+}
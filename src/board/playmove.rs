@@ -0,0 +1,275 @@
+use crate::{
+    board::{bitboard::{get_lsb, Bitboard}, zobrist::ZOBRIST, Board},
+    defs::{Castling, Color, Colors, NrOf, Piece, Pieces, Square, Variant, BB_SQUARES, EMPTY},
+    movegen::defs::Move,
+    moves::king_attacks,
+};
+
+const BACK_RANKS: u64 = 0xff00_0000_0000_00ff;
+
+// state that can't be recovered from the move itself when taking it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Undo {
+    pub castling: u8,
+    pub en_passant: Option<u8>,
+    pub half_move: u8,
+    pub key: u64,
+    pub promoted: Bitboard,
+    // atomic: the pieces a capture blew up, see Board::explode
+    pub exploded: u32,
+}
+
+impl Board {
+    #[inline(always)]
+    pub fn side_to_move(&self) -> Color {
+        if self.turn { Colors::WHITE as Color } else { Colors::BLACK as Color }
+    }
+
+    // plays a pseudo-legal move; legality is the caller's business
+    pub fn make_move(&mut self, m: Move) -> Undo {
+        let mut undo = Undo {
+            castling: self.castling,
+            en_passant: self.en_passant,
+            half_move: self.half_move,
+            key: self.key,
+            promoted: self.promoted,
+            exploded: 0,
+        };
+        let us = self.side_to_move();
+        let them = us ^ 1;
+        let (from, to, piece) = (m.from(), m.to(), m.piece());
+        let placed = if m.is_promotion() { m.promoted() } else { piece };
+        let crazyhouse = self.variant == Variant::Crazyhouse;
+        // depends on the pawns, so it comes out before anything moves
+        self.key ^= self.en_passant_key();
+
+        if m.is_capture() {
+            let square = captured_square(m, us);
+            self.remove_piece(m.captured(), square, them);
+            self.key ^= ZOBRIST.pieces[them][m.captured()][square];
+            // the capturer gets the piece in hand, as a pawn again if it was promoted
+            if crazyhouse {
+                let banked = if self.promoted & BB_SQUARES[square] != EMPTY { Pieces::PAWN as Piece } else { m.captured() };
+                self.promoted &= !BB_SQUARES[square];
+                self.pocket_add(us, banked);
+            }
+        }
+        if m.is_drop() {
+            self.pocket_take(us, piece);
+            self.add_piece(piece, to, us);
+            self.key ^= ZOBRIST.pieces[us][piece][to];
+        } else {
+            // in Chess960 the king and rook may land on each other's squares, so
+            // both leave the board before either is put back
+            let rook_move = m.is_castling().then(|| self.castling_rook(to));
+            self.remove_piece(piece, from, us);
+            if let Some((rook_from, _)) = rook_move {
+                self.remove_piece(Pieces::ROOK as Piece, rook_from, us);
+            }
+            self.add_piece(placed, to, us);
+            self.key ^= ZOBRIST.pieces[us][piece][from] ^ ZOBRIST.pieces[us][placed][to];
+            if let Some((rook_from, rook_to)) = rook_move {
+                self.add_piece(Pieces::ROOK as Piece, rook_to, us);
+                let rook = &ZOBRIST.pieces[us][Pieces::ROOK as Piece];
+                self.key ^= rook[rook_from] ^ rook[rook_to];
+            }
+            if crazyhouse {
+                if m.is_promotion() {
+                    self.promoted |= BB_SQUARES[to];
+                } else if self.promoted & BB_SQUARES[from] != EMPTY {
+                    self.promoted ^= BB_SQUARES[from] | BB_SQUARES[to];
+                }
+            }
+        }
+        if self.variant == Variant::Atomic && m.is_capture() {
+            undo.exploded = self.explode(placed, us, to);
+        }
+
+        self.en_passant = if m.is_double_step() { Some(((from + to) / 2) as u8) } else { None };
+        self.key ^= ZOBRIST.castling[self.castling as usize];
+        if self.castling != 0 {
+            self.castling &= self.castling_kept(piece, us, from, to);
+        }
+        self.key ^= ZOBRIST.castling[self.castling as usize] ^ ZOBRIST.side;
+        self.half_move = if piece == Pieces::PAWN as Piece || m.is_capture() { 0 } else { self.half_move.saturating_add(1) };
+        if us == Colors::BLACK as Color {
+            self.full_move = self.full_move.saturating_add(1);
+        }
+        self.turn = !self.turn;
+        self.key ^= self.en_passant_key();
+        undo
+    }
+
+    // the search copies boards instead; kept for the perft tests
+    #[allow(dead_code)]
+    pub fn unmake_move(&mut self, m: Move, undo: Undo) {
+        self.turn = !self.turn;
+        let us = self.side_to_move();
+        let them = us ^ 1;
+        let (from, to, piece) = (m.from(), m.to(), m.piece());
+
+        if self.variant == Variant::Atomic && m.is_capture() {
+            self.unexplode(if m.is_promotion() { m.promoted() } else { piece }, us, to, undo.exploded);
+        }
+        if m.is_drop() {
+            self.remove_piece(piece, to, us);
+            self.pockets[us][piece] += 1;
+        } else {
+            let rook_move = m.is_castling().then(|| self.castling_rook(to));
+            self.remove_piece(if m.is_promotion() { m.promoted() } else { piece }, to, us);
+            if let Some((_, rook_to)) = rook_move {
+                self.remove_piece(Pieces::ROOK as Piece, rook_to, us);
+            }
+            self.add_piece(piece, from, us);
+            if let Some((rook_from, _)) = rook_move {
+                self.add_piece(Pieces::ROOK as Piece, rook_from, us);
+            }
+        }
+        if m.is_capture() {
+            let square = captured_square(m, us);
+            self.add_piece(m.captured(), square, them);
+            if self.variant == Variant::Crazyhouse {
+                let banked = if undo.promoted & BB_SQUARES[square] != EMPTY { Pieces::PAWN as Piece } else { m.captured() };
+                self.pockets[us][banked] -= 1;
+            }
+        }
+        self.promoted = undo.promoted;
+
+        if us == Colors::BLACK as Color {
+            self.full_move = self.full_move.saturating_sub(1);
+        }
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.half_move = undo.half_move;
+        self.key = undo.key;
+    }
+
+    // passes the turn; only used by the search for null move pruning
+    pub fn make_null_move(&mut self) -> Undo {
+        let undo = Undo {
+            castling: self.castling,
+            en_passant: self.en_passant,
+            half_move: self.half_move,
+            key: self.key,
+            promoted: self.promoted,
+            exploded: 0,
+        };
+        self.key ^= self.en_passant_key() ^ ZOBRIST.side;
+        self.en_passant = None;
+        self.half_move = self.half_move.saturating_add(1);
+        self.turn = !self.turn;
+        undo
+    }
+}
+
+impl Board {
+    // pocket keys are indexed by how many of the piece were already in hand
+    #[inline(always)]
+    fn pocket_add(&mut self, color: Color, piece: Piece) {
+        let n = self.pockets[color][piece] as usize;
+        self.key ^= ZOBRIST.pockets[color][piece][n % NrOf::POCKET];
+        self.pockets[color][piece] += 1;
+    }
+
+    #[inline(always)]
+    fn pocket_take(&mut self, color: Color, piece: Piece) {
+        self.pockets[color][piece] -= 1;
+        let n = self.pockets[color][piece] as usize;
+        self.key ^= ZOBRIST.pockets[color][piece][n % NrOf::POCKET];
+    }
+}
+
+impl Board {
+    // atomic: a capture blows up the capturing piece and every piece but the
+    // pawns around the square, castling rights going with the kings and rooks.
+    // returns what was blown up, four bits per square of the blast in square
+    // order: 0 for nothing, else the piece plus one and the colour in the high bit
+    fn explode(&mut self, piece: Piece, color: Color, square: Square) -> u32 {
+        self.remove_piece(piece, square, color);
+        self.key ^= ZOBRIST.pieces[color][piece][square];
+
+        let mut exploded = 0;
+        let mut kept = Castling::ALL;
+        let mut blast = king_attacks(square);
+        let mut shift = 0;
+        while blast != EMPTY {
+            let sq = get_lsb(blast);
+            blast &= blast - 1;
+            let victim = self.piece_on(sq);
+            if victim != Pieces::NONE as Piece && victim != Pieces::PAWN as Piece {
+                let owner = if self.color[Colors::WHITE as Color] & BB_SQUARES[sq] != EMPTY { Colors::WHITE } else { Colors::BLACK } as Color;
+                self.remove_piece(victim, sq, owner);
+                self.key ^= ZOBRIST.pieces[owner][victim][sq];
+                kept &= self.castling_kept(victim, owner, sq, sq);
+                exploded |= ((victim as u32 + 1) | (owner as u32) << 3) << shift;
+            }
+            shift += 4;
+        }
+        if self.castling & !kept != 0 {
+            self.key ^= ZOBRIST.castling[self.castling as usize];
+            self.castling &= kept;
+            self.key ^= ZOBRIST.castling[self.castling as usize];
+        }
+        exploded
+    }
+
+    // puts back what explode blew up; the key is restored from the Undo
+    #[allow(dead_code)]
+    fn unexplode(&mut self, piece: Piece, color: Color, square: Square, mut exploded: u32) {
+        self.add_piece(piece, square, color);
+        let mut blast = king_attacks(square);
+        while blast != EMPTY {
+            let sq = get_lsb(blast);
+            blast &= blast - 1;
+            let code = exploded & 0xf;
+            exploded >>= 4;
+            if code != 0 {
+                self.add_piece((code & 0x7) as Piece - 1, sq, (code >> 3) as Color);
+            }
+        }
+    }
+}
+
+// the pawn taken en passant is not on the destination square
+#[inline(always)]
+pub fn captured_square(m: Move, us: Color) -> Square {
+    if !m.is_en_passant() {
+        m.to()
+    } else if us == Colors::WHITE as Color {
+        m.to() - 8
+    } else {
+        m.to() + 8
+    }
+}
+
+impl Board {
+    // rook source and destination for a castling king landing on `king_to`,
+    // which is on the g or c file whatever the variant
+    #[inline(always)]
+    pub fn castling_rook(&self, king_to: Square) -> (Square, Square) {
+        let kingside = king_to % 8 == 6;
+        let index = 2 * (king_to / 8 != 0) as usize + !kingside as usize;
+        let back_rank = king_to - king_to % 8;
+        (self.castling_rooks[index] as Square, back_rank + if kingside { 5 } else { 3 })
+    }
+
+    // castling rights that survive a move: a king move gives up both of its
+    // side's, and a move from or to a castling rook's square the rook's own
+    #[inline(always)]
+    fn castling_kept(&self, piece: Piece, us: Color, from: Square, to: Square) -> u8 {
+        let mut kept = Castling::ALL;
+        // castling kings and rooks all stand on the first or last rank
+        if (BB_SQUARES[from] | BB_SQUARES[to]) & BACK_RANKS == EMPTY {
+            return kept;
+        }
+        if piece == Pieces::KING as Piece {
+            kept &= if us == Colors::WHITE as Color { !(Castling::WK | Castling::WQ) } else { !(Castling::BK | Castling::BQ) };
+        }
+        for (i, &rook) in self.castling_rooks.iter().enumerate() {
+            if rook as Square == from || rook as Square == to {
+                kept &= !(1 << i);
+            }
+        }
+        kept
+    }
+}
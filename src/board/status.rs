@@ -0,0 +1,248 @@
+use crate::{
+    board::Board,
+    defs::{Color, Colors, Pieces, Variant, EMPTY},
+    movegen::{in_check, legal_moves},
+    moves::{AttackTable, DARK_SQUARES},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawReason {
+    Stalemate,
+    // the current position has occurred three times; claimable
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    // fifty moves by each side without a capture or pawn move; claimable
+    FiftyMoves,
+    SeventyFiveMoves,
+    // neither side can mate by any series of legal moves
+    InsufficientMaterial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    // the game goes on unless the side to move claims the draw
+    Claimable(DrawReason),
+    Checkmate { winner: Color },
+    // atomic chess: the loser's king was blown up
+    Exploded { winner: Color },
+    // automatic draws end the game without a claim
+    Draw(DrawReason),
+}
+
+impl DrawReason {
+    pub fn as_string(&self) -> &'static str {
+        match self {
+            DrawReason::Stalemate => "Stalemate",
+            DrawReason::ThreefoldRepetition => "Draw by threefold repetition",
+            DrawReason::FivefoldRepetition => "Draw by fivefold repetition",
+            DrawReason::FiftyMoves => "Draw by fifty move rule",
+            DrawReason::SeventyFiveMoves => "Draw by seventy-five move rule",
+            DrawReason::InsufficientMaterial => "Draw by insufficient material",
+        }
+    }
+}
+
+impl GameStatus {
+    // from white's point of view, claimable draws counted as claimed
+    pub fn result(&self) -> Option<f64> {
+        match self {
+            GameStatus::Ongoing => None,
+            GameStatus::Checkmate { winner } | GameStatus::Exploded { winner } => {
+                Some(if *winner == Colors::WHITE as Color { 1.0 } else { 0.0 })
+            }
+            GameStatus::Claimable(_) | GameStatus::Draw(_) => Some(0.5),
+        }
+    }
+
+    pub fn as_string(&self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "Ongoing",
+            GameStatus::Checkmate { winner } if *winner == Colors::WHITE as Color => "White mates",
+            GameStatus::Checkmate { .. } => "Black mates",
+            GameStatus::Exploded { winner } if *winner == Colors::WHITE as Color => "White explodes the black king",
+            GameStatus::Exploded { .. } => "Black explodes the white king",
+            GameStatus::Claimable(reason) | GameStatus::Draw(reason) => reason.as_string(),
+        }
+    }
+}
+
+impl Board {
+    // `history` holds the keys of the positions before this one, oldest first,
+    // as kept by the search and the match runners
+    pub fn status(&self, at: &AttackTable, history: &[u64]) -> GameStatus {
+        if self.king_exploded() {
+            return GameStatus::Exploded { winner: self.side_to_move() ^ 1 };
+        }
+        if legal_moves(self, at).is_empty() {
+            return if in_check(self, at) {
+                GameStatus::Checkmate { winner: self.side_to_move() ^ 1 }
+            } else {
+                GameStatus::Draw(DrawReason::Stalemate)
+            };
+        }
+
+        let repetitions = self.repetitions(history);
+        if repetitions >= 4 {
+            return GameStatus::Draw(DrawReason::FivefoldRepetition);
+        }
+        if self.half_move >= 150 {
+            return GameStatus::Draw(DrawReason::SeventyFiveMoves);
+        }
+        if self.insufficient_material() {
+            return GameStatus::Draw(DrawReason::InsufficientMaterial);
+        }
+        if repetitions >= 2 {
+            return GameStatus::Claimable(DrawReason::ThreefoldRepetition);
+        }
+        if self.half_move >= 100 {
+            return GameStatus::Claimable(DrawReason::FiftyMoves);
+        }
+        GameStatus::Ongoing
+    }
+
+    // atomic chess: the side to move has lost its king, and with it the game
+    pub fn king_exploded(&self) -> bool {
+        self.variant == Variant::Atomic && self.get_pieces(Pieces::KING as usize, self.side_to_move()) == EMPTY
+    }
+
+    // earlier occurrences of this position; only positions since the last
+    // capture or pawn move can match
+    pub fn repetitions(&self, history: &[u64]) -> usize {
+        history.iter().rev().take(self.half_move as usize).filter(|&&k| k == self.key).count()
+    }
+
+    // bare kings, a single minor piece, or only bishops that all stand on
+    // squares of one colour. never in crazyhouse, where captures refill the pockets
+    pub fn insufficient_material(&self) -> bool {
+        if self.variant == Variant::Crazyhouse {
+            return false;
+        }
+        let others = self.pieces[Pieces::QUEEN as usize] | self.pieces[Pieces::ROOK as usize] | self.pieces[Pieces::PAWN as usize];
+        if others != EMPTY {
+            return false;
+        }
+        let knights = self.pieces[Pieces::KNIGHT as usize];
+        let bishops = self.pieces[Pieces::BISHOP as usize];
+        if (knights | bishops).count_ones() <= 1 {
+            return true;
+        }
+        knights == EMPTY && (bishops & DARK_SQUARES == EMPTY || bishops & !DARK_SQUARES == EMPTY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{movegen::parse_move, moves::with_table};
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        board
+    }
+
+    // plays the moves, keeping the key history the way the match runners do
+    fn play(at: &AttackTable, board: &mut Board, history: &mut Vec<u64>, moves: &str) {
+        for s in moves.split_whitespace() {
+            let m = parse_move(board, at, s).unwrap_or_else(|| panic!("illegal move {s}"));
+            history.push(board.key);
+            board.make_move(m);
+        }
+    }
+
+    #[test]
+    fn mate_and_stalemate() {
+        with_table(|at| {
+            let mate = board("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").status(at, &[]);
+            assert_eq!(mate, GameStatus::Checkmate { winner: Colors::BLACK as Color });
+            assert_eq!(mate.result(), Some(0.0));
+            let stalemate = board("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").status(at, &[]);
+            assert_eq!(stalemate, GameStatus::Draw(DrawReason::Stalemate));
+            assert_eq!(stalemate.result(), Some(0.5));
+            assert_eq!(board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").status(at, &[]), GameStatus::Ongoing);
+        });
+    }
+
+    #[test]
+    fn repetitions() {
+        with_table(|at| {
+            let mut board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+            let mut history = Vec::new();
+            let shuffle = "g1f3 g8f6 f3g1 f6g8";
+            play(at, &mut board, &mut history, shuffle);
+            assert_eq!(board.repetitions(&history), 1);
+            assert_eq!(board.status(at, &history), GameStatus::Ongoing);
+            play(at, &mut board, &mut history, shuffle);
+            assert_eq!(board.status(at, &history), GameStatus::Claimable(DrawReason::ThreefoldRepetition));
+            play(at, &mut board, &mut history, shuffle);
+            assert_eq!(board.status(at, &history), GameStatus::Claimable(DrawReason::ThreefoldRepetition));
+            play(at, &mut board, &mut history, shuffle);
+            assert_eq!(board.repetitions(&history), 4);
+            assert_eq!(board.status(at, &history), GameStatus::Draw(DrawReason::FivefoldRepetition));
+        });
+    }
+
+    #[test]
+    fn unusable_en_passant_square_does_not_split_repetitions() {
+        with_table(|at| {
+            // after e4 nothing can take en passant, so the position already
+            // counts as the first of the repetition
+            let mut game = board("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+            let mut history = Vec::new();
+            play(at, &mut game, &mut history, "e2e4 e8d8 e1d1 d8e8 d1e1 e8d8 e1d1 d8e8 d1e1");
+            assert_eq!(game.status(at, &history), GameStatus::Claimable(DrawReason::ThreefoldRepetition));
+
+            // a pawn that could take keeps the en passant square in the key
+            let with_ep = board("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1");
+            let without = board("4k3/8/8/8/3pP3/8/8/4K3 b - - 0 1");
+            assert_ne!(with_ep.key, without.key);
+            let blocked = board("4k3/8/8/8/p3P3/8/8/4K3 b - e3 0 1");
+            assert_eq!(blocked.key, board("4k3/8/8/8/p3P3/8/8/4K3 b - - 0 1").key);
+        });
+    }
+
+    #[test]
+    fn move_rules() {
+        with_table(|at| {
+            let fen = |half_move: u32| format!("6k1/5ppp/8/8/8/8/8/R5K1 w - - {half_move} 80");
+            assert_eq!(board(&fen(99)).status(at, &[]), GameStatus::Ongoing);
+            assert_eq!(board(&fen(100)).status(at, &[]), GameStatus::Claimable(DrawReason::FiftyMoves));
+            assert_eq!(board(&fen(149)).status(at, &[]), GameStatus::Claimable(DrawReason::FiftyMoves));
+            assert_eq!(board(&fen(150)).status(at, &[]), GameStatus::Draw(DrawReason::SeventyFiveMoves));
+
+            // mate delivered on the hundredth half-move stands
+            let mut mate = board(&fen(99));
+            play(at, &mut mate, &mut Vec::new(), "a1a8");
+            assert_eq!(mate.half_move, 100);
+            assert_eq!(mate.status(at, &[]), GameStatus::Checkmate { winner: Colors::WHITE as Color });
+        });
+    }
+
+    #[test]
+    fn insufficient_material() {
+        with_table(|at| {
+            let draw = GameStatus::Draw(DrawReason::InsufficientMaterial);
+            assert_eq!(board("8/8/8/4k3/8/8/8/4K3 w - - 0 1").status(at, &[]), draw);
+            assert_eq!(board("8/8/8/4k3/8/8/8/1N2K3 w - - 0 1").status(at, &[]), draw);
+            // bishops on c1 and f8 are both on dark squares
+            assert_eq!(board("5b2/8/8/4k3/8/8/8/2B1K3 w - - 0 1").status(at, &[]), draw);
+            assert_eq!(board("2b5/8/8/4k3/8/8/8/2B1K3 w - - 0 1").status(at, &[]), GameStatus::Ongoing);
+            assert_eq!(board("8/8/8/4k3/8/8/8/1NN1K3 w - - 0 1").status(at, &[]), GameStatus::Ongoing);
+            // crazyhouse captures put pieces back in hand
+            assert_eq!(board("8/8/8/4k3/8/8/8/4K3[] w - - 0 1").status(at, &[]), GameStatus::Ongoing);
+        });
+    }
+
+    #[test]
+    fn atomic_explosion() {
+        with_table(|at| {
+            let mut board = board("3r3k/6p1/8/8/8/8/8/3K2R1 w - - 0 1");
+            board.variant = Variant::Atomic;
+            play(at, &mut board, &mut Vec::new(), "g1g7");
+            let status = board.status(at, &[]);
+            assert_eq!(status, GameStatus::Exploded { winner: Colors::WHITE as Color });
+            assert_eq!(status.result(), Some(1.0));
+        });
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{
+    board::{bitboard::get_lsb, Board},
+    defs::{NrOf, Pieces, EMPTY},
+    moves::pawn_attacks,
+};
+
+// random keys for hashing positions, generated at compile time
+pub struct Zobrist {
+    pub pieces: [[[u64; NrOf::SQUARES]; NrOf::PIECE_TYPES]; NrOf::COLORS],
+    pub castling: [u64; NrOf::CASTLING_PERMISSIONS],
+    pub en_passant: [u64; NrOf::FILES],
+    pub side: u64,
+    // xored in for every piece in a pocket: [color][piece][how many were there before]
+    pub pockets: [[[u64; NrOf::POCKET]; NrOf::PIECE_TYPES]; NrOf::COLORS],
+}
+
+const fn next_key(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545f4914f6cdd1d)
+}
+
+const fn init_zobrist() -> Zobrist {
+    let mut state: u64 = 0x3243f6a8885a308d;
+    let mut z = Zobrist {
+        pieces: [[[0; NrOf::SQUARES]; NrOf::PIECE_TYPES]; NrOf::COLORS],
+        castling: [0; NrOf::CASTLING_PERMISSIONS],
+        en_passant: [0; NrOf::FILES],
+        side: 0,
+        pockets: [[[0; NrOf::POCKET]; NrOf::PIECE_TYPES]; NrOf::COLORS],
+    };
+    let mut color = 0;
+    while color < NrOf::COLORS {
+        let mut piece = 0;
+        while piece < NrOf::PIECE_TYPES {
+            let mut sq = 0;
+            while sq < NrOf::SQUARES {
+                z.pieces[color][piece][sq] = next_key(&mut state);
+                sq += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+    let mut i = 0;
+    while i < NrOf::CASTLING_PERMISSIONS {
+        z.castling[i] = next_key(&mut state);
+        i += 1;
+    }
+    let mut i = 0;
+    while i < NrOf::FILES {
+        z.en_passant[i] = next_key(&mut state);
+        i += 1;
+    }
+    z.side = next_key(&mut state);
+    let mut color = 0;
+    while color < NrOf::COLORS {
+        let mut piece = 0;
+        while piece < NrOf::PIECE_TYPES {
+            let mut n = 0;
+            while n < NrOf::POCKET {
+                z.pockets[color][piece][n] = next_key(&mut state);
+                n += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+    z
+}
+
+pub const ZOBRIST: Zobrist = init_zobrist();
+
+impl Board {
+    // hash of the position from scratch; make_move keeps `key` up to date incrementally
+    pub fn compute_key(&self) -> u64 {
+        let mut key = 0;
+        for color in 0..NrOf::COLORS {
+            for piece in 0..NrOf::PIECE_TYPES {
+                let mut bb = self.get_pieces(piece, color);
+                while bb != EMPTY {
+                    key ^= ZOBRIST.pieces[color][piece][get_lsb(bb)];
+                    bb &= bb - 1;
+                }
+            }
+        }
+        key ^= ZOBRIST.castling[self.castling as usize];
+        key ^= self.en_passant_key();
+        if !self.turn {
+            key ^= ZOBRIST.side;
+        }
+        for color in 0..NrOf::COLORS {
+            for piece in 0..NrOf::PIECE_TYPES {
+                for n in 0..self.pockets[color][piece] as usize {
+                    key ^= ZOBRIST.pockets[color][piece][n % NrOf::POCKET];
+                }
+            }
+        }
+        key
+    }
+
+    // the en passant file only counts when a pawn of the side to move stands
+    // ready to take, so a double step nobody can answer doesn't split repetitions
+    pub fn en_passant_key(&self) -> u64 {
+        match self.en_passant {
+            Some(ep) => {
+                let us = self.side_to_move();
+                let takers = pawn_attacks(us ^ 1, ep as usize) & self.get_pieces(Pieces::PAWN as usize, us);
+                if takers != EMPTY { ZOBRIST.en_passant[ep as usize % 8] } else { 0 }
+            }
+            None => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        defs::Variant,
+        movegen::legal_moves,
+        moves::{with_table, AttackTable},
+    };
+
+    fn check_keys(board: &Board, at: &AttackTable, depth: u8) {
+        assert_eq!(board.key, board.compute_key(), "{}", board.as_fen());
+        if depth == 0 {
+            return;
+        }
+        let mut null = *board;
+        null.make_null_move();
+        assert_eq!(null.key, null.compute_key(), "null move in {}", board.as_fen());
+        for &m in legal_moves(board, at).iter() {
+            let mut next = *board;
+            next.make_move(m);
+            check_keys(&next, at, depth - 1);
+        }
+    }
+
+    #[test]
+    fn incremental_key_matches_compute_key() {
+        with_table(|at| {
+            for (fen, variant) in [
+                ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", None),
+                ("rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3", None),
+                ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", None),
+                ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", None),
+                ("r1bqk2r/pppp1ppp/2n2n2/4p3/1bB1P3/2N2N2/PPPP1PPP/R1BQK2R[Pp] w KQkq - 0 5", None),
+                ("rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1", Some(Variant::Atomic)),
+            ] {
+                let mut board = Board::new();
+                board.parse_fen(Some(fen)).unwrap();
+                if let Some(variant) = variant {
+                    board.variant = variant;
+                }
+                check_keys(&board, at, 3);
+            }
+        });
+    }
+}
@@ -0,0 +1,264 @@
+pub mod builder;
+pub mod keys;
+
+use std::fmt::{self, Display};
+use std::cmp::Reverse;
+use std::fs;
+
+use self::keys::RANDOM;
+use crate::{
+    board::Board,
+    defs::{Castling, Colors, Piece, Pieces, Square},
+    movegen::{defs::Move, legal_moves},
+    moves::AttackTable,
+    rng::Rng,
+};
+
+const ENTRY_SIZE: usize = 16;
+const CASTLING_KEYS: usize = 768;
+const EN_PASSANT_KEYS: usize = 772;
+const TURN_KEY: usize = 780;
+
+#[derive(Debug)]
+pub enum BookError {
+    Io(String),
+    BadSize(String),
+    BadOption(String),
+    BadFen(String),
+}
+
+impl Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error reading book: {e}"),
+            Self::BadSize(path) => write!(f, "Error in book {path}: size is not a multiple of {ENTRY_SIZE} bytes"),
+            Self::BadOption(o) => write!(f, "Error in book options: {o}"),
+            Self::BadFen(e) => write!(f, "Error in book position: {e}"),
+        }
+    }
+}
+
+// one 16 byte record of a .bin file, all fields big endian
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BookEntry {
+    pub key: u64,
+    pub mv: u16,
+    pub weight: u16,
+    pub learn: u32,
+}
+
+impl BookEntry {
+    pub fn from_bytes(bytes: &[u8]) -> BookEntry {
+        BookEntry {
+            key: u64::from_be_bytes(bytes[0..8].try_into().expect("8 bytes")),
+            mv: u16::from_be_bytes(bytes[8..10].try_into().expect("2 bytes")),
+            weight: u16::from_be_bytes(bytes[10..12].try_into().expect("2 bytes")),
+            learn: u32::from_be_bytes(bytes[12..16].try_into().expect("4 bytes")),
+        }
+    }
+
+    pub fn as_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+        bytes[0..8].copy_from_slice(&self.key.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.mv.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.weight.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.learn.to_be_bytes());
+        bytes
+    }
+}
+
+// a Polyglot opening book, entries sorted by key as the format requires
+pub struct Book {
+    pub entries: Vec<BookEntry>,
+}
+
+impl Book {
+    // sorted by key, then heaviest move first as other Polyglot tools write them
+    pub fn new(mut entries: Vec<BookEntry>) -> Book {
+        entries.sort_by_key(|e| (e.key, Reverse(e.weight), e.mv));
+        Book { entries }
+    }
+
+    pub fn load(path: &str) -> Result<Book, BookError> {
+        let bytes = fs::read(path).map_err(|e| BookError::Io(format!("{path}: {e}")))?;
+        if !bytes.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(BookError::BadSize(path.to_string()));
+        }
+        let mut entries: Vec<BookEntry> = bytes.chunks_exact(ENTRY_SIZE).map(BookEntry::from_bytes).collect();
+        // books written by other tools are sorted already, this only guards the search
+        if !entries.is_sorted_by_key(|e| e.key) {
+            entries.sort_by_key(|e| e.key);
+        }
+        Ok(Book { entries })
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), BookError> {
+        let bytes: Vec<u8> = self.entries.iter().flat_map(BookEntry::as_bytes).collect();
+        fs::write(path, bytes).map_err(|e| BookError::Io(format!("{path}: {e}")))
+    }
+
+    // every entry for a position, found by binary search
+    pub fn entries(&self, key: u64) -> &[BookEntry] {
+        let start = self.entries.partition_point(|e| e.key < key);
+        let end = start + self.entries[start..].partition_point(|e| e.key == key);
+        &self.entries[start..end]
+    }
+
+    // the book moves of a position that are legal in it, with their weights
+    pub fn moves(&self, board: &Board, at: &AttackTable) -> Vec<(Move, u16)> {
+        self.entries(polyglot_key(board))
+            .iter()
+            .filter_map(|e| decode_move(board, at, e.mv).map(|m| (m, e.weight)))
+            .collect()
+    }
+
+    // the heaviest move, or one picked with probability proportional to its weight
+    pub fn probe(&self, board: &Board, at: &AttackTable, best: bool, rng: &mut Rng) -> Option<Move> {
+        let moves = self.moves(board, at);
+        if best {
+            // the first of equal weights, as books list them in the order they prefer
+            let top = moves.iter().map(|&(_, w)| w).max()?;
+            return moves.iter().find(|&&(_, w)| w == top).map(|&(m, _)| m);
+        }
+        let total: u64 = moves.iter().map(|&(_, w)| w as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.next_u64() % total;
+        for (m, w) in moves {
+            if pick < w as u64 {
+                return Some(m);
+            }
+            pick -= w as u64;
+        }
+        None
+    }
+}
+
+// Polyglot numbers pieces black pawn, white pawn, black knight, ... white king
+fn piece_kind(piece: Piece, white: bool) -> usize {
+    let order = match piece {
+        p if p == Pieces::PAWN as Piece => 0,
+        p if p == Pieces::KNIGHT as Piece => 1,
+        p if p == Pieces::BISHOP as Piece => 2,
+        p if p == Pieces::ROOK as Piece => 3,
+        p if p == Pieces::QUEEN as Piece => 4,
+        _ => 5,
+    };
+    2 * order + white as usize
+}
+
+// the standard Polyglot hash, independent of the engine's own zobrist keys.
+// the en passant file only counts when a pawn can actually take there
+pub fn polyglot_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for square in 0..64 {
+        let piece = board.piece_on(square);
+        if piece != Pieces::NONE as Piece {
+            let white = board.color[Colors::WHITE as usize] & (1 << square) != 0;
+            key ^= RANDOM[64 * piece_kind(piece, white) + square];
+        }
+    }
+    for (i, right) in [Castling::WK, Castling::WQ, Castling::BK, Castling::BQ].into_iter().enumerate() {
+        if board.castling & right != 0 {
+            key ^= RANDOM[CASTLING_KEYS + i];
+        }
+    }
+    if let Some(ep) = board.en_passant {
+        let ep = ep as usize;
+        let file = ep % 8;
+        // the pawns that could capture stand beside the pawn that just moved
+        let (us, rank) = if board.turn { (Colors::WHITE as usize, 4) } else { (Colors::BLACK as usize, 3) };
+        let pawns = board.get_pieces(Pieces::PAWN as usize, us);
+        let beside = [file.checked_sub(1), (file < 7).then_some(file + 1)];
+        if beside.iter().flatten().any(|&f| pawns & (1 << (rank * 8 + f)) != 0) {
+            key ^= RANDOM[EN_PASSANT_KEYS + file];
+        }
+    }
+    if board.turn {
+        key ^= RANDOM[TURN_KEY];
+    }
+    key
+}
+
+// the destination as Polyglot has it
+fn target(board: &Board, m: Move) -> Square {
+    match m.is_castling() {
+        true => board.castling_rook(m.to()).0,
+        false => m.to(),
+    }
+}
+
+// Polyglot promotion pieces, 0 for none
+const PROMOTIONS: [Pieces; 4] = [Pieces::KNIGHT, Pieces::BISHOP, Pieces::ROOK, Pieces::QUEEN];
+
+// castling is stored as the king taking its own rook, e1h1 for O-O
+pub fn decode_move(board: &Board, at: &AttackTable, raw: u16) -> Option<Move> {
+    let to = (raw & 0x3f) as Square;
+    let from = ((raw >> 6) & 0x3f) as Square;
+    let promotion = match (raw >> 12) & 7 {
+        0 => None,
+        p => Some(*PROMOTIONS.get(p as usize - 1)? as Piece),
+    };
+    legal_moves(board, at).iter().copied().find(|m| {
+        m.from() == from && target(board, *m) == to && (m.is_promotion().then(|| m.promoted()) == promotion)
+    })
+}
+
+pub fn encode_move(board: &Board, m: Move) -> u16 {
+    let to = target(board, m);
+    let promotion = match m.is_promotion() {
+        true => PROMOTIONS.iter().position(|&p| p as Piece == m.promoted()).map_or(0, |i| i + 1),
+        false => 0,
+    };
+    (to | (m.from() << 6) | (promotion << 12)) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{movegen::parse_move, moves::with_table};
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        board
+    }
+
+    // the test positions from the Polyglot book format description
+    #[test]
+    fn polyglot_keys() {
+        let vectors = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 0x463b96181691fc9c),
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", 0x823c9b50fd114196),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2", 0x0756b94461c50fb0),
+            ("rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2", 0x662fafb965db29d4),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3", 0x22a48b5a8e47ff78),
+            ("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3", 0x652a607ca3f242c1),
+            ("rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4", 0x00fdd303c946bdd9),
+            ("rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3", 0x3c8123ea7b067637),
+            ("rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4", 0x5c3f9b829b279560),
+        ];
+        for (fen, key) in vectors {
+            assert_eq!(polyglot_key(&board(fen)), key, "{fen}");
+        }
+    }
+
+    #[test]
+    fn castling_is_the_king_taking_its_rook() {
+        with_table(|at| {
+            let castling = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+            let short = parse_move(&castling, at, "e1g1").unwrap();
+            let raw = encode_move(&castling, short);
+            assert_eq!(raw, 4 << 6 | 7);
+            assert_eq!(decode_move(&castling, at, raw), Some(short));
+            let long = parse_move(&castling, at, "e1c1").unwrap();
+            assert_eq!(decode_move(&castling, at, encode_move(&castling, long)), Some(long));
+
+            let promotion = board("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+            let knight = parse_move(&promotion, at, "e7e8n").unwrap();
+            assert_eq!(encode_move(&promotion, knight) >> 12, 1);
+            assert_eq!(decode_move(&promotion, at, encode_move(&promotion, knight)), Some(knight));
+        });
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    board::Board,
+    book::{decode_move, encode_move, polyglot_key, Book, BookEntry, BookError},
+    movegen::san::move_to_san,
+    moves::AttackTable,
+    pgn::{reader::PgnReader, Game},
+};
+
+// weight of a move for the side that played it
+const WIN_WEIGHT: u32 = 2;
+const DRAW_WEIGHT: u32 = 1;
+
+pub struct MakeBookOptions {
+    pub pgns: Vec<String>,
+    pub out: String,
+    // a move needs to have been played in this many games
+    pub min_games: u32,
+    // moves after this many plies are left out
+    pub max_ply: usize,
+    // both WhiteElo and BlackElo must be at least this, games without them are skipped
+    pub min_elo: Option<u32>,
+    pub results: Vec<String>,
+}
+
+impl Default for MakeBookOptions {
+    fn default() -> MakeBookOptions {
+        MakeBookOptions {
+            pgns: Vec::new(),
+            out: String::from("book.bin"),
+            min_games: 1,
+            max_ply: 30,
+            min_elo: None,
+            results: vec![String::from("1-0"), String::from("0-1"), String::from("1/2-1/2")],
+        }
+    }
+}
+
+impl MakeBookOptions {
+    // rustChess makebook <file.pgn>... [--out file] [--min-games n] [--max-ply n] [--min-elo n] [--results 1-0,0-1,1/2-1/2]
+    pub fn parse(args: &[String]) -> Result<MakeBookOptions, BookError> {
+        let mut options = MakeBookOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                options.pgns.push(arg.clone());
+                continue;
+            }
+            let value = iter.next().ok_or_else(|| BookError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || BookError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--out" => options.out = value.clone(),
+                "--min-games" => options.min_games = value.parse().map_err(|_| bad())?,
+                "--max-ply" => options.max_ply = value.parse().map_err(|_| bad())?,
+                "--min-elo" => options.min_elo = Some(value.parse().map_err(|_| bad())?),
+                "--results" => {
+                    options.results = value.split(',').map(String::from).collect();
+                    if options.results.iter().any(|r| !["1-0", "0-1", "1/2-1/2", "*"].contains(&r.as_str())) {
+                        return Err(bad());
+                    }
+                }
+                _ => return Err(BookError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.pgns.is_empty() {
+            return Err(BookError::BadOption(String::from("no PGN files given")));
+        }
+        if options.min_games == 0 || options.max_ply == 0 {
+            return Err(BookError::BadOption(String::from("min-games and max-ply must be positive")));
+        }
+        Ok(options)
+    }
+
+    fn accepts(&self, game: &Game) -> bool {
+        if !self.results.contains(&game.result) {
+            return false;
+        }
+        let Some(min_elo) = self.min_elo else { return true };
+        let elo = |tag| game.tag(tag).and_then(|e| e.trim().parse::<u32>().ok());
+        matches!((elo("WhiteElo"), elo("BlackElo")), (Some(w), Some(b)) if w >= min_elo && b >= min_elo)
+    }
+}
+
+#[derive(Default)]
+struct MoveStats {
+    games: u32,
+    weight: u32,
+}
+
+// builds a book from the main lines of the games that pass the filters. a
+// move is weighted 2 for each game its side won and 1 for each draw; moves
+// that only lost get no entry
+pub fn makebook(at: &AttackTable, options: &MakeBookOptions) -> Result<(), BookError> {
+    let mut stats: HashMap<(u64, u16), MoveStats> = HashMap::new();
+    let (mut read, mut used, mut errors) = (0, 0, 0);
+
+    for path in &options.pgns {
+        let reader = PgnReader::open(path, at).map_err(|e| BookError::Io(format!("{path}: {e}")))?;
+        for game in reader {
+            read += 1;
+            let game = match game {
+                Ok(game) => game,
+                Err(e) => {
+                    println!("{path}: {e}");
+                    errors += 1;
+                    continue;
+                }
+            };
+            if !options.accepts(&game) {
+                continue;
+            }
+            used += 1;
+            let (white_weight, black_weight) = match game.result.as_str() {
+                "1-0" => (WIN_WEIGHT, 0),
+                "0-1" => (0, WIN_WEIGHT),
+                "1/2-1/2" => (DRAW_WEIGHT, DRAW_WEIGHT),
+                _ => (0, 0),
+            };
+            for (board, mv) in game.mainline().into_iter().take(options.max_ply) {
+                let entry = stats.entry((polyglot_key(&board), encode_move(&board, mv))).or_default();
+                entry.games += 1;
+                entry.weight += if board.turn { white_weight } else { black_weight };
+            }
+            if used % 10000 == 0 {
+                println!("{used} games, {} moves", stats.len());
+            }
+        }
+    }
+
+    let mut moves: Vec<(u64, u16, u32)> = stats
+        .into_iter()
+        .filter(|(_, s)| s.games >= options.min_games && s.weight > 0)
+        .map(|((key, mv), s)| (key, mv, s.weight))
+        .collect();
+    moves.sort_unstable();
+    // weights are scaled down per position when the largest does not fit in 16 bits
+    let mut entries = Vec::with_capacity(moves.len());
+    for position in moves.chunk_by(|a, b| a.0 == b.0) {
+        let max = position.iter().map(|&(_, _, w)| w).max().unwrap_or(0) as u64;
+        for &(key, mv, weight) in position {
+            let weight = match max > u16::MAX as u64 {
+                true => (weight as u64 * u16::MAX as u64 / max).max(1),
+                false => weight as u64,
+            };
+            entries.push(BookEntry { key, mv, weight: weight as u16, learn: 0 });
+        }
+    }
+    let book = Book::new(entries);
+    book.save(&options.out)?;
+    let positions = book.entries.chunk_by(|a, b| a.key == b.key).count();
+    println!(
+        "Read {read} games, used {used}, {errors} with errors. Wrote {} moves in {positions} positions to {}",
+        book.entries.len(),
+        options.out
+    );
+    Ok(())
+}
+
+// merges books into one. a position takes its moves from the first book
+// that has it, so earlier books override later ones
+pub fn mergebook(inputs: &[String], output: &str) -> Result<usize, BookError> {
+    let mut entries = Vec::new();
+    let mut seen = HashSet::new();
+    for path in inputs {
+        let book = Book::load(path)?;
+        let keys: HashSet<u64> = book.entries.iter().map(|e| e.key).collect();
+        entries.extend(book.entries.into_iter().filter(|e| !seen.contains(&e.key)));
+        seen.extend(keys);
+    }
+    let book = Book::new(entries);
+    book.save(output)?;
+    Ok(book.entries.len())
+}
+
+// prints the entries of a position with their moves in SAN
+pub fn dumpbook(at: &AttackTable, path: &str, fen: &str) -> Result<(), BookError> {
+    let book = Book::load(path)?;
+    let mut board = Board::new();
+    let parsed = if fen.is_empty() { board.parse_fen(None) } else { board.parse_fen(Some(fen)) };
+    parsed.map_err(|e| BookError::BadFen(e.to_string()))?;
+
+    let key = polyglot_key(&board);
+    let entries = book.entries(key);
+    println!("{} (key {key:016x}): {} moves", board.as_fen(), entries.len());
+    let total: u64 = entries.iter().map(|e| e.weight as u64).sum();
+    for e in entries {
+        let san = match decode_move(&board, at, e.mv) {
+            Some(m) => move_to_san(&board, at, m),
+            None => format!("illegal {:04x}", e.mv),
+        };
+        let share = if total > 0 { 100.0 * e.weight as f64 / total as f64 } else { 0.0 };
+        println!("{san:<8} weight {:>5} {share:>6.2}% learn {}", e.weight, e.learn);
+    }
+    Ok(())
+}
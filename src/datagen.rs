@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use self::packed::{PackedError, PackedWriter, Record};
+use crate::{
+    board::{fen::Epd, Board},
+    defs::Colors,
+    movegen::{in_check, legal_moves},
+    moves::AttackTable,
+    rng::Rng,
+    search::{
+        defs::{SearchLimits, MATE_BOUND},
+        Search,
+    },
+};
+
+pub mod packed;
+
+// openings scored beyond this after the random plies are thrown away
+const OPENING_SCORE_LIMIT: i32 = 1000;
+const DATAGEN_HASH_MB: usize = 8;
+
+#[derive(Debug)]
+pub enum DatagenError {
+    Io(String),
+    BadOption(String),
+    BadBook(usize, String),
+    Packed(PackedError),
+}
+
+impl Display for DatagenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error writing training data: {e}"),
+            Self::BadOption(o) => write!(f, "Error in datagen options: {o}"),
+            Self::BadBook(line, e) => write!(f, "Error in opening book line {line}: {e}"),
+            Self::Packed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+pub struct DatagenOptions {
+    pub output: String,
+    // write the packed binary format instead of text
+    pub packed: bool,
+    pub games: usize,
+    pub nodes: u64,
+    pub random_plies: usize,
+    pub book: Option<String>,
+    pub threads: usize,
+    pub seed: u64,
+    // a side scoring at least win_score for win_plies consecutive plies wins
+    pub win_score: i32,
+    pub win_plies: usize,
+    // from draw_after plies on, |score| <= draw_score for draw_plies plies is a draw
+    pub draw_after: usize,
+    pub draw_score: i32,
+    pub draw_plies: usize,
+    pub max_plies: usize,
+}
+
+impl Default for DatagenOptions {
+    fn default() -> DatagenOptions {
+        DatagenOptions {
+            output: String::from("data.txt"),
+            packed: false,
+            games: 1000,
+            nodes: 5000,
+            random_plies: 8,
+            book: None,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: 1,
+            win_score: 1500,
+            win_plies: 4,
+            draw_after: 80,
+            draw_score: 10,
+            draw_plies: 12,
+            max_plies: 400,
+        }
+    }
+}
+
+impl DatagenOptions {
+    // rustChess datagen [--out file] [--format text|packed] [--games n] [--nodes n] [--random-plies n] [--book file.epd]
+    //   [--threads n] [--seed n] [--win-score cp] [--win-plies n] [--draw-after n]
+    //   [--draw-score cp] [--draw-plies n] [--max-plies n]
+    pub fn parse(args: &[String]) -> Result<DatagenOptions, DatagenError> {
+        let mut options = DatagenOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = iter.next().ok_or_else(|| DatagenError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || DatagenError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--out" => options.output = value.clone(),
+                "--format" => {
+                    options.packed = match value.as_str() {
+                        "text" => false,
+                        "packed" => true,
+                        _ => return Err(bad()),
+                    }
+                }
+                "--games" => options.games = value.parse().map_err(|_| bad())?,
+                "--nodes" => options.nodes = value.parse().map_err(|_| bad())?,
+                "--random-plies" => options.random_plies = value.parse().map_err(|_| bad())?,
+                "--book" => options.book = Some(value.clone()),
+                "--threads" => options.threads = value.parse().map_err(|_| bad())?,
+                "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+                "--win-score" => options.win_score = value.parse().map_err(|_| bad())?,
+                "--win-plies" => options.win_plies = value.parse().map_err(|_| bad())?,
+                "--draw-after" => options.draw_after = value.parse().map_err(|_| bad())?,
+                "--draw-score" => options.draw_score = value.parse().map_err(|_| bad())?,
+                "--draw-plies" => options.draw_plies = value.parse().map_err(|_| bad())?,
+                "--max-plies" => options.max_plies = value.parse().map_err(|_| bad())?,
+                _ => return Err(DatagenError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.threads == 0 || options.nodes == 0 {
+            return Err(DatagenError::BadOption(String::from("threads and nodes must be positive")));
+        }
+        Ok(options)
+    }
+}
+
+// the position of every EPD line; opcodes are ignored
+pub fn load_book(path: &str) -> Result<Vec<Board>, DatagenError> {
+    let text = fs::read_to_string(path).map_err(|e| DatagenError::Io(format!("{path}: {e}")))?;
+    let mut book = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let epd = Epd::parse(line).map_err(|e| DatagenError::BadBook(n + 1, e.to_string()))?;
+        book.push(epd.board);
+    }
+    if book.is_empty() {
+        return Err(DatagenError::BadBook(0, String::from("no positions")));
+    }
+    Ok(book)
+}
+
+struct Game {
+    // every position kept for training with its white-relative score
+    positions: Vec<(Board, i32)>,
+    // from white's point of view
+    result: f32,
+}
+
+// the start position after the book line and random plies, or None when the
+// random moves ran into a finished game
+pub fn play_opening(at: &AttackTable, book: &[Board], random_plies: usize, rng: &mut Rng) -> Option<(Board, Vec<u64>)> {
+    let mut board = if book.is_empty() {
+        let mut start = Board::new();
+        start.parse_fen(None).ok()?;
+        start
+    } else {
+        book[rng.below(book.len())]
+    };
+    let mut keys = Vec::new();
+    for _ in 0..random_plies {
+        let moves = legal_moves(&board, at);
+        if moves.is_empty() {
+            return None;
+        }
+        keys.push(board.key);
+        board.make_move(moves.get(rng.below(moves.len())));
+    }
+    if legal_moves(&board, at).is_empty() {
+        return None;
+    }
+    Some((board, keys))
+}
+
+fn play_game(
+    at: &AttackTable,
+    search: &mut Search,
+    options: &DatagenOptions,
+    mut board: Board,
+    mut keys: Vec<u64>,
+) -> Option<Game> {
+    let limits = SearchLimits::nodes(options.nodes);
+    let mut positions = Vec::new();
+    let (mut white_wins, mut black_wins, mut quiet_plies) = (0, 0, 0);
+
+    for ply in 0.. {
+        let white = board.side_to_move() == Colors::WHITE as usize;
+        if let Some(result) = board.status(at, &keys).result() {
+            return Some(Game { positions, result: result as f32 });
+        }
+        if ply >= options.max_plies {
+            return Some(Game { positions, result: 0.5 });
+        }
+
+        let found = search.go(&board, &keys, limits);
+        let white_score = if white { found.score } else { -found.score };
+        if ply == 0 && found.score.abs() > OPENING_SCORE_LIMIT {
+            return None;
+        }
+
+        white_wins = if white_score >= options.win_score { white_wins + 1 } else { 0 };
+        black_wins = if white_score <= -options.win_score { black_wins + 1 } else { 0 };
+        quiet_plies = if ply >= options.draw_after && white_score.abs() <= options.draw_score { quiet_plies + 1 } else { 0 };
+        if white_wins >= options.win_plies {
+            return Some(Game { positions, result: 1.0 });
+        }
+        if black_wins >= options.win_plies {
+            return Some(Game { positions, result: 0.0 });
+        }
+        if quiet_plies >= options.draw_plies {
+            return Some(Game { positions, result: 0.5 });
+        }
+
+        // positions in check or with a tactical best move say little about the static evaluation
+        let best = found.best_move;
+        let tactical = best.is_capture() || best.is_promotion();
+        if !tactical && !in_check(&board, at) && found.score.abs() < MATE_BOUND {
+            positions.push((board, white_score));
+        }
+
+        keys.push(board.key);
+        board.make_move(best);
+    }
+    None
+}
+
+// finished games are written in game order, so the output only depends on the seed
+enum DataWriter {
+    Text(BufWriter<File>),
+    Packed(PackedWriter<BufWriter<File>>),
+}
+
+struct Output {
+    writer: DataWriter,
+    next: usize,
+    pending: BTreeMap<usize, Option<Game>>,
+    positions: usize,
+    games: usize,
+}
+
+impl Output {
+    fn add(&mut self, index: usize, game: Option<Game>) -> Result<(), DatagenError> {
+        self.pending.insert(index, game);
+        while let Some(game) = self.pending.remove(&self.next) {
+            self.next += 1;
+            let Some(game) = game else { continue };
+            self.games += 1;
+            self.positions += game.positions.len();
+            for &(board, score) in &game.positions {
+                let record = Record { board, score: score.clamp(i16::MIN as i32, i16::MAX as i32) as i16, result: game.result };
+                match &mut self.writer {
+                    DataWriter::Text(w) => writeln!(w, "{}", record.as_text()).map_err(|e| DatagenError::Io(e.to_string()))?,
+                    DataWriter::Packed(w) => w.write(&record).map_err(DatagenError::Packed)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn datagen(at: &AttackTable, options: &DatagenOptions) -> Result<(), DatagenError> {
+    let book = match &options.book {
+        Some(path) => load_book(path)?,
+        None => Vec::new(),
+    };
+    let file = File::create(&options.output).map_err(|e| DatagenError::Io(format!("{}: {e}", options.output)))?;
+    let writer = if options.packed {
+        DataWriter::Packed(PackedWriter::new(BufWriter::new(file)).map_err(DatagenError::Packed)?)
+    } else {
+        DataWriter::Text(BufWriter::new(file))
+    };
+    let output = Mutex::new(Output { writer, next: 0, pending: BTreeMap::new(), positions: 0, games: 0 });
+    let next_game = AtomicUsize::new(0);
+    let started = Instant::now();
+
+    thread::scope(|s| -> Result<(), DatagenError> {
+        let workers: Vec<_> = (0..options.threads)
+            .map(|_| {
+                s.spawn(|| -> Result<(), DatagenError> {
+                    let mut search = Search::new(at, DATAGEN_HASH_MB);
+                    loop {
+                        let index = next_game.fetch_add(1, Ordering::Relaxed);
+                        if index >= options.games {
+                            return Ok(());
+                        }
+                        // every game has its own seed so the thread count doesn't change the data
+                        let mut rng = Rng::new(options.seed.wrapping_mul(0x9e3779b97f4a7c15) ^ index as u64);
+                        search.clear();
+                        let game = play_opening(at, &book, options.random_plies, &mut rng)
+                            .and_then(|(board, keys)| play_game(at, &mut search, options, board, keys));
+
+                        let mut output = output.lock().expect("datagen output lock poisoned");
+                        output.add(index, game)?;
+                        if (index + 1).is_multiple_of(100) {
+                            let seconds = started.elapsed().as_secs_f64().max(1e-9);
+                            println!(
+                                "{} games, {} positions, {:.0} positions/s",
+                                output.games,
+                                output.positions,
+                                output.positions as f64 / seconds
+                            );
+                        }
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().expect("datagen thread panicked")?;
+        }
+        Ok(())
+    })?;
+
+    let output = output.into_inner().expect("datagen output lock poisoned");
+    match output.writer {
+        DataWriter::Text(mut w) => w.flush().map_err(|e| DatagenError::Io(e.to_string()))?,
+        DataWriter::Packed(w) => {
+            w.finish().map_err(DatagenError::Packed)?;
+        }
+    }
+    println!("Wrote {} positions from {} games to {}", output.positions, output.games, options.output);
+    Ok(())
+}
@@ -0,0 +1,518 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+use crate::{
+    board::{bitboard::get_lsb, Board, STANDARD_CASTLING_ROOKS},
+    defs::{Color, Colors, NrOf, Piece, Variant, EMPTY},
+    rng::Rng,
+};
+
+// file layout: magic "RCTD", version u32, then RECORD_SIZE bytes per position.
+// a record, all integers little-endian:
+//   0..8    occupancy bitboard
+//   8..24   one 4-bit code per occupied square in ascending square order, low
+//           nibble first: piece index (king 0 .. pawn 5), +8 for black
+//   24      en passant square, 64 if none; bit 7 set when black is to move
+//   25      castling rights as in Board::castling
+//   26      half-move clock
+//   27..29  full-move number u16
+//   29..31  score i16, centipawns from white's point of view
+//   31      result from white's point of view: 0 loss, 1 draw, 2 win
+//   32..34  since version 2, u16: the files of the castling rooks in the order
+//           of Board::castling_rooks, 3 bits each, and bit 15 for Chess960
+// new versions may only append fields, so older records are read by filling
+// in what they lack; readers reject versions they don't know
+pub const MAGIC: &[u8; 4] = b"RCTD";
+pub const VERSION: u32 = 2;
+pub const HEADER_SIZE: usize = 8;
+pub const RECORD_SIZE: usize = 34;
+// version 1 records stop before the castling rooks, which were always the standard ones
+const V1_RECORD_SIZE: usize = 32;
+const CHESS960_FLAG: u16 = 0x8000;
+const MAX_PIECES: usize = 32;
+const NO_EN_PASSANT: u8 = 64;
+const BLACK_TO_MOVE: u8 = 0x80;
+const BLACK_CODE: u8 = 8;
+
+#[derive(Debug)]
+pub enum PackedError {
+    Io(String),
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    BadRecord(u64, String),
+    BadLine(usize, String),
+    // pockets, promoted pieces and the variant have no place in a record
+    UnsupportedVariant(Variant),
+}
+
+impl Display for PackedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error accessing training data: {e}"),
+            Self::BadMagic => write!(f, "Error in packed data: not a rustChess training file"),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "Error in packed data: format version {v}, this build reads version {VERSION}")
+            }
+            Self::Truncated => write!(f, "Error in packed data: file ends inside a record"),
+            Self::BadRecord(n, e) => write!(f, "Error in packed data record {n}: {e}"),
+            Self::BadLine(n, e) => write!(f, "Error in training data line {n}: {e}"),
+            Self::UnsupportedVariant(v) => write!(f, "Error in packed data: {} positions can't be packed", v.as_string()),
+        }
+    }
+}
+
+// one labelled position; score and result are from white's point of view
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Record {
+    pub board: Board,
+    pub score: i16,
+    pub result: f32,
+}
+
+impl Record {
+    pub fn pack(&self) -> Result<[u8; RECORD_SIZE], PackedError> {
+        if self.board.variant != Variant::Standard {
+            return Err(PackedError::UnsupportedVariant(self.board.variant));
+        }
+        let mut bytes = [0u8; RECORD_SIZE];
+        let occupancy = self.board.get_all_pieces();
+        bytes[0..8].copy_from_slice(&occupancy.to_le_bytes());
+
+        let mut bb = occupancy;
+        let mut i = 0;
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            let black = self.board.color[Colors::BLACK as Color] & (1 << sq) != 0;
+            let code = self.board.piece_on(sq) as u8 | if black { BLACK_CODE } else { 0 };
+            bytes[8 + i / 2] |= code << (4 * (i % 2));
+            i += 1;
+        }
+
+        bytes[24] = self.board.en_passant.unwrap_or(NO_EN_PASSANT) | if self.board.turn { 0 } else { BLACK_TO_MOVE };
+        bytes[25] = self.board.castling;
+        bytes[26] = self.board.half_move;
+        bytes[27..29].copy_from_slice(&self.board.full_move.to_le_bytes());
+        bytes[29..31].copy_from_slice(&self.score.to_le_bytes());
+        bytes[31] = (self.result * 2.0).round() as u8;
+        bytes[32..34].copy_from_slice(&castling_rooks(&self.board.castling_rooks, self.board.chess960).to_le_bytes());
+        Ok(bytes)
+    }
+
+    pub fn unpack(bytes: &[u8; RECORD_SIZE]) -> Result<Record, String> {
+        let mut occupancy = u64::from_le_bytes(bytes[0..8].try_into().expect("slice of eight bytes"));
+        if occupancy.count_ones() as usize > MAX_PIECES {
+            return Err(String::from("more than 32 pieces"));
+        }
+
+        let mut board = Board::new();
+        let mut i = 0;
+        while occupancy != EMPTY {
+            let sq = get_lsb(occupancy);
+            occupancy &= occupancy - 1;
+            let code = (bytes[8 + i / 2] >> (4 * (i % 2))) & 0xf;
+            let piece = (code & !BLACK_CODE) as Piece;
+            if piece >= NrOf::PIECE_TYPES {
+                return Err(format!("bad piece code {code}"));
+            }
+            let color = if code & BLACK_CODE != 0 { Colors::BLACK } else { Colors::WHITE };
+            board.add_piece(piece, sq, color as Color);
+            i += 1;
+        }
+
+        let ep = bytes[24] & !BLACK_TO_MOVE;
+        board.en_passant = match ep {
+            NO_EN_PASSANT => None,
+            sq if sq < NO_EN_PASSANT => Some(sq),
+            _ => return Err(format!("bad en passant square {ep}")),
+        };
+        board.turn = bytes[24] & BLACK_TO_MOVE == 0;
+        board.castling = bytes[25] & 0xf;
+        board.half_move = bytes[26];
+        board.full_move = u16::from_le_bytes([bytes[27], bytes[28]]);
+        let rooks = u16::from_le_bytes([bytes[32], bytes[33]]);
+        for (i, rook) in board.castling_rooks.iter_mut().enumerate() {
+            // rights 2 and 3 are black's, on the eighth rank
+            *rook = (rooks >> (3 * i)) as u8 & 7 | if i < 2 { 0 } else { 56 };
+        }
+        board.chess960 = rooks & CHESS960_FLAG != 0;
+        board.init();
+
+        let score = i16::from_le_bytes([bytes[29], bytes[30]]);
+        if bytes[31] > 2 {
+            return Err(format!("bad result {}", bytes[31]));
+        }
+        Ok(Record { board, score, result: bytes[31] as f32 / 2.0 })
+    }
+
+    // "<fen> | <score> | <result>", the result as 1.0, 0.5, 0.0 or 1-0, 1/2-1/2, 0-1
+    pub fn from_text(line: &str) -> Result<Record, String> {
+        let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+        if parts.len() != 3 {
+            return Err(String::from("expected fen | score | result"));
+        }
+        let mut board = Board::new();
+        board.parse_fen(Some(parts[0])).map_err(|e| e.to_string())?;
+        let score: f32 = parts[1].parse().map_err(|_| format!("bad score {}", parts[1]))?;
+        let result: f32 = match parts[2].trim_matches(|c| c == '[' || c == ']') {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            "1/2-1/2" => 0.5,
+            r => r.parse().ok().filter(|r| [0.0, 0.5, 1.0].contains(r)).ok_or_else(|| format!("bad result {r}"))?,
+        };
+        let score = score.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        Ok(Record { board, score, result })
+    }
+
+    pub fn as_text(&self) -> String {
+        format!("{} | {} | {:.1}", self.board.as_fen(), self.score, self.result)
+    }
+}
+
+fn castling_rooks(rooks: &[u8; 4], chess960: bool) -> u16 {
+    let files = rooks.iter().enumerate().fold(0, |files, (i, &sq)| files | (sq as u16 % 8) << (3 * i));
+    files | if chess960 { CHESS960_FLAG } else { 0 }
+}
+
+pub struct PackedWriter<W: Write> {
+    inner: W,
+    pub count: u64,
+}
+
+impl<W: Write> PackedWriter<W> {
+    pub fn new(mut inner: W) -> Result<PackedWriter<W>, PackedError> {
+        inner.write_all(MAGIC).map_err(io_error)?;
+        inner.write_all(&VERSION.to_le_bytes()).map_err(io_error)?;
+        Ok(PackedWriter { inner, count: 0 })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), PackedError> {
+        self.write_raw(&record.pack()?)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8; RECORD_SIZE]) -> Result<(), PackedError> {
+        self.count += 1;
+        self.inner.write_all(bytes).map_err(io_error)
+    }
+
+    pub fn finish(mut self) -> Result<W, PackedError> {
+        self.inner.flush().map_err(io_error)?;
+        Ok(self.inner)
+    }
+}
+
+// reads one record at a time, so files larger than memory can be streamed
+pub struct PackedReader<R: Read> {
+    inner: R,
+    count: u64,
+    // bytes per record in the version being read
+    record_size: usize,
+}
+
+impl<R: Read> PackedReader<R> {
+    pub fn new(mut inner: R) -> Result<PackedReader<R>, PackedError> {
+        let mut header = [0u8; HEADER_SIZE];
+        inner.read_exact(&mut header).map_err(|_| PackedError::BadMagic)?;
+        if &header[..4] != MAGIC {
+            return Err(PackedError::BadMagic);
+        }
+        let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let record_size = match version {
+            1 => V1_RECORD_SIZE,
+            VERSION => RECORD_SIZE,
+            _ => return Err(PackedError::UnsupportedVersion(version)),
+        };
+        Ok(PackedReader { inner, count: 0, record_size })
+    }
+
+    // a record of the current version, whatever version the file has
+    fn read_raw(&mut self) -> Option<Result<[u8; RECORD_SIZE], PackedError>> {
+        let mut bytes = [0u8; RECORD_SIZE];
+        if self.record_size == V1_RECORD_SIZE {
+            bytes[32..34].copy_from_slice(&castling_rooks(&STANDARD_CASTLING_ROOKS, false).to_le_bytes());
+        }
+        let mut filled = 0;
+        while filled < self.record_size {
+            match self.inner.read(&mut bytes[filled..self.record_size]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(PackedError::Truncated)),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => return Some(Err(io_error(e))),
+            }
+        }
+        self.count += 1;
+        Some(Ok(bytes))
+    }
+}
+
+impl<R: Read> Iterator for PackedReader<R> {
+    type Item = Result<Record, PackedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bytes = match self.read_raw()? {
+            Ok(bytes) => bytes,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(Record::unpack(&bytes).map_err(|e| PackedError::BadRecord(self.count, e)))
+    }
+}
+
+fn io_error(e: std::io::Error) -> PackedError {
+    PackedError::Io(e.to_string())
+}
+
+fn open(path: &str) -> Result<BufReader<File>, PackedError> {
+    File::open(path).map(BufReader::new).map_err(|e| PackedError::Io(format!("{path}: {e}")))
+}
+
+fn create(path: &str) -> Result<BufWriter<File>, PackedError> {
+    File::create(path).map(BufWriter::new).map_err(|e| PackedError::Io(format!("{path}: {e}")))
+}
+
+pub fn is_packed(path: &str) -> bool {
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && &magic == MAGIC
+}
+
+// every record of a text or packed file, whichever `path` holds
+pub fn read_records(path: &str) -> Result<Vec<Record>, PackedError> {
+    if is_packed(path) {
+        return PackedReader::new(open(path)?)?.collect();
+    }
+    let mut records = Vec::new();
+    for (n, line) in open(path)?.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        if !line.trim().is_empty() {
+            records.push(Record::from_text(&line).map_err(|e| PackedError::BadLine(n + 1, e))?);
+        }
+    }
+    Ok(records)
+}
+
+// text to packed or packed to text, depending on what `input` holds; returns the record count
+pub fn convert(input: &str, output: &str) -> Result<u64, PackedError> {
+    if is_packed(input) {
+        let mut writer = create(output)?;
+        let mut count = 0;
+        for record in PackedReader::new(open(input)?)? {
+            writeln!(writer, "{}", record?.as_text()).map_err(io_error)?;
+            count += 1;
+        }
+        writer.flush().map_err(io_error)?;
+        return Ok(count);
+    }
+    let mut writer = PackedWriter::new(create(output)?)?;
+    for (n, line) in open(input)?.lines().enumerate() {
+        let line = line.map_err(io_error)?;
+        if !line.trim().is_empty() {
+            writer.write(&Record::from_text(&line).map_err(|e| PackedError::BadLine(n + 1, e))?)?;
+        }
+    }
+    let count = writer.count;
+    writer.finish()?;
+    Ok(count)
+}
+
+// shuffles the records of a packed file in memory; RECORD_SIZE bytes per position
+pub fn shuffle(input: &str, output: &str, seed: u64) -> Result<u64, PackedError> {
+    let mut reader = PackedReader::new(open(input)?)?;
+    let mut records = Vec::new();
+    while let Some(bytes) = reader.read_raw() {
+        records.push(bytes?);
+    }
+    Rng::new(seed).shuffle(&mut records);
+
+    let mut writer = PackedWriter::new(create(output)?)?;
+    for record in &records {
+        writer.write_raw(record)?;
+    }
+    let count = writer.count;
+    writer.finish()?;
+    Ok(count)
+}
+
+// keeps the first record of every position, compared by Zobrist key, so
+// clocks and labels don't make a position distinct; returns (kept, read)
+pub fn dedup(input: &str, output: &str) -> Result<(u64, u64), PackedError> {
+    let mut reader = PackedReader::new(open(input)?)?;
+    let mut writer = PackedWriter::new(create(output)?)?;
+    let mut seen = HashSet::new();
+    while let Some(bytes) = reader.read_raw() {
+        let bytes = bytes?;
+        let record = Record::unpack(&bytes).map_err(|e| PackedError::BadRecord(reader.count, e))?;
+        if seen.insert(record.board.key) {
+            writer.write_raw(&bytes)?;
+        }
+    }
+    let kept = writer.count;
+    writer.finish()?;
+    Ok((kept, reader.count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const FENS: [&str; 5] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 37 1234",
+        "4k3/8/8/8/8/8/8/4K3 b - - 100 9999",
+    ];
+
+    fn record(fen: &str, score: i16, result: f32) -> Record {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        Record { board, score, result }
+    }
+
+    #[test]
+    fn start_position_layout() {
+        let bytes = record(FENS[0], -25, 0.5).pack().unwrap();
+        // ranks 1, 2, 7 and 8 are occupied
+        assert_eq!(&bytes[0..8], &0xffff_0000_0000_ffffu64.to_le_bytes());
+        // a1 rook (2) and b1 knight (4) share the first byte, low nibble first
+        assert_eq!(bytes[8], 0x42);
+        // white pawns on the second rank
+        assert_eq!(&bytes[12..16], &[0x55; 4]);
+        // black pawns, then black r n b q k b n r
+        assert_eq!(&bytes[16..20], &[0xdd; 4]);
+        assert_eq!(&bytes[20..24], &[0xca, 0x9b, 0xb8, 0xac]);
+        assert_eq!(bytes[24], NO_EN_PASSANT);
+        assert_eq!(bytes[25], 0xf);
+        assert_eq!(bytes[26], 0);
+        assert_eq!(&bytes[27..29], &[1, 0]);
+        assert_eq!(&bytes[29..31], &(-25i16).to_le_bytes());
+        assert_eq!(bytes[31], 1);
+        // h, a, h and a rook files, not Chess960
+        assert_eq!(u16::from_le_bytes([bytes[32], bytes[33]]), 0b000_111_000_111);
+    }
+
+    #[test]
+    fn side_and_en_passant_share_a_byte() {
+        let white = record(FENS[2], 0, 1.0).pack().unwrap();
+        assert_eq!(white[24], 45); // f6
+        let black = record(FENS[3], 0, 0.0).pack().unwrap();
+        assert_eq!(black[24], BLACK_TO_MOVE | NO_EN_PASSANT);
+        assert_eq!(black[26], 37);
+        assert_eq!(u16::from_le_bytes([black[27], black[28]]), 1234);
+        assert_eq!(black[31], 0);
+    }
+
+    #[test]
+    fn records_round_trip() {
+        for (i, fen) in FENS.iter().enumerate() {
+            let original = record(fen, i as i16 * 300 - 600, i as f32 % 3.0 / 2.0);
+            let unpacked = Record::unpack(&original.pack().unwrap()).unwrap();
+            assert_eq!(unpacked, original);
+            assert_eq!(unpacked.board.as_fen(), *fen);
+            assert_eq!(unpacked.board.key, original.board.key);
+        }
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let line = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 37 1234 | -143 | 0.0";
+        let parsed = Record::from_text(line).unwrap();
+        assert_eq!(parsed.as_text(), line);
+        assert_eq!(Record::from_text("8/8/8/8/8/8/8/K1k5 w - - 0 1 | 12.6 | 1/2-1/2").unwrap().score, 13);
+        assert!(Record::from_text("8/8/8/8/8/8/8/K1k5 w - - 0 1 | 0 | 0.3").is_err());
+        assert!(Record::from_text("8/8/8/8/8/8/8/K1k5 w - - 0 1 | 0").is_err());
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let records: Vec<Record> = FENS.iter().map(|fen| record(fen, 10, 1.0)).collect();
+        let mut writer = PackedWriter::new(Vec::new()).unwrap();
+        records.iter().for_each(|r| writer.write(r).unwrap());
+        let bytes = writer.finish().unwrap();
+        assert_eq!(&bytes[..4], MAGIC);
+        assert_eq!(&bytes[4..8], &VERSION.to_le_bytes());
+        assert_eq!(bytes.len(), HEADER_SIZE + RECORD_SIZE * FENS.len());
+
+        let read: Vec<Record> = PackedReader::new(&bytes[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(read, records);
+
+        let cut = &bytes[..bytes.len() - 1];
+        assert!(matches!(PackedReader::new(cut).unwrap().last(), Some(Err(PackedError::Truncated))));
+        let mut other_version = bytes.clone();
+        other_version[4] = 3;
+        assert!(matches!(PackedReader::new(&other_version[..]), Err(PackedError::UnsupportedVersion(3))));
+        assert!(matches!(PackedReader::new(&b"fen | 0 | 0.5"[..]), Err(PackedError::BadMagic)));
+    }
+
+    #[test]
+    fn chess960_castling_round_trips() {
+        // the b1 rook is not the outermost one, so X-FEN needs its file
+        let original = record("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/RR2K1R1 w GBgb - 0 1", 0, 0.5);
+        let unpacked = Record::unpack(&original.pack().unwrap()).unwrap();
+        assert_eq!(unpacked, original);
+        assert_eq!(unpacked.board.castling_rooks, [6, 1, 62, 57]);
+        assert!(unpacked.board.chess960);
+        assert_eq!(unpacked.board.as_fen(), "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/RR2K1R1 w KBkq - 0 1");
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let records: Vec<Record> = FENS.iter().map(|fen| record(fen, 10, 1.0)).collect();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        records.iter().for_each(|r| bytes.extend_from_slice(&r.pack().unwrap()[..V1_RECORD_SIZE]));
+        let read: Vec<Record> = PackedReader::new(&bytes[..]).unwrap().map(Result::unwrap).collect();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn variants_are_rejected() {
+        let crazyhouse = record("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Nn] w KQkq - 0 1", 0, 0.5);
+        assert!(matches!(crazyhouse.pack(), Err(PackedError::UnsupportedVariant(Variant::Crazyhouse))));
+        let mut atomic = record(FENS[0], 0, 0.5);
+        atomic.board.variant = Variant::Atomic;
+        let mut writer = PackedWriter::new(Vec::new()).unwrap();
+        assert!(matches!(writer.write(&atomic), Err(PackedError::UnsupportedVariant(Variant::Atomic))));
+    }
+
+    #[test]
+    fn bad_records_are_rejected() {
+        let mut bytes = record(FENS[0], 0, 0.5).pack().unwrap();
+        bytes[8] = 0x47;
+        assert!(Record::unpack(&bytes).is_err());
+        let mut bytes = record(FENS[0], 0, 0.5).pack().unwrap();
+        bytes[31] = 3;
+        assert!(Record::unpack(&bytes).is_err());
+    }
+
+    #[test]
+    fn shuffle_and_dedup_files() {
+        let dir = std::env::temp_dir().join(format!("rustchess-packed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+
+        // the same position with different clocks and labels counts as a duplicate
+        let text: Vec<String> = FENS
+            .iter()
+            .map(|fen| record(fen, 1, 1.0).as_text())
+            .chain([record("4k3/8/8/8/8/8/8/4K3 b - - 3 70", -5, 0.5).as_text()])
+            .collect();
+        fs::write(path("data.txt"), text.join("\n")).unwrap();
+
+        assert_eq!(convert(&path("data.txt"), &path("data.bin")).unwrap(), 6);
+        assert_eq!(dedup(&path("data.bin"), &path("dedup.bin")).unwrap(), (5, 6));
+        assert_eq!(shuffle(&path("dedup.bin"), &path("shuffled.bin"), 7).unwrap(), 5);
+        assert_eq!(convert(&path("shuffled.bin"), &path("shuffled.txt")).unwrap(), 5);
+
+        let mut expected: Vec<String> = text[..5].to_vec();
+        let mut shuffled: Vec<String> = fs::read_to_string(path("shuffled.txt")).unwrap().lines().map(String::from).collect();
+        expected.sort();
+        shuffled.sort();
+        assert_eq!(shuffled, expected);
+        assert_eq!(read_records(&path("shuffled.bin")).unwrap().len(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
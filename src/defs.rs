@@ -11,11 +11,14 @@ impl NrOf {
     pub const FILES: usize = 8;
     pub const RANKS: usize = 8;
     pub const COLORS: usize = 2;
+    // most pieces of one type a crazyhouse pocket can hold: every pawn of the game
+    pub const POCKET: usize = 16;
 }
 
 pub const FEN_START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
-pub const MAX_MOVES: u8 = 100;
+// largest half move clock a FEN may give; the 75-move rule ends every game by then
+pub const MAX_MOVES: u8 = 150;
 
 pub type Square = usize;
 pub type Piece = usize;
@@ -32,6 +35,8 @@ impl Castling {
 
 }
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Pieces {
     KING,
     QUEEN,
@@ -43,6 +48,19 @@ pub enum Pieces {
 }
 
 impl Pieces {
+    pub fn from_index(piece: Piece) -> Pieces {
+        match piece {
+            0 => Pieces::KING,
+            1 => Pieces::QUEEN,
+            2 => Pieces::ROOK,
+            3 => Pieces::BISHOP,
+            4 => Pieces::KNIGHT,
+            5 => Pieces::PAWN,
+            _ => Pieces::NONE,
+        }
+    }
+
+    #[allow(dead_code)]
     pub fn as_string(&self) -> &'static str {
         match self {
             Pieces::KING => "King",
@@ -71,12 +89,18 @@ impl Pieces {
 
 pub type Color = usize;
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Colors {
     WHITE,
     BLACK,
 }
 
 impl Colors {
+    pub fn from_index(color: Color) -> Colors {
+        if color == Colors::WHITE as Color { Colors::WHITE } else { Colors::BLACK }
+    }
+
     pub fn as_string(&self) -> &'static str {
         match self {
             Colors::WHITE => "White",
@@ -85,6 +109,30 @@ impl Colors {
     }
 }
 
+// rules other than standard chess, named as in the UCI_Variant option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Standard,
+    Crazyhouse,
+    Atomic,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 3] = [Variant::Standard, Variant::Crazyhouse, Variant::Atomic];
+
+    pub fn as_string(&self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        Variant::ALL.into_iter().find(|v| v.as_string().eq_ignore_ascii_case(name))
+    }
+}
+
 const fn init_bb_squares() -> [Bitboard; NrOf::SQUARES] {
     let mut squares = [0; NrOf::SQUARES];
     let mut i = 0;
@@ -97,6 +145,7 @@ const fn init_bb_squares() -> [Bitboard; NrOf::SQUARES] {
 
 pub const BB_SQUARES: [Bitboard; NrOf::SQUARES] = init_bb_squares();
 
+#[allow(non_camel_case_types)]
 #[derive(Debug, EnumIter)]
 pub enum ALL_SQUARES {
     A1,
@@ -0,0 +1,244 @@
+pub mod attacks;
+pub mod defs;
+mod activity;
+mod endgame;
+mod king_safety;
+mod kpk;
+pub mod params;
+mod threats;
+pub mod trace;
+pub mod tuner;
+
+use crate::{
+    board::Board,
+    board::bitboard::{Bitboard, count_bits},
+    defs::{Color, Colors, NrOf, Pieces, Square, Variant, EMPTY},
+    moves::AttackTable,
+};
+
+use self::{
+    defs::{Score, PHASE_WEIGHTS, SCALE_DRAW, SCALE_NORMAL, TOTAL_PHASE},
+    attacks::AttackMaps,
+    activity::{bishops, files_and_ranks, mobility, outposts, trapped_pieces},
+    endgame::scale_factor,
+    king_safety::{king_attacks, pawn_shelter},
+    params::EvalParams,
+    threats::threats,
+};
+
+pub const TERM_COUNT: usize = 9;
+pub const TERM_NAMES: [&str; TERM_COUNT] = [
+    "Material",
+    "King shelter",
+    "King attacks",
+    "Mobility",
+    "Outposts",
+    "Bishops",
+    "Rooks and queens",
+    "Trapped pieces",
+    "Threats",
+];
+
+// static evaluation in centipawns from the point of view of the side to move
+pub fn evaluate_with(params: &EvalParams, board: &Board, at: &AttackTable) -> i32 {
+    let v = evaluate_white(params, board, at);
+    if board.turn { v } else { -v }
+}
+
+// static evaluation in centipawns from white's point of view
+pub fn evaluate_white(params: &EvalParams, board: &Board, at: &AttackTable) -> i32 {
+    // endgame knowledge is for standard chess, not drops or explosions
+    if let Some((v, _)) = endgame::probe(board).filter(|_| board.variant == Variant::Standard) {
+        return v;
+    }
+
+    let maps = AttackMaps::new(board, at);
+    let white = eval_terms(params, board, at, &maps, Colors::WHITE as Color);
+    let black = eval_terms(params, board, at, &maps, Colors::BLACK as Color);
+
+    let mut score = Score::ZERO;
+    for i in 0..TERM_COUNT {
+        score += white[i] - black[i];
+    }
+    blend(board, score)
+}
+
+// every evaluation term for `color`, in the order of TERM_NAMES
+pub fn eval_terms(params: &EvalParams, board: &Board, at: &AttackTable, maps: &AttackMaps, color: Color) -> [Score; TERM_COUNT] {
+    let has_king = board.get_pieces(Pieces::KING as usize, color) != EMPTY;
+    let enemy_has_king = board.get_pieces(Pieces::KING as usize, color ^ 1) != EMPTY;
+    [
+        material(params, board, color),
+        if has_king { pawn_shelter(params, board, color) } else { Score::ZERO },
+        if has_king { king_attacks(params, board, at, maps, color) } else { Score::ZERO },
+        mobility(params, board, maps, color),
+        outposts(params, board, maps, color),
+        bishops(params, board, color),
+        files_and_ranks(params, board, color),
+        trapped_pieces(params, board, maps, color),
+        if enemy_has_king { threats(params, board, at, maps, color) } else { Score::ZERO },
+    ]
+}
+
+// tapers a white-relative score by the game phase and scales its endgame part
+pub fn blend(board: &Board, score: Score) -> i32 {
+    let sf = match board.variant {
+        Variant::Standard => scale_factor(board, if score.eg > 0 { Colors::WHITE as Color } else { Colors::BLACK as Color }),
+        Variant::Crazyhouse | Variant::Atomic => SCALE_NORMAL,
+    };
+    if sf == SCALE_DRAW {
+        return 0;
+    }
+    let phase = game_phase(board);
+    (score.mg * phase + score.eg * (TOTAL_PHASE - phase) * sf / SCALE_NORMAL) / TOTAL_PHASE
+}
+
+pub fn material(params: &EvalParams, board: &Board, color: Color) -> Score {
+    let mut score = Score::ZERO;
+    for piece in 0..NrOf::PIECE_TYPES {
+        score += params.piece_values[piece] * count_bits(board.get_pieces(piece, color)) as i32;
+        // crazyhouse pieces in hand count as much as those on the board
+        score += params.piece_values[piece] * board.pockets[color][piece] as i32;
+    }
+    score
+}
+
+// TOTAL_PHASE with all minor and major pieces on the board, 0 with none
+pub fn game_phase(board: &Board) -> i32 {
+    let mut phase = 0;
+    for (weight, &pieces) in PHASE_WEIGHTS.iter().zip(board.pieces.iter()) {
+        phase += weight * count_bits(pieces) as i32;
+    }
+    phase.min(TOTAL_PHASE)
+}
+
+// attacks of a non-pawn piece standing on `sq`
+#[inline(always)]
+pub fn piece_attacks(at: &AttackTable, piece: usize, sq: Square, occ: Bitboard) -> Bitboard {
+    match piece {
+        p if p == Pieces::QUEEN as usize => at.get_bishop_attacks(sq, occ) | at.get_rook_attacks(sq, occ),
+        p if p == Pieces::ROOK as usize => at.get_rook_attacks(sq, occ),
+        p if p == Pieces::BISHOP as usize => at.get_bishop_attacks(sq, occ),
+        p if p == Pieces::KNIGHT as usize => at.knights[sq],
+        p if p == Pieces::KING as usize => at.kings[sq],
+        _ => EMPTY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{evaluation::{defs::KNOWN_WIN, params::DEFAULT_PARAMS}, moves::with_table};
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        board
+    }
+
+    // the same position with the colours swapped and the board flipped vertically
+    fn mirror(fen: &str) -> String {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let swap_case = |s: &str| -> String {
+            s.chars().map(|c| if c.is_ascii_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() }).collect()
+        };
+        let placement = fields[0].split('/').rev().map(swap_case).collect::<Vec<String>>().join("/");
+        let turn = if fields[1] == "w" { "b" } else { "w" };
+        let mut castling: Vec<char> = swap_case(fields[2]).chars().collect();
+        castling.sort_by_key(|c| (c.is_ascii_lowercase(), *c != 'K' && *c != 'k'));
+        let ep = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'1' + b'8' - rank) as char),
+            _ => "-".to_string(),
+        };
+        format!("{placement} {turn} {} {ep} {}", castling.into_iter().collect::<String>(), fields[4..].join(" "))
+    }
+
+    #[test]
+    fn colour_symmetry() {
+        with_table(|at| {
+            let fens = [
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R b KQ - 3 8",
+                "2r3k1/5pp1/p3p2p/1p1bP3/3B1P2/P5P1/1P4KP/2R5 w - - 0 30",
+                "8/5pk1/6p1/3B4/8/2b3P1/5PK1/8 b - - 0 50",
+                "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+            ];
+            for fen in fens {
+                let flipped = mirror(fen);
+                let (a, b) = (board(fen), board(&flipped));
+                assert_eq!(evaluate_white(&DEFAULT_PARAMS, &a, at), -evaluate_white(&DEFAULT_PARAMS, &b, at), "{fen} vs {flipped}");
+                assert_eq!(evaluate_with(&DEFAULT_PARAMS, &a, at), evaluate_with(&DEFAULT_PARAMS, &b, at), "{fen} vs {flipped}");
+            }
+        });
+    }
+
+    #[test]
+    fn pawn_shield_and_storm() {
+        let white = Colors::WHITE as Color;
+        let shelter = |fen| pawn_shelter(&DEFAULT_PARAMS, &board(fen), white).mg;
+
+        let intact = shelter("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let pushed = shelter("6k1/5ppp/8/8/8/6PP/5P2/6K1 w - - 0 1");
+        let missing = shelter("6k1/5ppp/8/8/8/8/5P2/6K1 w - - 0 1");
+        assert!(intact > pushed, "{intact} {pushed}");
+        assert!(pushed > missing, "{pushed} {missing}");
+
+        // black pawns rolling towards the white king are worse than ones at home
+        let quiet = shelter("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let storm = shelter("6k1/8/8/8/6pp/8/5PPP/6K1 w - - 0 1");
+        assert!(quiet > storm, "{quiet} {storm}");
+    }
+
+    #[test]
+    fn lone_minor_pieces_are_draws() {
+        with_table(|at| {
+            for fen in ["8/8/8/4k3/8/8/8/2B1K3 w - - 0 1", "8/8/8/4k3/8/8/8/1N2K3 b - - 0 1", "2b1k3/8/8/8/8/8/8/4K3 w - - 0 1"] {
+                assert_eq!(evaluate_white(&DEFAULT_PARAMS, &board(fen), at), 0, "{fen}");
+            }
+        });
+    }
+
+    #[test]
+    fn kpk_bitbase() {
+        with_table(|at| {
+            let eval = |fen: &str| evaluate_white(&DEFAULT_PARAMS, &board(fen), at);
+            let wins = [
+                // king on the sixth in front of the pawn wins whoever moves
+                "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+                "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+                // the black king is outside the square of the pawn
+                "k7/8/8/8/8/8/6P1/6K1 w - - 0 1",
+            ];
+            let draws = [
+                "4k3/8/4P3/4K3/8/8/8/8 w - - 0 1",
+                // the defending king reaches the corner in front of a rook pawn
+                "k7/8/8/8/8/8/P7/K7 w - - 0 1",
+            ];
+            for fen in wins {
+                assert!(eval(fen) > KNOWN_WIN, "{fen}");
+                assert!(eval(&mirror(fen)) < -KNOWN_WIN, "{fen} mirrored");
+            }
+            for fen in draws {
+                assert_eq!(eval(fen), 0, "{fen}");
+                assert_eq!(eval(&mirror(fen)), 0, "{fen} mirrored");
+            }
+        });
+    }
+
+    #[test]
+    fn kxk_pushes_the_king_to_the_edge() {
+        with_table(|at| {
+            let eval = |fen: &str| evaluate_white(&DEFAULT_PARAMS, &board(fen), at);
+            let centre = eval("8/8/8/4k3/8/4K3/8/R7 w - - 0 1");
+            let edge = eval("4k3/8/4K3/8/8/8/8/R7 w - - 0 1");
+            let far_king = eval("4k3/8/8/8/8/8/4K3/R7 w - - 0 1");
+            assert!(centre > KNOWN_WIN);
+            assert!(edge > centre, "{edge} {centre}");
+            // and to bring the strong king closer
+            assert!(edge > far_king, "{edge} {far_king}");
+            assert_eq!(eval("8/8/8/4k3/8/4K3/8/R7 b - - 0 1"), centre);
+            assert_eq!(eval(&mirror("4k3/8/4K3/8/8/8/8/R7 w - - 0 1")), -edge);
+        });
+    }
+}
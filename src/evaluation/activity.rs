@@ -0,0 +1,165 @@
+use crate::{
+    board::Board,
+    board::bitboard::{Bitboard, count_bits, get_lsb},
+    defs::{Castling, Color, Colors, Pieces, BB_SQUARES, EMPTY},
+    evaluation::{attacks::AttackMaps, defs::{Score, relative_rank}, params::EvalParams},
+    moves::{DARK_SQUARES, adjacent_files_bb, file_bb, forward_ranks_bb, rank_bb},
+};
+
+// squares not covered by enemy pawns and not taken by our own pawns or king
+pub fn mobility_area(board: &Board, maps: &AttackMaps, color: Color) -> Bitboard {
+    let enemy_pawn_attacks = maps.by_piece[color ^ 1][Pieces::PAWN as usize];
+    let own_blockers = board.get_pieces(Pieces::PAWN as usize, color) | board.get_pieces(Pieces::KING as usize, color);
+    !(enemy_pawn_attacks | own_blockers)
+}
+
+pub fn mobility(params: &EvalParams, board: &Board, maps: &AttackMaps, color: Color) -> Score {
+    let area = mobility_area(board, maps, color);
+
+    let mut score = Score::ZERO;
+    for piece in [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT] {
+        let p = piece as usize;
+        let mut bb = board.get_pieces(p, color);
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            score += mobility_bonus(params, p, count_bits(maps.from_square[sq] & area) as usize);
+        }
+    }
+    score
+}
+
+#[inline(always)]
+fn mobility_bonus(params: &EvalParams, piece: usize, count: usize) -> Score {
+    match piece {
+        p if p == Pieces::QUEEN as usize => params.queen_mobility[count],
+        p if p == Pieces::ROOK as usize => params.rook_mobility[count],
+        p if p == Pieces::BISHOP as usize => params.bishop_mobility[count],
+        p if p == Pieces::KNIGHT as usize => params.knight_mobility[count],
+        _ => Score::ZERO,
+    }
+}
+
+// a knight or bishop on the 4th to 6th rank, protected by a pawn and out of
+// reach of every enemy pawn
+pub fn outposts(params: &EvalParams, board: &Board, maps: &AttackMaps, color: Color) -> Score {
+    let them = color ^ 1;
+    let their_pawns = board.get_pieces(Pieces::PAWN as usize, them);
+
+    let mut score = Score::ZERO;
+    for (piece, bonus) in [(Pieces::KNIGHT, params.knight_outpost), (Pieces::BISHOP, params.bishop_outpost)] {
+        let mut bb = board.get_pieces(piece as usize, color);
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            let rank = relative_rank(color, sq);
+            if !(3..=5).contains(&rank) {
+                continue;
+            }
+            let protected = maps.by_piece[color][Pieces::PAWN as usize] & BB_SQUARES[sq] != EMPTY;
+            let attackable = their_pawns & adjacent_files_bb(sq % 8) & forward_ranks_bb(color, sq / 8) != EMPTY;
+            if protected && !attackable {
+                score += bonus;
+            }
+        }
+    }
+    score
+}
+
+pub fn bishops(params: &EvalParams, board: &Board, color: Color) -> Score {
+    let our_bishops = board.get_pieces(Pieces::BISHOP as usize, color);
+    let our_pawns = board.get_pieces(Pieces::PAWN as usize, color);
+
+    let mut score = Score::ZERO;
+    if our_bishops & DARK_SQUARES != EMPTY && our_bishops & !DARK_SQUARES != EMPTY {
+        score += params.bishop_pair;
+    }
+
+    let mut bb = our_bishops;
+    while bb != EMPTY {
+        let sq = get_lsb(bb);
+        bb &= bb - 1;
+        let same_colour = if BB_SQUARES[sq] & DARK_SQUARES != EMPTY { DARK_SQUARES } else { !DARK_SQUARES };
+        score += params.bad_bishop_pawn * count_bits(our_pawns & same_colour) as i32;
+    }
+    score
+}
+
+// rooks and queens on open and semi-open files, rooks on the 7th rank
+pub fn files_and_ranks(params: &EvalParams, board: &Board, color: Color) -> Score {
+    let them = color ^ 1;
+    let our_pawns = board.get_pieces(Pieces::PAWN as usize, color);
+    let their_pawns = board.get_pieces(Pieces::PAWN as usize, them);
+    let seventh = rank_bb(if color == Colors::WHITE as Color { 6 } else { 1 });
+    let eighth = rank_bb(if color == Colors::WHITE as Color { 7 } else { 0 });
+
+    let mut score = Score::ZERO;
+    for (piece, open, semi_open) in [
+        (Pieces::ROOK, params.rook_open_file, params.rook_semi_open_file),
+        (Pieces::QUEEN, params.queen_open_file, params.queen_semi_open_file),
+    ] {
+        let mut bb = board.get_pieces(piece as usize, color);
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            let file = file_bb(sq % 8);
+            if our_pawns & file == EMPTY {
+                score += if their_pawns & file == EMPTY { open } else { semi_open };
+            }
+        }
+    }
+
+    // only worth something if it cuts off the king or eats pawns
+    let rooks_on_seventh = board.get_pieces(Pieces::ROOK as usize, color) & seventh;
+    if rooks_on_seventh != EMPTY
+        && (their_pawns & seventh != EMPTY || board.get_pieces(Pieces::KING as usize, them) & eighth != EMPTY)
+    {
+        score += params.rook_on_seventh * count_bits(rooks_on_seventh) as i32;
+    }
+    score
+}
+
+pub fn trapped_pieces(params: &EvalParams, board: &Board, maps: &AttackMaps, color: Color) -> Score {
+    let them = color ^ 1;
+    let mut score = Score::ZERO;
+
+    // a rook boxed in by its own uncastled king
+    let ksq = board.king_square(color);
+    let king_file = ksq % 8;
+    let can_castle = if color == Colors::WHITE as Color {
+        board.castling & (Castling::WK | Castling::WQ) != 0
+    } else {
+        board.castling & (Castling::BK | Castling::BQ) != 0
+    };
+    if board.get_pieces(Pieces::KING as usize, color) != EMPTY && relative_rank(color, ksq) == 0 {
+        let area = mobility_area(board, maps, color);
+        let mut bb = board.get_pieces(Pieces::ROOK as usize, color);
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            let rook_file = sq % 8;
+            let mobility = count_bits(maps.from_square[sq] & area);
+            if mobility <= 3 && relative_rank(color, sq) == 0 && (king_file < 4) == (rook_file < king_file) {
+                score += if can_castle { params.trapped_rook } else { params.trapped_rook * 2 };
+            }
+        }
+    }
+
+    // a bishop on a7/h7 (a2/h2 for black) shut in by a pawn on b6/g6 (b3/g3)
+    let their_pawns = board.get_pieces(Pieces::PAWN as usize, them);
+    let mut bb = board.get_pieces(Pieces::BISHOP as usize, color);
+    while bb != EMPTY {
+        let sq = get_lsb(bb);
+        bb &= bb - 1;
+        let file = sq % 8;
+        if relative_rank(color, sq) != 6 || (file != 0 && file != 7) {
+            continue;
+        }
+        let blocker_file = if file == 0 { 1 } else { 6 };
+        let blocker_rank = if color == Colors::WHITE as Color { 5 } else { 2 };
+        if their_pawns & BB_SQUARES[blocker_rank * 8 + blocker_file] != EMPTY {
+            score += params.trapped_bishop;
+        }
+    }
+    score
+}
@@ -0,0 +1,65 @@
+use crate::{
+    board::Board,
+    board::bitboard::{Bitboard, get_lsb},
+    defs::{Color, Colors, NrOf, Pieces, EMPTY},
+    evaluation::piece_attacks,
+    moves::{AttackTable, NOT_A_FILE, NOT_H_FILE},
+};
+
+// full attack maps for both sides, built once per evaluation and shared by
+// king safety, mobility and threats
+pub struct AttackMaps {
+    // attacks of the piece standing on each square, 0 for empty squares
+    pub from_square: [Bitboard; NrOf::SQUARES],
+    // all squares attacked by each piece type of each color
+    pub by_piece: [[Bitboard; NrOf::PIECE_TYPES]; NrOf::COLORS],
+    // all squares attacked by each color
+    pub all: [Bitboard; NrOf::COLORS],
+    // squares attacked at least twice by each color
+    pub double: [Bitboard; NrOf::COLORS],
+}
+
+impl AttackMaps {
+    pub fn new(board: &Board, at: &AttackTable) -> AttackMaps {
+        let mut maps = AttackMaps {
+            from_square: [EMPTY; NrOf::SQUARES],
+            by_piece: [[EMPTY; NrOf::PIECE_TYPES]; NrOf::COLORS],
+            all: [EMPTY; NrOf::COLORS],
+            double: [EMPTY; NrOf::COLORS],
+        };
+        let occ = board.get_all_pieces();
+
+        for color in 0..NrOf::COLORS {
+            // pawns first: two pawns can hit the same square from both sides
+            let pawns = board.get_pieces(Pieces::PAWN as usize, color);
+            let (left, right) = if color == Colors::WHITE as Color {
+                ((pawns & NOT_A_FILE) << 7, (pawns & NOT_H_FILE) << 9)
+            } else {
+                ((pawns & NOT_A_FILE) >> 9, (pawns & NOT_H_FILE) >> 7)
+            };
+            let mut bb = pawns;
+            while bb != EMPTY {
+                let sq = get_lsb(bb);
+                bb &= bb - 1;
+                maps.from_square[sq] = at.pawns[color][sq];
+            }
+            maps.by_piece[color][Pieces::PAWN as usize] = left | right;
+            maps.double[color] = left & right;
+            maps.all[color] = left | right;
+
+            for piece in Pieces::KING as usize..=Pieces::KNIGHT as usize {
+                let mut bb = board.get_pieces(piece, color);
+                while bb != EMPTY {
+                    let sq = get_lsb(bb);
+                    bb &= bb - 1;
+                    let attacks = piece_attacks(at, piece, sq, occ);
+                    maps.from_square[sq] = attacks;
+                    maps.by_piece[color][piece] |= attacks;
+                    maps.double[color] |= maps.all[color] & attacks;
+                    maps.all[color] |= attacks;
+                }
+            }
+        }
+        maps
+    }
+}
@@ -0,0 +1,102 @@
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+use crate::defs::{NrOf, Square, Color, Colors};
+
+// a pair of midgame and endgame values, blended by the game phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+pub const fn s(mg: i32, eg: i32) -> Score {
+    Score { mg, eg }
+}
+
+impl Score {
+    pub const ZERO: Score = s(0, 0);
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, rhs: Score) -> Score {
+        s(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, rhs: Score) -> Score {
+        s(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, rhs: Score) {
+        self.mg += rhs.mg;
+        self.eg += rhs.eg;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, rhs: Score) {
+        self.mg -= rhs.mg;
+        self.eg -= rhs.eg;
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, rhs: i32) -> Score {
+        s(self.mg * rhs, self.eg * rhs)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        s(-self.mg, -self.eg)
+    }
+}
+
+// indexed by Pieces: king, queen, rook, bishop, knight, pawn
+pub const PIECE_VALUES: [Score; NrOf::PIECE_TYPES] = [
+    s(0, 0),
+    s(1025, 936),
+    s(477, 512),
+    s(365, 297),
+    s(337, 281),
+    s(82, 94),
+];
+
+pub const PHASE_WEIGHTS: [i32; NrOf::PIECE_TYPES] = [0, 4, 2, 1, 1, 0];
+pub const TOTAL_PHASE: i32 = 24;
+
+// rank of a square as seen from `color`, 0 is the home rank
+#[inline(always)]
+pub fn relative_rank(color: Color, sq: Square) -> usize {
+    if color == Colors::WHITE as Color { sq / 8 } else { 7 - sq / 8 }
+}
+
+// scores at or beyond this are certain wins found by the endgame evaluators
+pub const KNOWN_WIN: i32 = 10000;
+
+// endgame scale factors, SCALE_NORMAL leaves the endgame score untouched
+pub const SCALE_DRAW: i32 = 0;
+pub const SCALE_NORMAL: i32 = 64;
+
+// chebyshev distance, the number of king moves between two squares
+#[inline(always)]
+pub fn distance(a: Square, b: Square) -> i32 {
+    let file = (a % 8) as i32 - (b % 8) as i32;
+    let rank = (a / 8) as i32 - (b / 8) as i32;
+    file.abs().max(rank.abs())
+}
+
+// number of king moves from `sq` to the nearest edge of the board
+#[inline(always)]
+pub fn edge_distance(sq: Square) -> i32 {
+    let file = (sq % 8) as i32;
+    let rank = (sq / 8) as i32;
+    file.min(7 - file).min(rank.min(7 - rank))
+}
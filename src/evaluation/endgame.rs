@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::{
+    board::Board,
+    board::bitboard::{count_bits, get_lsb},
+    defs::{Color, Colors, NrOf, Pieces, Square, BB_SQUARES, EMPTY},
+    evaluation::{
+        defs::{distance, edge_distance, relative_rank, KNOWN_WIN, PIECE_VALUES, SCALE_DRAW, SCALE_NORMAL},
+        kpk::Kpk,
+    },
+    moves::{A_FILE, DARK_SQUARES, file_bb},
+};
+
+// evaluates a position for the strong side, positive is good for the strong side
+type EndgameFn = fn(&Board, Color) -> i32;
+
+// bonus for the winning king approaching the losing one, by king distance
+const PUSH_CLOSE: [i32; 8] = [0, 0, 100, 80, 60, 40, 20, 10];
+
+// special evaluators keyed by the material of both sides
+pub struct Endgames {
+    evaluators: HashMap<u64, (EndgameFn, Color, &'static str)>,
+    kpk: Kpk,
+}
+
+impl Endgames {
+    pub fn new() -> Endgames {
+        let mut endgames = Endgames {
+            evaluators: HashMap::new(),
+            kpk: Kpk::new(),
+        };
+        endgames.add("KNNK", draw);
+        endgames.add("KBNK", kbnk);
+        endgames.add("KPK", kpk);
+        endgames.add("KRKP", krkp);
+        endgames.add("KQKP", kqkp);
+        endgames
+    }
+
+    // `code` lists the strong side first, e.g. "KBNK"; both colours are registered
+    fn add(&mut self, code: &'static str, f: EndgameFn) {
+        for strong in [Colors::WHITE as Color, Colors::BLACK as Color] {
+            self.evaluators.insert(key_from_code(code, strong), (f, strong, code));
+        }
+    }
+}
+
+pub fn endgames() -> &'static Endgames {
+    static ENDGAMES: OnceLock<Endgames> = OnceLock::new();
+    ENDGAMES.get_or_init(Endgames::new)
+}
+
+// packs the piece counts of both sides, 4 bits per piece type except the king
+pub fn material_key(board: &Board) -> u64 {
+    let mut key = 0;
+    for color in 0..NrOf::COLORS {
+        for piece in Pieces::QUEEN as usize..NrOf::PIECE_TYPES {
+            let count = count_bits(board.get_pieces(piece, color)) as u64;
+            key |= count << material_shift(color, piece);
+        }
+    }
+    key
+}
+
+#[inline(always)]
+fn material_shift(color: Color, piece: usize) -> u64 {
+    (4 * (color * 5 + piece - 1)) as u64
+}
+
+fn key_from_code(code: &str, strong: Color) -> u64 {
+    let weak_start = code[1..].find('K').expect("endgame code needs two kings") + 1;
+    let mut key = 0;
+    for (i, c) in code.chars().enumerate() {
+        let color = if i < weak_start { strong } else { strong ^ 1 };
+        let piece = match c {
+            'Q' => Pieces::QUEEN,
+            'R' => Pieces::ROOK,
+            'B' => Pieces::BISHOP,
+            'N' => Pieces::KNIGHT,
+            'P' => Pieces::PAWN,
+            _ => continue,
+        } as usize;
+        key += 1 << material_shift(color, piece);
+    }
+    key
+}
+
+// score of a recognised endgame from white's point of view and the name of
+// its evaluator, None if the position should go through the normal evaluation
+pub fn probe(board: &Board) -> Option<(i32, &'static str)> {
+    let key = material_key(board);
+    if let Some(&(f, strong, name)) = endgames().evaluators.get(&key) {
+        let v = f(board, strong);
+        return Some((if strong == Colors::WHITE as Color { v } else { -v }, name));
+    }
+
+    // any decisive material against a lone king
+    for strong in [Colors::WHITE as Color, Colors::BLACK as Color] {
+        let weak = strong ^ 1;
+        if board.color[weak] == board.get_pieces(Pieces::KING as usize, weak)
+            && non_pawn_material(board, strong) >= PIECE_VALUES[Pieces::ROOK as usize].eg
+        {
+            let v = kxk(board, strong);
+            return Some((if strong == Colors::WHITE as Color { v } else { -v }, "KXK"));
+        }
+    }
+    None
+}
+
+pub fn non_pawn_material(board: &Board, color: Color) -> i32 {
+    let mut npm = 0;
+    for (piece, value) in PIECE_VALUES.iter().enumerate().take(Pieces::PAWN as usize).skip(Pieces::QUEEN as usize) {
+        npm += value.eg * count_bits(board.get_pieces(piece, color)) as i32;
+    }
+    npm
+}
+
+// drive the lone king to the edge and bring our king closer
+fn kxk(board: &Board, strong: Color) -> i32 {
+    let weak = strong ^ 1;
+    let strong_king = board.king_square(strong);
+    let weak_king = board.king_square(weak);
+
+    let mut v = non_pawn_material(board, strong)
+        + PIECE_VALUES[Pieces::PAWN as usize].eg * count_bits(board.get_pieces(Pieces::PAWN as usize, strong)) as i32
+        + 100 * (3 - edge_distance(weak_king))
+        + PUSH_CLOSE[distance(strong_king, weak_king) as usize];
+
+    let queens_or_rooks = board.get_pieces(Pieces::QUEEN as usize, strong) | board.get_pieces(Pieces::ROOK as usize, strong);
+    let bishops = board.get_pieces(Pieces::BISHOP as usize, strong);
+    let knights = board.get_pieces(Pieces::KNIGHT as usize, strong);
+    let pawns = board.get_pieces(Pieces::PAWN as usize, strong);
+
+    // bishops that all live on one colour can never mate
+    if queens_or_rooks | knights | pawns == EMPTY && (bishops & DARK_SQUARES == EMPTY || bishops & !DARK_SQUARES == EMPTY) {
+        return 0;
+    }
+
+    if queens_or_rooks != EMPTY
+        || (bishops != EMPTY && knights != EMPTY)
+        || (bishops & DARK_SQUARES != EMPTY && bishops & !DARK_SQUARES != EMPTY)
+    {
+        v = (v + KNOWN_WIN).min(2 * KNOWN_WIN - 1);
+    }
+    v
+}
+
+fn draw(_board: &Board, _strong: Color) -> i32 {
+    0
+}
+
+// mate with bishop and knight: only the corners of the bishop's colour work
+fn kbnk(board: &Board, strong: Color) -> i32 {
+    let weak = strong ^ 1;
+    let strong_king = board.king_square(strong);
+    let weak_king = board.king_square(weak);
+    let bishop = get_lsb(board.get_pieces(Pieces::BISHOP as usize, strong));
+
+    // a1 and h8 are dark, a8 and h1 are light
+    let (c1, c2) = if BB_SQUARES[bishop] & DARK_SQUARES != EMPTY { (0, 63) } else { (56, 7) };
+    let corner = distance(weak_king, c1).min(distance(weak_king, c2));
+
+    KNOWN_WIN
+        + PIECE_VALUES[Pieces::BISHOP as usize].eg
+        + PIECE_VALUES[Pieces::KNIGHT as usize].eg
+        + PUSH_CLOSE[distance(strong_king, weak_king) as usize]
+        + 60 * (7 - corner)
+}
+
+// exact result from the KPK bitbase
+fn kpk(board: &Board, strong: Color) -> i32 {
+    let weak = strong ^ 1;
+    let pawn = get_lsb(board.get_pieces(Pieces::PAWN as usize, strong));
+    let strong_to_move = (board.turn as usize ^ 1) == strong;
+
+    let wk = normalize(board, strong, board.king_square(strong));
+    let bk = normalize(board, strong, board.king_square(weak));
+    let psq = normalize(board, strong, pawn);
+    let turn = if strong_to_move { Colors::WHITE as Color } else { Colors::BLACK as Color };
+
+    if !endgames().kpk.probe(wk, psq, bk, turn) {
+        return 0;
+    }
+    KNOWN_WIN + PIECE_VALUES[Pieces::PAWN as usize].eg + (psq / 8) as i32
+}
+
+// rook against pawn, from the classic set of rules of thumb
+fn krkp(board: &Board, strong: Color) -> i32 {
+    let weak = strong ^ 1;
+    let wk = relative_square(strong, board.king_square(strong));
+    let bk = relative_square(strong, board.king_square(weak));
+    let rook = relative_square(strong, get_lsb(board.get_pieces(Pieces::ROOK as usize, strong)));
+    let pawn = relative_square(strong, get_lsb(board.get_pieces(Pieces::PAWN as usize, weak)));
+    let strong_to_move = (board.turn as usize ^ 1) == strong;
+
+    // the weak pawn runs down the board towards rank 1 in these coordinates
+    let queening = pawn % 8;
+    let rook_value = PIECE_VALUES[Pieces::ROOK as usize].eg;
+
+    if wk < pawn && wk % 8 == pawn % 8 {
+        return rook_value - distance(wk, pawn);
+    }
+    if distance(bk, pawn) >= 3 + !strong_to_move as i32 && distance(bk, rook) >= 3 {
+        return rook_value - distance(wk, pawn);
+    }
+    if bk / 8 <= 2 && distance(bk, pawn) == 1 && wk / 8 >= 3 && distance(wk, pawn) > 2 + strong_to_move as i32 {
+        return 80 - 8 * distance(wk, pawn);
+    }
+    let ahead = pawn.saturating_sub(8);
+    200 - 8 * (distance(wk, ahead) - distance(bk, ahead) - distance(pawn, queening))
+}
+
+// queen against pawn: a pawn on the 7th on a bishop or rook file defended by
+// its king is a draw, anything else wins
+fn kqkp(board: &Board, strong: Color) -> i32 {
+    let weak = strong ^ 1;
+    let strong_king = board.king_square(strong);
+    let weak_king = board.king_square(weak);
+    let pawn = get_lsb(board.get_pieces(Pieces::PAWN as usize, weak));
+
+    let mut v = PUSH_CLOSE[distance(strong_king, weak_king) as usize];
+    let drawing_files = file_bb(0) | file_bb(2) | file_bb(5) | file_bb(7);
+    if relative_rank(weak, pawn) != 6 || distance(weak_king, pawn) != 1 || BB_SQUARES[pawn] & drawing_files == EMPTY {
+        v += PIECE_VALUES[Pieces::QUEEN as usize].eg - PIECE_VALUES[Pieces::PAWN as usize].eg;
+    }
+    v
+}
+
+// flips the board so the strong side plays up the board as white
+#[inline(always)]
+fn relative_square(strong: Color, sq: Square) -> Square {
+    if strong == Colors::WHITE as Color { sq } else { sq ^ 56 }
+}
+
+// as relative_square, and mirrors the files so the strong pawn is on files a-d
+fn normalize(board: &Board, strong: Color, sq: Square) -> Square {
+    let pawn = get_lsb(board.get_pieces(Pieces::PAWN as usize, strong));
+    let sq = relative_square(strong, sq);
+    if pawn % 8 >= 4 { sq ^ 7 } else { sq }
+}
+
+// scales down the endgame part of the evaluation in drawish material
+// configurations, `strong` is the side the evaluation favours
+pub fn scale_factor(board: &Board, strong: Color) -> i32 {
+    let weak = strong ^ 1;
+    let pawn = Pieces::PAWN as usize;
+    let bishop = Pieces::BISHOP as usize;
+    let strong_pawns = board.get_pieces(pawn, strong);
+    let strong_npm = non_pawn_material(board, strong);
+    let weak_npm = non_pawn_material(board, weak);
+    let bishop_value = PIECE_VALUES[bishop].eg;
+
+    // without pawns a minor piece more is not enough to win
+    if strong_pawns == EMPTY && strong_npm - weak_npm <= bishop_value {
+        return if strong_npm < PIECE_VALUES[Pieces::ROOK as usize].eg {
+            SCALE_DRAW
+        } else if weak_npm <= bishop_value {
+            4
+        } else {
+            14
+        };
+    }
+
+    // bishop and rook pawns with the wrong coloured bishop
+    if strong_npm == bishop_value && weak_npm == 0 && strong_pawns != EMPTY {
+        let pawn_file = get_lsb(strong_pawns) % 8;
+        if (pawn_file == 0 || pawn_file == 7) && strong_pawns & !(A_FILE << pawn_file) == EMPTY {
+            let queening = if strong == Colors::WHITE as Color { 56 + pawn_file } else { pawn_file };
+            let bishop_sq = get_lsb(board.get_pieces(bishop, strong));
+            let same_colour = (BB_SQUARES[bishop_sq] & DARK_SQUARES != EMPTY) == (BB_SQUARES[queening] & DARK_SQUARES != EMPTY);
+            if !same_colour && distance(board.king_square(weak), queening) <= 1 {
+                return SCALE_DRAW;
+            }
+        }
+    }
+
+    // opposite coloured bishops
+    let strong_bishops = board.get_pieces(bishop, strong);
+    let weak_bishops = board.get_pieces(bishop, weak);
+    if count_bits(strong_bishops) == 1
+        && count_bits(weak_bishops) == 1
+        && (strong_bishops & DARK_SQUARES == EMPTY) != (weak_bishops & DARK_SQUARES == EMPTY)
+    {
+        if strong_npm == bishop_value && weak_npm == bishop_value {
+            let extra_pawns = count_bits(strong_pawns) as i32 - count_bits(board.get_pieces(pawn, weak)) as i32;
+            return (18 + 6 * extra_pawns.max(0)).min(SCALE_NORMAL);
+        }
+        return 46;
+    }
+    SCALE_NORMAL
+}
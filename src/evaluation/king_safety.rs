@@ -0,0 +1,113 @@
+use crate::{
+    board::Board,
+    board::bitboard::{Bitboard, count_bits, get_lsb, get_msb},
+    defs::{Color, Colors, NrOf, Pieces, BB_SQUARES, EMPTY},
+    evaluation::{attacks::AttackMaps, defs::{s, Score, relative_rank}, params::EvalParams},
+    moves::{AttackTable, file_bb, forward_ranks_bb, rank_bb},
+};
+
+// pawn shield, pawn storm and open files on the king file and its neighbours
+pub fn pawn_shelter(params: &EvalParams, board: &Board, color: Color) -> Score {
+    let them = color ^ 1;
+    let ksq = board.king_square(color);
+    let king_file = ksq % 8;
+    let in_front = forward_ranks_bb(color, ksq / 8) | rank_bb(ksq / 8);
+    let our_pawns = board.get_pieces(Pieces::PAWN as usize, color);
+    let their_pawns = board.get_pieces(Pieces::PAWN as usize, them);
+
+    let mut score = Score::ZERO;
+    let center = king_file.clamp(1, 6);
+    for file in center - 1..=center + 1 {
+        let ours = our_pawns & in_front & file_bb(file);
+        let theirs = their_pawns & in_front & file_bb(file);
+        let our_rank = if ours == EMPTY { 0 } else { relative_rank(color, closest(color, ours)) };
+        let their_rank = if theirs == EMPTY { 0 } else { relative_rank(color, closest(color, theirs)) };
+        let edge = file.min(7 - file);
+
+        score.mg += params.shield[edge][our_rank];
+        if our_rank != 0 && our_rank + 1 == their_rank {
+            score -= params.blocked_storm[their_rank];
+        } else {
+            score.mg -= params.storm[edge][their_rank];
+        }
+
+        if our_pawns & file_bb(file) == EMPTY {
+            score += if their_pawns & file_bb(file) == EMPTY { params.king_open_file } else { params.king_semi_open_file };
+        }
+    }
+    score
+}
+
+// attack-unit model: enemy pieces hitting the king zone and safe checks
+pub fn king_attacks(params: &EvalParams, board: &Board, at: &AttackTable, maps: &AttackMaps, color: Color) -> Score {
+    let them = color ^ 1;
+    let zone = king_zone(at, color, board.king_square(color));
+    let checks = safe_checks(board, at, maps, color);
+
+    let mut attackers = 0;
+    let mut units = 0;
+    for piece in [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT] {
+        let p = piece as usize;
+        let mut bb = board.get_pieces(p, them);
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            let attacks = maps.from_square[sq];
+            if attacks & zone != EMPTY {
+                attackers += 1;
+                units += params.attack_units[p] * count_bits(attacks & zone) as i32;
+            }
+        }
+        if checks[p] != EMPTY {
+            units += params.safe_check_units[p];
+        }
+    }
+
+    // a single attacker is only dangerous if it is the queen
+    let has_queen = board.get_pieces(Pieces::QUEEN as usize, them) != EMPTY;
+    if attackers < 2 && !(attackers == 1 && has_queen) {
+        return Score::ZERO;
+    }
+    if !has_queen {
+        units /= 2;
+    }
+
+    let danger = params.safety_table[(units as usize).min(params.safety_table.len() - 1)];
+    s(-danger, -danger / 4)
+}
+
+// squares, per enemy piece type, from which the enemy can check the king of
+// `color` without the checking piece being captured
+pub fn safe_checks(board: &Board, at: &AttackTable, maps: &AttackMaps, color: Color) -> [Bitboard; NrOf::PIECE_TYPES] {
+    let them = color ^ 1;
+    let ksq = board.king_square(color);
+    let occ = board.get_all_pieces();
+    let safe = !maps.all[color] & !board.color[them];
+
+    let bishop_checks = at.get_bishop_attacks(ksq, occ);
+    let rook_checks = at.get_rook_attacks(ksq, occ);
+    let mut checks = [EMPTY; NrOf::PIECE_TYPES];
+    checks[Pieces::QUEEN as usize] = (bishop_checks | rook_checks) & maps.by_piece[them][Pieces::QUEEN as usize];
+    checks[Pieces::ROOK as usize] = rook_checks & maps.by_piece[them][Pieces::ROOK as usize];
+    checks[Pieces::BISHOP as usize] = bishop_checks & maps.by_piece[them][Pieces::BISHOP as usize];
+    checks[Pieces::KNIGHT as usize] = at.knights[ksq] & maps.by_piece[them][Pieces::KNIGHT as usize];
+    for bb in checks.iter_mut() {
+        *bb &= safe;
+    }
+    checks
+}
+
+// the squares around the king plus one more rank towards the enemy
+pub fn king_zone(at: &AttackTable, color: Color, ksq: usize) -> Bitboard {
+    let zone = at.kings[ksq] | BB_SQUARES[ksq];
+    if color == Colors::WHITE as Color {
+        zone | (zone << 8)
+    } else {
+        zone | (zone >> 8)
+    }
+}
+
+#[inline(always)]
+fn closest(color: Color, bb: Bitboard) -> usize {
+    if color == Colors::WHITE as Color { get_lsb(bb) } else { get_msb(bb) }
+}
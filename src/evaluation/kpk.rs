@@ -0,0 +1,135 @@
+use crate::{
+    board::bitboard::{Bitboard, get_lsb},
+    defs::{Color, Colors, Square, BB_SQUARES, EMPTY},
+    evaluation::defs::distance,
+    moves::{king_attacks, pawn_attacks},
+};
+
+// KPK bitbase for the pawn side as white with the pawn on files a-d,
+// indexed by king squares, side to move and pawn square
+const MAX_INDEX: usize = 2 * 24 * 64 * 64;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+pub struct Kpk {
+    wins: Vec<u64>,
+}
+
+impl Kpk {
+    pub fn new() -> Kpk {
+        let mut db = vec![INVALID; MAX_INDEX];
+        for (idx, result) in db.iter_mut().enumerate() {
+            *result = init(idx);
+        }
+
+        // keep resolving positions from their successors until nothing changes
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..MAX_INDEX {
+                if db[idx] == UNKNOWN {
+                    let result = classify(idx, &db);
+                    if result != UNKNOWN {
+                        db[idx] = result;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let mut wins = vec![0u64; MAX_INDEX / 64];
+        for (idx, &result) in db.iter().enumerate() {
+            if result == WIN {
+                wins[idx / 64] |= 1 << (idx % 64);
+            }
+        }
+        Kpk { wins }
+    }
+
+    // the caller normalizes so the pawn side is white with the pawn on files a-d
+    pub fn probe(&self, white_king: Square, pawn: Square, black_king: Square, turn: Color) -> bool {
+        let idx = index(turn, black_king, white_king, pawn);
+        self.wins[idx / 64] & (1 << (idx % 64)) != 0
+    }
+}
+
+#[inline(always)]
+fn index(turn: Color, black_king: Square, white_king: Square, pawn: Square) -> usize {
+    white_king | (black_king << 6) | (turn << 12) | ((pawn % 8) << 13) | ((6 - pawn / 8) << 15)
+}
+
+fn decode(idx: usize) -> (Square, Square, Color, Square) {
+    let white_king = idx & 0x3f;
+    let black_king = (idx >> 6) & 0x3f;
+    let turn = (idx >> 12) & 1;
+    let pawn = (6 - ((idx >> 15) & 7)) * 8 + ((idx >> 13) & 3);
+    (white_king, black_king, turn, pawn)
+}
+
+fn init(idx: usize) -> u8 {
+    let (wk, bk, turn, pawn) = decode(idx);
+    let white = Colors::WHITE as Color;
+    let pawn_attacks_white = pawn_attacks(white, pawn);
+
+    if distance(wk, bk) <= 1
+        || wk == pawn
+        || bk == pawn
+        || (turn == white && pawn_attacks_white & BB_SQUARES[bk] != EMPTY)
+    {
+        return INVALID;
+    }
+
+    // the pawn promotes and the new queen can't be taken
+    if turn == white
+        && pawn / 8 == 6
+        && wk != pawn + 8
+        && bk != pawn + 8
+        && (distance(bk, pawn + 8) > 1 || distance(wk, pawn + 8) == 1)
+    {
+        return WIN;
+    }
+
+    // stalemate, or the black king takes the undefended pawn
+    if turn != white {
+        let escapes: Bitboard = king_attacks(bk) & !(king_attacks(wk) | pawn_attacks_white);
+        if escapes == EMPTY || king_attacks(bk) & BB_SQUARES[pawn] & !king_attacks(wk) != EMPTY {
+            return DRAW;
+        }
+    }
+    UNKNOWN
+}
+
+fn classify(idx: usize, db: &[u8]) -> u8 {
+    let (wk, bk, turn, pawn) = decode(idx);
+    let white = Colors::WHITE as Color;
+    let black = Colors::BLACK as Color;
+    let (good, bad) = if turn == white { (WIN, DRAW) } else { (DRAW, WIN) };
+
+    let mut r = INVALID;
+    let mut moves = king_attacks(if turn == white { wk } else { bk });
+    while moves != EMPTY {
+        let to = get_lsb(moves);
+        moves &= moves - 1;
+        r |= if turn == white { db[index(black, bk, to, pawn)] } else { db[index(white, to, wk, pawn)] };
+    }
+
+    if turn == white {
+        if pawn / 8 < 6 {
+            r |= db[index(black, bk, wk, pawn + 8)];
+        }
+        if pawn / 8 == 1 && pawn + 8 != wk && pawn + 8 != bk {
+            r |= db[index(black, bk, wk, pawn + 16)];
+        }
+    }
+
+    if r & good != 0 {
+        good
+    } else if r & UNKNOWN != 0 {
+        UNKNOWN
+    } else {
+        bad
+    }
+}
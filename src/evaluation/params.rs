@@ -0,0 +1,299 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::slice::Iter;
+
+use crate::evaluation::defs::{s, Score, PIECE_VALUES};
+
+// every weight summed into the tapered score, as one parameter vector for tuning.
+// the specialised endgame evaluators and scale factors are rules, not weights,
+// and stay where they are.
+//
+// parameter file format: one line per field, its name followed by its values
+// flattened in order (mg before eg for scores); missing fields keep their default.
+
+#[derive(Debug)]
+pub enum ParamsError {
+    Io(String),
+    UnknownName(usize, String),
+    BadValue(usize, String),
+    WrongCount { line: usize, expected: usize, found: usize },
+}
+
+impl Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error reading parameter file: {e}"),
+            Self::UnknownName(line, name) => write!(f, "Error in parameter file line {line}: unknown parameter {name}"),
+            Self::BadValue(line, value) => write!(f, "Error in parameter file line {line}: bad value {value}"),
+            Self::WrongCount { line, expected, found } => {
+                write!(f, "Error in parameter file line {line}: expected {expected} values, found {found}")
+            }
+        }
+    }
+}
+
+// a single weight or a table of them
+pub trait Param {
+    fn push_values(&self, out: &mut Vec<i32>);
+    // None when `values` runs out
+    fn set_values(&mut self, values: &mut Iter<i32>) -> Option<()>;
+    fn as_rust(&self) -> String;
+    fn type_name(&self) -> String;
+}
+
+impl Param for i32 {
+    fn push_values(&self, out: &mut Vec<i32>) {
+        out.push(*self);
+    }
+
+    fn set_values(&mut self, values: &mut Iter<i32>) -> Option<()> {
+        *self = *values.next()?;
+        Some(())
+    }
+
+    fn as_rust(&self) -> String {
+        self.to_string()
+    }
+
+    fn type_name(&self) -> String {
+        String::from("i32")
+    }
+}
+
+impl Param for Score {
+    fn push_values(&self, out: &mut Vec<i32>) {
+        out.push(self.mg);
+        out.push(self.eg);
+    }
+
+    fn set_values(&mut self, values: &mut Iter<i32>) -> Option<()> {
+        self.mg = *values.next()?;
+        self.eg = *values.next()?;
+        Some(())
+    }
+
+    fn as_rust(&self) -> String {
+        format!("s({}, {})", self.mg, self.eg)
+    }
+
+    fn type_name(&self) -> String {
+        String::from("Score")
+    }
+}
+
+impl<T: Param, const N: usize> Param for [T; N] {
+    fn push_values(&self, out: &mut Vec<i32>) {
+        self.iter().for_each(|v| v.push_values(out));
+    }
+
+    fn set_values(&mut self, values: &mut Iter<i32>) -> Option<()> {
+        self.iter_mut().try_for_each(|v| v.set_values(values))
+    }
+
+    fn as_rust(&self) -> String {
+        let items: Vec<String> = self.iter().map(Param::as_rust).collect();
+        format!("[{}]", items.join(", "))
+    }
+
+    fn type_name(&self) -> String {
+        format!("[{}; {N}]", self[0].type_name())
+    }
+}
+
+// declares EvalParams, its defaults and name-based access to every field
+macro_rules! eval_params {
+    ($($name:ident: $ty:ty = $value:expr,)*) => {
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct EvalParams {
+            $(pub $name: $ty,)*
+        }
+
+        pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+            $($name: $value,)*
+        };
+
+        impl EvalParams {
+            pub fn fields(&self) -> Vec<(&'static str, &dyn Param)> {
+                vec![$((stringify!($name), &self.$name as &dyn Param),)*]
+            }
+
+            pub fn fields_mut(&mut self) -> Vec<(&'static str, &mut dyn Param)> {
+                vec![$((stringify!($name), &mut self.$name as &mut dyn Param),)*]
+            }
+        }
+    };
+}
+
+impl EvalParams {
+    pub fn to_vector(&self) -> Vec<i32> {
+        let mut out = Vec::new();
+        for (_, field) in self.fields() {
+            field.push_values(&mut out);
+        }
+        out
+    }
+
+    // the inverse of to_vector, which must have produced `values`
+    pub fn from_vector(values: &[i32]) -> EvalParams {
+        let mut params = DEFAULT_PARAMS;
+        let mut iter = values.iter();
+        for (name, field) in params.fields_mut() {
+            field.set_values(&mut iter).unwrap_or_else(|| panic!("parameter vector ends before {name}"));
+        }
+        params
+    }
+
+    // field and flat index within it of each vector entry, for reports
+    pub fn vector_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for (name, field) in self.fields() {
+            let mut values = Vec::new();
+            field.push_values(&mut values);
+            match values.len() {
+                1 => names.push(name.to_string()),
+                n => names.extend((0..n).map(|i| format!("{name}[{i}]"))),
+            }
+        }
+        names
+    }
+
+    pub fn load(path: &str) -> Result<EvalParams, ParamsError> {
+        let text = fs::read_to_string(path).map_err(|e| ParamsError::Io(format!("{path}: {e}")))?;
+        EvalParams::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<EvalParams, ParamsError> {
+        let mut params = DEFAULT_PARAMS;
+        for (n, line) in text.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+            let Some(name) = tokens.next().filter(|t| !t.starts_with('#')) else { continue };
+            let values = tokens
+                .map(|t| t.parse::<i32>().map_err(|_| ParamsError::BadValue(n + 1, t.to_string())))
+                .collect::<Result<Vec<i32>, ParamsError>>()?;
+
+            let mut fields = params.fields_mut();
+            let (_, field) = fields
+                .iter_mut()
+                .find(|(field_name, _)| *field_name == name)
+                .ok_or_else(|| ParamsError::UnknownName(n + 1, name.to_string()))?;
+            let mut expected = Vec::new();
+            field.push_values(&mut expected);
+            if expected.len() != values.len() {
+                return Err(ParamsError::WrongCount { line: n + 1, expected: expected.len(), found: values.len() });
+            }
+            field.set_values(&mut values.iter());
+        }
+        Ok(params)
+    }
+
+    pub fn as_text(&self) -> String {
+        let mut text = String::new();
+        for (name, field) in self.fields() {
+            let mut values = Vec::new();
+            field.push_values(&mut values);
+            let values: Vec<String> = values.iter().map(i32::to_string).collect();
+            text.push_str(&format!("{name} {}\n", values.join(" ")));
+        }
+        text
+    }
+
+    // a replacement for the eval_params! block at the end of this file
+    pub fn as_rust(&self) -> String {
+        let mut text = String::from("eval_params! {\n");
+        for (name, field) in self.fields() {
+            text.push_str(&format!("    {name}: {} = {},\n", field.type_name(), field.as_rust()));
+        }
+        text.push_str("}\n");
+        text
+    }
+}
+
+eval_params! {
+    // indexed by Pieces: king, queen, rook, bishop, knight, pawn
+    piece_values: [Score; 6] = PIECE_VALUES,
+
+    // mobility and piece activity
+    // mobility bonus indexed by the number of reachable squares in the mobility area
+    knight_mobility: [Score; 9] = [
+        s(-62, -81), s(-53, -56), s(-12, -30), s(-4, -14), s(3, 8), s(13, 15),
+        s(22, 23), s(28, 27), s(33, 33),
+    ],
+    bishop_mobility: [Score; 14] = [
+        s(-48, -59), s(-20, -23), s(16, -3), s(26, 13), s(38, 24), s(51, 42), s(55, 54),
+        s(63, 57), s(63, 65), s(68, 73), s(81, 78), s(81, 86), s(91, 88), s(98, 97),
+    ],
+    rook_mobility: [Score; 15] = [
+        s(-58, -76), s(-27, -18), s(-15, 28), s(-10, 55), s(-5, 69), s(-2, 82), s(9, 112),
+        s(16, 118), s(30, 132), s(29, 142), s(32, 155), s(38, 165), s(46, 166), s(48, 169),
+        s(58, 171),
+    ],
+    queen_mobility: [Score; 28] = [
+        s(-39, -36), s(-21, -15), s(3, 8), s(3, 18), s(14, 34), s(22, 54), s(28, 61),
+        s(41, 73), s(43, 79), s(48, 92), s(56, 94), s(60, 104), s(60, 113), s(66, 120),
+        s(67, 123), s(70, 126), s(71, 133), s(73, 136), s(79, 140), s(88, 143), s(88, 148),
+        s(99, 166), s(102, 170), s(102, 175), s(106, 184), s(109, 191), s(113, 206), s(116, 212),
+    ],
+    knight_outpost: Score = s(56, 36),
+    bishop_outpost: Score = s(30, 21),
+    bishop_pair: Score = s(30, 58),
+    // per own pawn standing on the same colour squares as the bishop
+    bad_bishop_pawn: Score = s(-3, -7),
+    rook_open_file: Score = s(48, 25),
+    rook_semi_open_file: Score = s(19, 7),
+    queen_open_file: Score = s(8, 6),
+    queen_semi_open_file: Score = s(4, 4),
+    rook_on_seventh: Score = s(11, 32),
+    trapped_rook: Score = s(-52, -10),
+    trapped_bishop: Score = s(-80, -90),
+    // king safety
+    // bonus for our closest pawn in front of the king, indexed by
+    // [distance of the file from the edge][relative rank of the pawn], 0 = no pawn
+    shield: [[i32; 8]; 4] = [
+        [-6, 81, 93, 58, 39, 18, 25, 0],
+        [-43, 61, 35, -49, -29, -11, -63, 0],
+        [-10, 75, 23, -2, 32, 3, -45, 0],
+        [-39, -13, -29, -52, -48, -67, -166, 0],
+    ],
+    // penalty for an enemy pawn marching at the king that is not blocked by one of ours
+    storm: [[i32; 8]; 4] = [
+        [89, -285, -185, 93, 57, 45, 51, 0],
+        [44, -18, 123, 46, 39, -7, 23, 0],
+        [4, 52, 162, 37, 7, -14, -2, 0],
+        [-10, -14, 90, 15, 2, -7, -16, 0],
+    ],
+    // penalty for an enemy pawn that has run into our shield pawn, by its relative rank
+    blocked_storm: [Score; 8] = [
+        s(0, 0), s(0, 0), s(76, 78), s(-10, 15), s(-7, 10), s(-4, 6), s(-1, 2), s(0, 0),
+    ],
+    king_open_file: Score = s(-42, -8),
+    king_semi_open_file: Score = s(-20, -4),
+    // attack units per attacked king zone square, indexed by Pieces
+    attack_units: [i32; 6] = [0, 5, 3, 2, 2, 0],
+    // attack units for a check that can be given without the checker being captured
+    safe_check_units: [i32; 6] = [0, 6, 5, 3, 4, 0],
+    // attack units are turned into a penalty through a non-linear table, so a lone
+    // attacker does little but a coordinated attack grows quickly
+    safety_table: [i32; 100] = [
+        0, 0, 1, 2, 3, 5, 7, 9, 12, 15,
+        18, 22, 26, 30, 35, 39, 44, 50, 56, 62,
+        68, 75, 82, 85, 89, 97, 105, 113, 122, 131,
+        140, 150, 169, 180, 191, 202, 213, 225, 237, 248,
+        260, 272, 283, 295, 307, 319, 330, 342, 354, 366,
+        377, 389, 401, 412, 424, 436, 448, 459, 471, 483,
+        494, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+        500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    ],
+    // threats
+    // bonus for attacking an enemy piece with a minor or a rook, indexed by the
+    // type of the attacked piece
+    threat_by_minor: [Score; 6] = [s(0, 0), s(62, 120), s(68, 112), s(57, 44), s(39, 42), s(0, 31)],
+    threat_by_rook: [Score; 6] = [s(0, 0), s(51, 38), s(0, 24), s(38, 71), s(38, 61), s(0, 24)],
+    threat_by_king: Score = s(24, 89),
+    threat_by_safe_pawn: Score = s(173, 94),
+    threat_by_pawn_push: Score = s(48, 39),
+    hanging: Score = s(69, 36),
+    // per enemy piece type that can give a safe check
+    safe_check: [Score; 6] = [s(0, 0), s(30, 10), s(34, 12), s(20, 8), s(24, 10), s(0, 0)],
+}
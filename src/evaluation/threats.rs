@@ -0,0 +1,72 @@
+use crate::{
+    board::Board,
+    board::bitboard::{count_bits, get_lsb},
+    defs::{Color, Colors, Pieces, EMPTY},
+    evaluation::{attacks::AttackMaps, king_safety::safe_checks, defs::Score, params::EvalParams},
+    moves::{AttackTable, pawn_attacks_bb, rank_bb},
+};
+
+// threats made by `color` against the enemy, positive is good for `color`
+pub fn threats(params: &EvalParams, board: &Board, at: &AttackTable, maps: &AttackMaps, color: Color) -> Score {
+    let them = color ^ 1;
+    let pawn = Pieces::PAWN as usize;
+    let their_pieces = board.color[them];
+    let non_pawn_enemies = their_pieces & !board.pieces[pawn] & !board.pieces[Pieces::KING as usize];
+
+    // squares the enemy holds with a pawn, or with two pieces against our one
+    let strongly_protected = maps.by_piece[them][pawn] | (maps.double[them] & !maps.double[color]);
+    let defended = non_pawn_enemies & strongly_protected;
+    let weak = their_pieces & !strongly_protected & maps.all[color];
+
+    let mut score = Score::ZERO;
+    if defended | weak != EMPTY {
+        let by_minor = maps.by_piece[color][Pieces::KNIGHT as usize] | maps.by_piece[color][Pieces::BISHOP as usize];
+        let mut bb = (defended | weak) & by_minor;
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            score += params.threat_by_minor[board.piece_on(sq)];
+        }
+
+        let mut bb = weak & maps.by_piece[color][Pieces::ROOK as usize];
+        while bb != EMPTY {
+            let sq = get_lsb(bb);
+            bb &= bb - 1;
+            score += params.threat_by_rook[board.piece_on(sq)];
+        }
+
+        if weak & maps.by_piece[color][Pieces::KING as usize] != EMPTY {
+            score += params.threat_by_king;
+        }
+
+        // undefended, or a piece we attack twice that the enemy covers only once
+        let hanging = weak & (!maps.all[them] | (non_pawn_enemies & maps.double[color]));
+        score += params.hanging * count_bits(hanging) as i32;
+    }
+
+    // pieces attacked by pawns that cannot be taken for free
+    let our_pawns = board.get_pieces(pawn, color);
+    let safe = !maps.all[them] | maps.all[color];
+    let safe_pawns = our_pawns & safe;
+    score += params.threat_by_safe_pawn * count_bits(pawn_attacks_bb(safe_pawns, color) & non_pawn_enemies) as i32;
+
+    // pieces that a safe pawn push would attack
+    let empty = !board.get_all_pieces();
+    let white = color == Colors::WHITE as Color;
+    let third = rank_bb(if white { 2 } else { 5 });
+    let mut pushes = (if white { our_pawns << 8 } else { our_pawns >> 8 }) & empty;
+    pushes |= (if white { (pushes & third) << 8 } else { (pushes & third) >> 8 }) & empty;
+    pushes &= !maps.by_piece[them][pawn] & safe;
+    score += params.threat_by_pawn_push * count_bits(pawn_attacks_bb(pushes, color) & non_pawn_enemies) as i32;
+
+    if board.get_pieces(Pieces::KING as usize, them) != EMPTY {
+        let checks = safe_checks(board, at, maps, them);
+        for piece in [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT] {
+            let p = piece as usize;
+            if checks[p] != EMPTY {
+                score += params.safe_check[p];
+            }
+        }
+    }
+    score
+}
@@ -0,0 +1,102 @@
+use crate::{
+    board::Board,
+    defs::{Color, Colors, NrOf, Pieces, Square},
+    evaluation::{
+        attacks::AttackMaps,
+        defs::{Score, SCALE_NORMAL, TOTAL_PHASE},
+        endgame::{self, scale_factor},
+        params::EvalParams,
+        eval_terms, evaluate_white, game_phase, TERM_COUNT, TERM_NAMES,
+    },
+    moves::AttackTable,
+};
+
+// prints every evaluation term per side, the blend and a per-square grid of
+// what each piece is worth to the evaluation
+pub fn print_trace(params: &EvalParams, board: &Board, at: &AttackTable) {
+    let white = Colors::WHITE as Color;
+    let black = Colors::BLACK as Color;
+
+    println!();
+    println!("{:>18} | {:^15} | {:^15} | {:^15}", "Term", "White", "Black", "Total");
+    println!("{:>18} | {:>7} {:>7} | {:>7} {:>7} | {:>7} {:>7}", "", "MG", "EG", "MG", "EG", "MG", "EG");
+    println!("{:-<18}-+-{:-<15}-+-{:-<15}-+-{:-<15}", "", "", "", "");
+
+    let maps = AttackMaps::new(board, at);
+    let white_terms = eval_terms(params, board, at, &maps, white);
+    let black_terms = eval_terms(params, board, at, &maps, black);
+    let mut total = Score::ZERO;
+    for i in 0..TERM_COUNT {
+        let diff = white_terms[i] - black_terms[i];
+        total += diff;
+        println!(
+            "{:>18} | {} | {} | {}",
+            TERM_NAMES[i],
+            format_score(white_terms[i]),
+            format_score(black_terms[i]),
+            format_score(diff)
+        );
+    }
+    println!("{:-<18}-+-{:-<15}-+-{:-<15}-+-{:-<15}", "", "", "", "");
+    println!("{:>18} | {:>15} | {:>15} | {}", "Total", "", "", format_score(total));
+    println!();
+
+    let phase = game_phase(board);
+    let strong = if total.eg > 0 { white } else { black };
+    println!("Phase: {}/{}", phase, TOTAL_PHASE);
+    println!("Scale factor: {}/{} (for {})", scale_factor(board, strong), SCALE_NORMAL, Colors::from_index(strong).as_string());
+    if let Some((v, name)) = endgame::probe(board) {
+        println!("Special endgame: {} ({})", name, format_pawns(v));
+    }
+
+    let v = evaluate_white(params, board, at);
+    println!("Final evaluation: {} (white side)", format_pawns(v));
+    println!();
+
+    print_piece_grid(params, board, at, v);
+}
+
+// each piece's contribution as the change in evaluation when it is removed
+fn print_piece_grid(params: &EvalParams, board: &Board, at: &AttackTable, base: i32) {
+    let white = Colors::WHITE as Color;
+    let black = Colors::BLACK as Color;
+    println!();
+    for r in 0..NrOf::RANKS {
+        let rank = 7 - r;
+        print!("{} ", 8 - r);
+        for file in 0..NrOf::FILES {
+            let sq: Square = rank * 8 + file;
+            let piece = board.piece_on(sq);
+            if piece == Pieces::NONE as usize {
+                print!("{:^8}", "_");
+                continue;
+            }
+            let color = if board.color[Colors::WHITE as Color] & (1 << sq) != 0 { white } else { black };
+            let mut letter = Pieces::from_index(piece).as_char();
+            if color == black {
+                letter = letter.to_ascii_lowercase();
+            }
+            if piece == Pieces::KING as usize {
+                print!("{:^8}", letter);
+                continue;
+            }
+            let mut without = *board;
+            without.remove_piece(piece, sq, color);
+            print!("{:^8}", format!("{}{:+.1}", letter, (base - evaluate_white(params, &without, at)) as f64 / 100.0));
+        }
+        println!();
+    }
+    print!("  ");
+    for file in ["a", "b", "c", "d", "e", "f", "g", "h"] {
+        print!("{:^8}", file);
+    }
+    println!();
+}
+
+fn format_score(score: Score) -> String {
+    format!("{:>7} {:>7}", format_pawns(score.mg), format_pawns(score.eg))
+}
+
+fn format_pawns(v: i32) -> String {
+    format!("{:+.2}", v as f64 / 100.0)
+}
@@ -0,0 +1,360 @@
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::thread;
+use std::time::Instant;
+
+use crate::{
+    board::Board,
+    datagen::packed::{is_packed, read_records, PackedError, Record},
+    evaluation::{
+        endgame,
+        evaluate_white,
+        params::{EvalParams, ParamsError, DEFAULT_PARAMS},
+    },
+    moves::AttackTable,
+};
+
+// texel tuning: every weight in EvalParams is fitted so that a sigmoid of the
+// static evaluation predicts the game results of a set of quiet positions
+
+#[derive(Debug)]
+pub enum TuneError {
+    Io(String),
+    BadOption(String),
+    BadPosition(usize, String),
+    NoData,
+    Params(ParamsError),
+    Data(PackedError),
+}
+
+impl Display for TuneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error reading tuning data: {e}"),
+            Self::BadOption(o) => write!(f, "Error in tune options: {o}"),
+            Self::BadPosition(n, e) => write!(f, "Error in tuning data line {n}: {e}"),
+            Self::NoData => write!(f, "Error in tuning data: no positions"),
+            Self::Params(e) => write!(f, "{e}"),
+            Self::Data(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+pub struct TuneOptions {
+    pub data: String,
+    pub output: String,
+    pub rust: Option<String>,
+    // parameter file to start from instead of the built-in weights
+    pub start: Option<String>,
+    // sigmoid scaling constant, fitted to the starting weights when not given
+    pub k: Option<f64>,
+    pub passes: usize,
+    pub threads: usize,
+}
+
+impl Default for TuneOptions {
+    fn default() -> TuneOptions {
+        TuneOptions {
+            data: String::new(),
+            output: String::from("params.txt"),
+            rust: None,
+            start: None,
+            k: None,
+            passes: 100,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        }
+    }
+}
+
+impl TuneOptions {
+    // rustChess tune <data> [--out file] [--rust file] [--params file] [--k f] [--passes n] [--threads n]
+    pub fn parse(args: &[String]) -> Result<TuneOptions, TuneError> {
+        let mut options = TuneOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                options.data = arg.clone();
+                continue;
+            }
+            let value = iter.next().ok_or_else(|| TuneError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || TuneError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--out" => options.output = value.clone(),
+                "--rust" => options.rust = Some(value.clone()),
+                "--params" => options.start = Some(value.clone()),
+                "--k" => options.k = Some(value.parse().map_err(|_| bad())?),
+                "--passes" => options.passes = value.parse().map_err(|_| bad())?,
+                "--threads" => options.threads = value.parse().map_err(|_| bad())?,
+                _ => return Err(TuneError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.data.is_empty() {
+            return Err(TuneError::BadOption(String::from("no tuning data given")));
+        }
+        if options.threads == 0 {
+            return Err(TuneError::BadOption(String::from("threads must be positive")));
+        }
+        Ok(options)
+    }
+}
+
+// a position with the result of its game from white's point of view
+struct Position {
+    board: Board,
+    result: f64,
+}
+
+// quiet positions from an EPD file with the result in a c9 opcode or as a
+// trailing token ("1-0", "0-1", "1/2-1/2", "[1.0]", "[0.5]", "[0.0]"), or from
+// datagen text or packed files. positions decided by a special endgame
+// evaluator are skipped since no weight changes their score
+fn load_positions(path: &str) -> Result<Vec<Position>, TuneError> {
+    let mut positions = Vec::new();
+    if is_packed(path) {
+        let records = read_records(path).map_err(TuneError::Data)?;
+        positions.extend(records.into_iter().map(|r| Position { board: r.board, result: r.result as f64 }));
+    } else {
+        let file = File::open(path).map_err(|e| TuneError::Io(format!("{path}: {e}")))?;
+        for (n, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| TuneError::Io(format!("{path}: {e}")))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            positions.push(parse_position(&line).map_err(|e| TuneError::BadPosition(n + 1, e))?);
+        }
+    }
+    positions.retain(|p| endgame::probe(&p.board).is_none());
+    if positions.is_empty() {
+        return Err(TuneError::NoData);
+    }
+    Ok(positions)
+}
+
+fn parse_position(line: &str) -> Result<Position, String> {
+    if line.contains('|') {
+        let record = Record::from_text(line)?;
+        return Ok(Position { board: record.board, result: record.result as f64 });
+    }
+
+    let result = line
+        .split(|c: char| c.is_whitespace() || c == ';' || c == '"')
+        .rev()
+        .find_map(|t| match t.trim_matches(|c| c == '[' || c == ']') {
+            "1-0" | "1.0" => Some(1.0),
+            "0-1" | "0.0" => Some(0.0),
+            "1/2-1/2" | "0.5" => Some(0.5),
+            _ => None,
+        })
+        .ok_or("no game result")?;
+
+    // EPD has no move counters, a full FEN may have them
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(String::from("expected a FEN or EPD position"));
+    }
+    let counters = fields.get(4..6).filter(|c| c.iter().all(|t| t.parse::<u16>().is_ok()));
+    let fen = match counters {
+        Some(c) => format!("{} {}", fields[..4].join(" "), c.join(" ")),
+        None => format!("{} 0 1", fields[..4].join(" ")),
+    };
+    let mut board = Board::new();
+    board.parse_fen(Some(&fen)).map_err(|e| e.to_string())?;
+    Ok(Position { board, result })
+}
+
+#[inline(always)]
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+// evaluations only depend on the weights, so they are computed once per
+// weight vector and shared by the K scan
+fn evaluations(at: &AttackTable, params: &EvalParams, positions: &[Position], threads: usize) -> Vec<i32> {
+    let chunk = positions.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk)
+            .map(|part| scope.spawn(move || part.iter().map(|p| evaluate_white(params, &p.board, at)).collect::<Vec<i32>>()))
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+    })
+}
+
+fn error_of(k: f64, evals: &[i32], positions: &[Position]) -> f64 {
+    let total: f64 = evals.iter().zip(positions).map(|(&e, p)| (p.result - sigmoid(k, e as f64)).powi(2)).sum();
+    total / positions.len() as f64
+}
+
+fn mean_error(at: &AttackTable, k: f64, params: &EvalParams, positions: &[Position], threads: usize) -> f64 {
+    error_of(k, &evaluations(at, params, positions, threads), positions)
+}
+
+// K minimising the error of the given evaluations, by a scan refined three times
+fn fit_k(evals: &[i32], positions: &[Position]) -> f64 {
+    let (mut lo, mut hi, mut step) = (0.0, 3.0, 0.1);
+    let mut best = 1.0;
+    for _ in 0..4 {
+        let mut best_error = f64::MAX;
+        let mut k = lo;
+        while k <= hi + 1e-9 {
+            let error = error_of(k, evals, positions);
+            if error < best_error {
+                best_error = error;
+                best = k;
+            }
+            k += step;
+        }
+        lo = (best - step).max(0.0);
+        hi = best + step;
+        step /= 10.0;
+    }
+    best
+}
+
+fn save(options: &TuneOptions, params: &EvalParams) -> Result<(), TuneError> {
+    fs::write(&options.output, params.as_text()).map_err(|e| TuneError::Io(format!("{}: {e}", options.output)))?;
+    if let Some(path) = &options.rust {
+        fs::write(path, params.as_rust()).map_err(|e| TuneError::Io(format!("{path}: {e}")))?;
+    }
+    Ok(())
+}
+
+// local search: every weight is moved by one in either direction for as long
+// as that lowers the error, until a pass over all of them changes nothing
+pub fn tune(at: &AttackTable, options: &TuneOptions) -> Result<(), TuneError> {
+    let started = Instant::now();
+    let positions = load_positions(&options.data)?;
+    let start = match &options.start {
+        Some(path) => EvalParams::load(path).map_err(TuneError::Params)?,
+        None => DEFAULT_PARAMS,
+    };
+    println!("Loaded {} positions", positions.len());
+
+    let k = match options.k {
+        Some(k) => k,
+        None => fit_k(&evaluations(at, &start, &positions, options.threads), &positions),
+    };
+    let mut values = start.to_vector();
+    let names = start.vector_names();
+    let mut best_error = mean_error(at, k, &start, &positions, options.threads);
+    println!("K {k:.3}, {} weights, error {best_error:.6}", values.len());
+
+    for pass in 1..=options.passes {
+        let mut changed = 0;
+        for i in 0..values.len() {
+            for delta in [1, -1] {
+                values[i] += delta;
+                let error = mean_error(at, k, &EvalParams::from_vector(&values), &positions, options.threads);
+                if error < best_error {
+                    best_error = error;
+                    changed += 1;
+                    break;
+                }
+                values[i] -= delta;
+            }
+        }
+
+        let params = EvalParams::from_vector(&values);
+        save(options, &params)?;
+        println!(
+            "Pass {pass}: error {best_error:.6}, {changed} weights changed, {}s",
+            started.elapsed().as_secs()
+        );
+        if changed == 0 {
+            break;
+        }
+    }
+
+    // what moved the most, for a quick look at the result
+    let initial = start.to_vector();
+    let mut moved: Vec<usize> = (0..values.len()).filter(|&i| values[i] != initial[i]).collect();
+    moved.sort_by_key(|&i| -(values[i] - initial[i]).abs());
+    for &i in moved.iter().take(10) {
+        println!("{:>24} {:>5} -> {:>5}", names[i], initial[i], values[i]);
+    }
+    println!("Weights written to {}", options.output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        defs::Pieces,
+        evaluation::{defs::TOTAL_PHASE, game_phase},
+        moves::with_table,
+    };
+
+    // results are made up; they only need to disagree with the evaluations
+    const LINES: [&str; 5] = [
+        "r1b1kb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 0 5 0.5",
+        "rnbqkb1r/pppp1ppp/5n2/4p3/4P3/5N2/PPPP1PPP/RNB1KB1R b KQkq - 1 3 1.0",
+        "rnb1kbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNB1KBNR w KQkq - 0 3 0.0",
+        "4k3/pp3ppp/8/8/8/8/PPP2PPP/3QK3 w - - 0 1 0.0",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10 1/2-1/2",
+    ];
+
+    fn positions() -> Vec<Position> {
+        LINES.iter().map(|line| parse_position(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn params_survive_the_vector_round_trip() {
+        with_table(|at| {
+            let values = DEFAULT_PARAMS.to_vector();
+            assert_eq!(values.len(), DEFAULT_PARAMS.vector_names().len());
+            let read = EvalParams::from_vector(&values);
+            assert_eq!(read, DEFAULT_PARAMS);
+            assert_eq!(EvalParams::parse(&read.as_text()).unwrap(), DEFAULT_PARAMS);
+            for p in positions() {
+                assert_eq!(evaluate_white(&read, &p.board, at), evaluate_white(&DEFAULT_PARAMS, &p.board, at));
+            }
+
+            // a step of the tuner lands on the weight it names and nowhere else
+            let queen_mg = DEFAULT_PARAMS.vector_names().iter().position(|n| n == "piece_values[2]").unwrap();
+            let mut tuned = values.clone();
+            tuned[queen_mg] += 7;
+            let params = EvalParams::from_vector(&tuned);
+            assert_eq!(params.piece_values[1].mg, DEFAULT_PARAMS.piece_values[1].mg + 7);
+            assert_eq!(params.to_vector(), tuned);
+        });
+    }
+
+    // the material terms are linear in the queen weights, so the derivative of
+    // the mean error with respect to them can be written down directly
+    #[test]
+    fn error_gradient_matches_finite_differences() {
+        with_table(|at| {
+            let positions = positions();
+            let names = DEFAULT_PARAMS.vector_names();
+            let k = 1.2;
+            let evals = evaluations(at, &DEFAULT_PARAMS, &positions, 2);
+            for (name, midgame) in [("piece_values[2]", true), ("piece_values[3]", false)] {
+                let i = names.iter().position(|n| n == name).unwrap();
+                let analytic: f64 = evals
+                    .iter()
+                    .zip(&positions)
+                    .map(|(&e, p)| {
+                        let queens = |c| p.board.get_pieces(Pieces::QUEEN as usize, c).count_ones() as f64;
+                        let phase = game_phase(&p.board) as f64 / TOTAL_PHASE as f64;
+                        let d_eval = (queens(0) - queens(1)) * if midgame { phase } else { 1.0 - phase };
+                        let s = sigmoid(k, e as f64);
+                        -2.0 * (p.result - s) * s * (1.0 - s) * k * 10f64.ln() / 400.0 * d_eval
+                    })
+                    .sum::<f64>()
+                    / positions.len() as f64;
+
+                let h = 50;
+                let error_with = |delta: i32| {
+                    let mut values = DEFAULT_PARAMS.to_vector();
+                    values[i] += delta;
+                    mean_error(at, k, &EvalParams::from_vector(&values), &positions, 2)
+                };
+                let numeric = (error_with(h) - error_with(-h)) / (2 * h) as f64;
+                assert!(analytic.abs() > 1e-6, "{name} has no gradient on these positions");
+                assert!((numeric - analytic).abs() <= 0.05 * analytic.abs(), "{name}: analytic {analytic} numeric {numeric}");
+            }
+        });
+    }
+}
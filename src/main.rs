@@ -1,33 +1,170 @@
+
+mod arena;
 mod board;
+mod book;
+mod datagen;
 mod defs;
 mod moves;
+mod movegen;
+mod nnue;
+mod pgn;
 mod sorcery;
 mod magics;
+mod evaluation;
+mod rng;
+mod search;
+mod spsa;
+mod testsuite;
+mod tournament;
+mod uci;
 
-use defs::{Square, Colors, BB_SQUARES, ALL_SQUARES};
+use std::env;
 
 use crate::{
-    board::bitboard::{Bitboard, set_bit, get_bit, get_lsb, count_bits, print_bitboard, pop_bit},
-    moves::{AttackTable, NOT_EIGHTH_RANK, NOT_FIRST_RANK},
-    sorcery::{Sorcerer},
+    arena::{run_match, MatchOptions},
+    board::{fen::chess960_fen, Board},
+    book::builder::{dumpbook, makebook, mergebook, MakeBookOptions},
+    datagen::{datagen, packed, DatagenOptions},
+    evaluation::{params::DEFAULT_PARAMS, trace::print_trace, tuner::{self, TuneOptions}},
+    movegen::perft,
+    moves::AttackTable,
+    nnue::trainer::{self, TrainOptions},
+    spsa::{spsa, SpsaOptions},
+    testsuite::{testsuite, TestSuiteOptions},
+    tournament::{tournament, TournamentOptions},
 };
 
-
 fn main() {
     let attack_table = AttackTable::new();
-    let mut occ: Bitboard = 0;
-    set_bit(&mut occ, ALL_SQUARES::C5 as usize);
-    set_bit(&mut occ, ALL_SQUARES::F2 as usize);
-    set_bit(&mut occ, ALL_SQUARES::G7 as usize);
-    set_bit(&mut occ, ALL_SQUARES::H8 as usize);
-    set_bit(&mut occ, ALL_SQUARES::B2 as usize);
-    set_bit(&mut occ, ALL_SQUARES::G5 as usize);
-    set_bit(&mut occ, ALL_SQUARES::E2 as usize);
-    set_bit(&mut occ, ALL_SQUARES::E7 as usize);
+    let args: Vec<String> = env::args().collect();
 
-    print_bitboard(occ);
-
-    print_bitboard(attack_table.get_bishop_attacks(ALL_SQUARES::D4 as Square, occ));
-    print_bitboard(attack_table.get_rook_attacks(ALL_SQUARES::E5 as Square, occ));
+    match args.get(1).map(String::as_str) {
+        // rustChess eval [fen]
+        Some("eval") => {
+            let fen = args[2..].join(" ");
+            let mut board = Board::new();
+            let parsed = if fen.is_empty() { board.parse_fen(None) } else { board.parse_fen(Some(&fen)) };
+            match parsed {
+                Ok(()) => print_trace(&DEFAULT_PARAMS, &board, &attack_table),
+                Err(e) => println!("{e}"),
+            }
+        }
+        // rustChess perft <depth> [fen]
+        Some("perft") => {
+            let depth = args.get(2).and_then(|d| d.parse::<u8>().ok()).unwrap_or(1);
+            let fen = args.get(3..).map(|f| f.join(" ")).unwrap_or_default();
+            let mut board = Board::new();
+            let parsed = if fen.is_empty() { board.parse_fen(None) } else { board.parse_fen(Some(&fen)) };
+            match parsed {
+                Ok(()) => println!("{}", perft(&board, &attack_table, depth)),
+                Err(e) => println!("{e}"),
+            }
+        }
+        // rustChess chess960 <n>: the start position with Scharnagl number n
+        Some("chess960") => match args.get(2).and_then(|n| n.parse().ok()).and_then(chess960_fen) {
+            Some(fen) => println!("{fen}"),
+            None => println!("Error in chess960: Scharnagl numbers go from 0 to 959"),
+        },
+        // rustChess train <data> [options]
+        Some("train") => {
+            let result = TrainOptions::parse(&args[2..]).and_then(|options| trainer::train(&options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess tune <data> [options]
+        Some("tune") => {
+            let result = TuneOptions::parse(&args[2..]).and_then(|options| tuner::tune(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess spsa [options]
+        Some("spsa") => {
+            let result = SpsaOptions::parse(&args[2..]).and_then(|options| spsa(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess match [options]
+        Some("match") => {
+            let result = MatchOptions::parse(&args[2..]).and_then(|options| run_match(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess tournament --engine <spec> --engine <spec> [options]
+        Some("tournament") => {
+            let result = TournamentOptions::parse(&args[2..]).and_then(|options| tournament(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess testsuite <file.epd> [options]
+        Some("testsuite") => {
+            let result = TestSuiteOptions::parse(&args[2..]).and_then(|options| testsuite(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess makebook <file.pgn>... [options]
+        Some("makebook") => {
+            let result = MakeBookOptions::parse(&args[2..]).and_then(|options| makebook(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess mergebook <input>... <output>: earlier books take priority
+        Some("mergebook") if args.len() >= 5 => match mergebook(&args[2..args.len() - 1], &args[args.len() - 1]) {
+            Ok(n) => println!("Wrote {n} entries"),
+            Err(e) => println!("{e}"),
+        },
+        // rustChess dumpbook <book> [fen]
+        Some("dumpbook") if args.len() >= 3 => {
+            if let Err(e) = dumpbook(&attack_table, &args[2], &args[3..].join(" ")) {
+                println!("{e}");
+            }
+        }
+        // rustChess datagen [options]
+        Some("datagen") => {
+            let result = DatagenOptions::parse(&args[2..]).and_then(|options| datagen(&attack_table, &options));
+            if let Err(e) = result {
+                println!("{e}");
+            }
+        }
+        // rustChess convert <input> <output>: text to packed or back
+        Some("convert") if args.len() == 4 => match packed::convert(&args[2], &args[3]) {
+            Ok(n) => println!("Converted {n} positions"),
+            Err(e) => println!("{e}"),
+        },
+        // rustChess shuffle <input> <output> [seed]
+        Some("shuffle") if args.len() == 4 || (args.len() == 5 && args[4].parse::<u64>().is_ok()) => {
+            let seed = args.get(4).map_or(1, |s| s.parse().unwrap_or(1));
+            match packed::shuffle(&args[2], &args[3], seed) {
+                Ok(n) => println!("Shuffled {n} positions"),
+                Err(e) => println!("{e}"),
+            }
+        }
+        // rustChess dedup <input> <output>
+        Some("dedup") if args.len() == 4 => match packed::dedup(&args[2], &args[3]) {
+            Ok((kept, read)) => println!("Kept {kept} of {read} positions"),
+            Err(e) => println!("{e}"),
+        },
+        // one of the commands above with the wrong arguments
+        Some(command @ ("mergebook" | "dumpbook" | "convert" | "shuffle" | "dedup")) => {
+            println!("Error in {command}: usage rustChess {command} {}", usage(command))
+        }
+        _ => uci::uci_loop(&attack_table),
+    }
+}
 
+fn usage(command: &str) -> &'static str {
+    match command {
+        "mergebook" => "<input> <input>... <output>",
+        "dumpbook" => "<book> [fen]",
+        "convert" => "<input> <output>",
+        "shuffle" => "<input> <output> [seed]",
+        "dedup" => "<input> <output>",
+        _ => "",
+    }
 }
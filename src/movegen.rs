@@ -0,0 +1,478 @@
+pub mod defs;
+pub mod san;
+
+use crate::{
+    board::Board,
+    board::bitboard::{Bitboard, get_lsb},
+    defs::{Castling, Color, Colors, Piece, Pieces, Square, Variant, BB_SQUARES, EMPTY},
+    moves::{AttackTable, rank_bb},
+};
+
+use self::defs::{square_name, Move, MoveList, MoveType};
+
+const PROMOTIONS: [Pieces; 4] = [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT];
+
+// pseudo-legal moves: the king of the side to move may be left in check
+pub fn generate_moves(board: &Board, at: &AttackTable, mt: MoveType, list: &mut MoveList) {
+    let us = board.side_to_move();
+    let them = us ^ 1;
+    let occ = board.get_all_pieces();
+    let targets = match mt {
+        MoveType::All => !board.color[us],
+        MoveType::Captures => board.color[them],
+    };
+    // atomic: the game is over once a king is blown up, and a king can't
+    // capture as it would blow itself up
+    let mut king_targets = targets;
+    if board.variant == Variant::Atomic {
+        if board.get_pieces(Pieces::KING as usize, us) == EMPTY || board.get_pieces(Pieces::KING as usize, them) == EMPTY {
+            return;
+        }
+        king_targets &= !board.color[them];
+    }
+
+    for piece in [Pieces::KING, Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT] {
+        let p = piece as usize;
+        let mut bb = board.get_pieces(p, us);
+        while bb != EMPTY {
+            let from = get_lsb(bb);
+            bb &= bb - 1;
+            let attacks = match p {
+                x if x == Pieces::KING as usize => at.kings[from] & king_targets,
+                x if x == Pieces::QUEEN as usize => at.get_bishop_attacks(from, occ) | at.get_rook_attacks(from, occ),
+                x if x == Pieces::ROOK as usize => at.get_rook_attacks(from, occ),
+                x if x == Pieces::BISHOP as usize => at.get_bishop_attacks(from, occ),
+                _ => at.knights[from],
+            };
+            add_moves(board, p, from, attacks & targets, list);
+        }
+    }
+
+    pawn_moves(board, at, mt, list);
+    if mt == MoveType::All {
+        castling_moves(board, at, list);
+        if board.variant == Variant::Crazyhouse {
+            drop_moves(board, list);
+        }
+    }
+}
+
+fn add_moves(board: &Board, piece: Piece, from: Square, mut targets: Bitboard, list: &mut MoveList) {
+    while targets != EMPTY {
+        let to = get_lsb(targets);
+        targets &= targets - 1;
+        list.push(Move::new(piece, from, to, board.piece_on(to), Pieces::NONE as Piece));
+    }
+}
+
+fn pawn_moves(board: &Board, at: &AttackTable, mt: MoveType, list: &mut MoveList) {
+    let us = board.side_to_move();
+    let them = us ^ 1;
+    let white = us == Colors::WHITE as Color;
+    let pawn = Pieces::PAWN as Piece;
+    let empty = !board.get_all_pieces();
+    let promotion_rank = rank_bb(if white { 7 } else { 0 });
+    let double_rank = rank_bb(if white { 3 } else { 4 });
+
+    let mut bb = board.get_pieces(pawn, us);
+    while bb != EMPTY {
+        let from = get_lsb(bb);
+        bb &= bb - 1;
+
+        let push = if white { BB_SQUARES[from] << 8 } else { BB_SQUARES[from] >> 8 } & empty;
+        let double = if white { push << 8 } else { push >> 8 } & empty & double_rank;
+        let captures = at.pawns[us][from] & board.color[them];
+
+        let mut targets = captures;
+        if mt == MoveType::All {
+            targets |= push | double;
+        } else {
+            // queen promotions are noisy enough to belong with the captures
+            targets |= push & promotion_rank;
+        }
+
+        while targets != EMPTY {
+            let to = get_lsb(targets);
+            targets &= targets - 1;
+            let captured = board.piece_on(to);
+            if BB_SQUARES[to] & promotion_rank != EMPTY {
+                for promotion in PROMOTIONS {
+                    if mt == MoveType::Captures && captured == Pieces::NONE as Piece && promotion as Piece != Pieces::QUEEN as Piece {
+                        continue;
+                    }
+                    list.push(Move::new(pawn, from, to, captured, promotion as Piece));
+                }
+            } else if BB_SQUARES[to] & double != EMPTY {
+                list.push(Move::new(pawn, from, to, captured, Pieces::NONE as Piece).with_double_step());
+            } else {
+                list.push(Move::new(pawn, from, to, captured, Pieces::NONE as Piece));
+            }
+        }
+
+        if let Some(ep) = board.en_passant {
+            let ep = ep as Square;
+            if at.pawns[us][from] & BB_SQUARES[ep] != EMPTY {
+                list.push(Move::new(pawn, from, ep, pawn, Pieces::NONE as Piece).with_en_passant());
+            }
+        }
+    }
+}
+
+fn castling_moves(board: &Board, at: &AttackTable, list: &mut MoveList) {
+    let us = board.side_to_move();
+    let them = us ^ 1;
+    let occ = board.get_all_pieces();
+    let king = Pieces::KING as Piece;
+
+    let (kingside, queenside) = if us == Colors::WHITE as Color { (Castling::WK, Castling::WQ) } else { (Castling::BK, Castling::BQ) };
+    if board.castling & (kingside | queenside) == 0 {
+        return;
+    }
+    if in_check(board, at) {
+        return;
+    }
+    let king_from = board.king_square(us);
+    let back_rank = king_from - king_from % 8;
+    // atomic: the king may pass next to the enemy king whatever attacks it there
+    let touching = if board.variant == Variant::Atomic { at.kings[board.king_square(them)] } else { EMPTY };
+
+    // king to g or c and rook to f or d, as in Chess960 where both may start
+    // anywhere on the back rank. every square either passes over must be empty
+    // but for the two of them, and the king may not cross an attacked square.
+    // the destination itself is checked when the move is made
+    for (right, king_file, rook_file) in [(kingside, 6, 5), (queenside, 2, 3)] {
+        if board.castling & right == 0 {
+            continue;
+        }
+        let king_to = back_rank + king_file;
+        let rook_from = board.castling_rooks[right.trailing_zeros() as usize] as Square;
+        let rook_to = back_rank + rook_file;
+        let movers = BB_SQUARES[king_from] | BB_SQUARES[rook_from];
+        if occ & !movers & (span(king_from, king_to) | span(rook_from, rook_to)) != EMPTY {
+            continue;
+        }
+        let mut path = span(king_from, king_to) & !BB_SQUARES[king_from] & !BB_SQUARES[king_to] & !touching;
+        let mut safe = true;
+        while path != EMPTY && safe {
+            safe = !is_square_attacked(board, at, get_lsb(path), them);
+            path &= path - 1;
+        }
+        if safe {
+            list.push(Move::new(king, king_from, king_to, Pieces::NONE as Piece, Pieces::NONE as Piece).with_castling());
+        }
+    }
+}
+
+// crazyhouse: any piece in hand onto any empty square, pawns not on the first or last rank
+fn drop_moves(board: &Board, list: &mut MoveList) {
+    let us = board.side_to_move();
+    let empty = !board.get_all_pieces();
+    for piece in [Pieces::QUEEN, Pieces::ROOK, Pieces::BISHOP, Pieces::KNIGHT, Pieces::PAWN] {
+        let p = piece as Piece;
+        if board.pockets[us][p] == 0 {
+            continue;
+        }
+        let mut targets = if p == Pieces::PAWN as Piece { empty & !(rank_bb(0) | rank_bb(7)) } else { empty };
+        while targets != EMPTY {
+            let to = get_lsb(targets);
+            targets &= targets - 1;
+            list.push(Move::new(p, to, to, Pieces::NONE as Piece, Pieces::NONE as Piece).with_drop());
+        }
+    }
+}
+
+// the squares from `a` to `b` on one rank, both included
+#[inline(always)]
+fn span(a: Square, b: Square) -> Bitboard {
+    let (low, high) = (a.min(b), a.max(b));
+    (u64::MAX >> (63 - high)) & (u64::MAX << low)
+}
+
+pub fn is_square_attacked(board: &Board, at: &AttackTable, sq: Square, by: Color) -> bool {
+    let occ = board.get_all_pieces();
+    let bishops_queens = board.get_pieces(Pieces::BISHOP as usize, by) | board.get_pieces(Pieces::QUEEN as usize, by);
+    let rooks_queens = board.get_pieces(Pieces::ROOK as usize, by) | board.get_pieces(Pieces::QUEEN as usize, by);
+
+    // a pawn of `by` attacks sq exactly when a pawn of the other side on sq would attack it
+    at.pawns[by ^ 1][sq] & board.get_pieces(Pieces::PAWN as usize, by) != EMPTY
+        || at.knights[sq] & board.get_pieces(Pieces::KNIGHT as usize, by) != EMPTY
+        || at.kings[sq] & board.get_pieces(Pieces::KING as usize, by) != EMPTY
+        || at.get_bishop_attacks(sq, occ) & bishops_queens != EMPTY
+        || at.get_rook_attacks(sq, occ) & rooks_queens != EMPTY
+}
+
+// is the side to move in check
+pub fn in_check(board: &Board, at: &AttackTable) -> bool {
+    king_attacked(board, at, board.side_to_move())
+}
+
+// after a move has been made: did the side that moved leave its king attacked.
+// in atomic chess also whether it blew up its own king
+pub fn left_in_check(board: &Board, at: &AttackTable) -> bool {
+    let moved = board.side_to_move() ^ 1;
+    if board.variant == Variant::Atomic && board.get_pieces(Pieces::KING as usize, moved) == EMPTY {
+        return true;
+    }
+    king_attacked(board, at, moved)
+}
+
+// atomic chess has no check once a king is gone, which ends the game, or while
+// the kings touch, as taking one would blow up the other
+#[inline(always)]
+fn king_attacked(board: &Board, at: &AttackTable, color: Color) -> bool {
+    if board.variant == Variant::Atomic {
+        let king = board.get_pieces(Pieces::KING as usize, color);
+        let enemy = board.get_pieces(Pieces::KING as usize, color ^ 1);
+        if king == EMPTY || enemy == EMPTY || at.kings[get_lsb(king)] & enemy != EMPTY {
+            return false;
+        }
+    }
+    is_square_attacked(board, at, board.king_square(color), color ^ 1)
+}
+
+pub fn legal_moves(board: &Board, at: &AttackTable) -> MoveList {
+    let mut pseudo = MoveList::new();
+    generate_moves(board, at, MoveType::All, &mut pseudo);
+
+    let mut legal = MoveList::new();
+    for &m in pseudo.iter() {
+        let mut b = *board;
+        b.make_move(m);
+        if !left_in_check(&b, at) {
+            legal.push(m);
+        }
+    }
+    legal
+}
+
+// finds the legal move matching a UCI move string such as e2e4 or a7a8q
+pub fn parse_move(board: &Board, at: &AttackTable, s: &str) -> Option<Move> {
+    let list = legal_moves(board, at);
+    let found = list.iter().copied().find(|m| move_to_uci(board, *m) == s);
+    found
+}
+
+// a move as UCI writes it, where Chess960 castling is the king taking its rook
+pub fn move_to_uci(board: &Board, m: Move) -> String {
+    if board.chess960 && m.is_castling() {
+        let (rook_from, _) = board.castling_rook(m.to());
+        return format!("{}{}", square_name(m.from()), square_name(rook_from));
+    }
+    m.as_string()
+}
+
+pub fn perft(board: &Board, at: &AttackTable, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut list = MoveList::new();
+    generate_moves(board, at, MoveType::All, &mut list);
+
+    let mut nodes = 0;
+    for &m in list.iter() {
+        let mut b = *board;
+        b.make_move(m);
+        if !left_in_check(&b, at) {
+            nodes += perft(&b, at, depth - 1);
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::fen::chess960_fen, defs::NrOf, moves::with_table};
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        board
+    }
+
+    fn atomic(fen: &str) -> Board {
+        let mut board = board(fen);
+        board.variant = Variant::Atomic;
+        board
+    }
+
+    // the standard perft positions from the chessprogramming wiki
+    #[test]
+    fn standard_perft() {
+        with_table(|at| {
+            let positions: [(&str, &[u64]); 6] = [
+                ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]),
+                ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]),
+                ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238, 674624]),
+                ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467, 422333]),
+                ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]),
+                ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890]),
+            ];
+            for (fen, counts) in positions {
+                let board = board(fen);
+                for (depth, &count) in counts.iter().enumerate() {
+                    assert_eq!(perft(&board, at, depth as u8 + 1), count, "{fen} depth {}", depth + 1);
+                }
+            }
+        });
+    }
+
+    // the first positions of the Chess960 perft suite on the chessprogramming wiki
+    #[test]
+    fn chess960_perft() {
+        with_table(|at| {
+            let positions = [
+                ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", [21, 528, 12189, 326672]),
+                ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", [21, 807, 18002, 667366]),
+                ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", [20, 479, 10471, 273318]),
+                ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", [22, 593, 13440, 382958]),
+                ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", [28, 1120, 31058, 1171749]),
+            ];
+            for (fen, counts) in positions {
+                let board = board(fen);
+                for (depth, &count) in counts.iter().enumerate().take(3) {
+                    assert_eq!(perft(&board, at, depth as u8 + 1), count, "{fen} depth {}", depth + 1);
+                }
+            }
+            let (fen, counts) = positions[0];
+            assert_eq!(perft(&board(fen), at, 4), counts[3]);
+        });
+    }
+
+    #[test]
+    fn scharnagl_start_positions() {
+        assert_eq!(chess960_fen(0).unwrap(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(chess960_fen(518).unwrap(), "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(chess960_fen(959).unwrap(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1");
+        assert_eq!(chess960_fen(960), None);
+        let start = board(&chess960_fen(0).unwrap());
+        assert_eq!(start.castling_rooks, [7, 5, 63, 61]);
+    }
+
+    #[test]
+    fn chess960_fen_round_trip() {
+        // Shredder-FEN in, X-FEN out: a file letter only where the rook isn't the outermost one
+        let shredder = board("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/RR2K1R1 w GBgb - 0 1");
+        assert_eq!(shredder.castling_rooks, [6, 1, 62, 57]);
+        let x_fen = shredder.as_fen();
+        assert_eq!(x_fen, "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/RR2K1R1 w KBkq - 0 1");
+        assert_eq!(board(&x_fen), shredder);
+
+        let outermost = board("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9");
+        assert_eq!(outermost.as_fen(), "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9");
+        assert_eq!(board(&outermost.as_fen()), outermost);
+    }
+
+    // counts from the python-chess and Fairy-Stockfish atomic perft suites
+    #[test]
+    fn atomic_perft() {
+        with_table(|at| {
+            let start = atomic("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+            assert_eq!(perft(&start, at, 3), 8902);
+            assert_eq!(perft(&start, at, 4), 197326);
+            let board = atomic("rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1");
+            assert_eq!(perft(&board, at, 1), 28);
+            assert_eq!(perft(&board, at, 2), 833);
+            assert_eq!(perft(&board, at, 3), 23353);
+        });
+    }
+
+    #[test]
+    fn atomic_chess960_castling_perft() {
+        with_table(|at| {
+            let board = atomic("8/8/8/8/8/8/2k5/rR4KR w KQ - 0 1");
+            assert_eq!(perft(&board, at, 1), 18);
+            assert_eq!(perft(&board, at, 2), 180);
+            assert_eq!(perft(&board, at, 3), 4364);
+            assert_eq!(perft(&board, at, 4), 61401);
+        });
+    }
+
+    #[test]
+    fn atomic_capture_explodes_neighbours() {
+        with_table(|at| {
+            let mut board = atomic("4k3/8/8/2npb3/3Q4/8/8/4K3 w - - 0 1");
+            let m = parse_move(&board, at, "d4d5").unwrap();
+            let before = board;
+            let undo = board.make_move(m);
+            // the queen, the pawn it took and both minor pieces go
+            assert_eq!(board.as_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+            assert_eq!(board.key, board.compute_key());
+            board.unmake_move(m, undo);
+            assert_eq!(board, before);
+        });
+    }
+
+    #[test]
+    fn atomic_kings() {
+        with_table(|at| {
+            // a king can't capture, and kings that touch are never in check
+            let board = atomic("8/8/8/8/8/8/2kq4/3K4 w - - 0 1");
+            assert!(parse_move(&board, at, "d1d2").is_none());
+            assert!(!in_check(&board, at));
+
+            // blowing up the enemy king wins even out of check
+            let mut board = atomic("3r3k/6p1/8/8/8/8/8/3K2R1 w - - 0 1");
+            assert!(in_check(&board, at));
+            let m = parse_move(&board, at, "g1g7").unwrap();
+            board.make_move(m);
+            assert!(board.king_exploded());
+            assert!(legal_moves(&board, at).is_empty());
+        });
+    }
+
+    // counts from the Fairy-Stockfish crazyhouse perft suite
+    #[test]
+    fn crazyhouse_perft() {
+        with_table(|at| {
+            let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1");
+            assert_eq!(start.variant, Variant::Crazyhouse);
+            assert_eq!(perft(&start, at, 4), 197281);
+            assert_eq!(perft(&start, at, 5), 4888832);
+            let drops = board("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - -");
+            assert_eq!(perft(&drops, at, 1), 301);
+            assert_eq!(perft(&drops, at, 2), 75353);
+            // an empty pocket written as a ninth rank, and a promoted queen
+            let promoted = board("4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - -");
+            assert_eq!(promoted.variant, Variant::Crazyhouse);
+            for (depth, count) in [20, 360, 5445, 132758].into_iter().enumerate() {
+                assert_eq!(perft(&promoted, at, depth as u8 + 1), count);
+            }
+        });
+    }
+
+    #[test]
+    fn crazyhouse_promoted_pieces_return_as_pawns() {
+        with_table(|at| {
+            let (white, black) = (Colors::WHITE as Color, Colors::BLACK as Color);
+            let mut promotion = board("4k3/P7/8/8/8/8/8/4K3[] w - - 0 1");
+            promotion.make_move(parse_move(&promotion, at, "a7a8q").unwrap());
+            assert_eq!(promotion.as_fen(), "Q~3k3/8/8/8/8/8/8/4K3[] b - - 0 1");
+
+            let mut capture = board("4k3/8/8/8/8/8/1q6/1Q~2K3[] b - - 0 1");
+            let before = capture;
+            let m = parse_move(&capture, at, "b2b1").unwrap();
+            let undo = capture.make_move(m);
+            assert_eq!(capture.pockets[black][Pieces::PAWN as usize], 1);
+            assert_eq!(capture.pockets[black][Pieces::QUEEN as usize], 0);
+            assert_eq!(capture.promoted, EMPTY);
+            assert_eq!(capture.as_fen(), "4k3/8/8/8/8/8/8/1q2K3[p] w - - 0 2");
+            assert_eq!(capture.key, capture.compute_key());
+            capture.unmake_move(m, undo);
+            assert_eq!(capture, before);
+
+            // a piece that was never a pawn is banked as itself
+            let mut capture = board("4k3/8/8/8/8/8/1q6/1Q2K3[] b - - 0 1");
+            capture.make_move(parse_move(&capture, at, "b2b1").unwrap());
+            assert_eq!(capture.pockets[black][Pieces::QUEEN as usize], 1);
+            assert_eq!(capture.pockets[white], [0; NrOf::PIECE_TYPES]);
+        });
+    }
+
+    #[test]
+    fn null_move() {
+        let m = Move::default();
+        assert!(m.is_null());
+        assert!(!m.is_capture() && !m.is_promotion() && !m.is_drop());
+    }
+}
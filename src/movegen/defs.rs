@@ -0,0 +1,197 @@
+use crate::defs::{Piece, Pieces, Square, ALL_SQUARES};
+use strum::IntoEnumIterator;
+
+// crazyhouse drops can take a position past 256
+pub const MAX_LEGAL_MOVES: usize = 512;
+
+// bits 0-5 from, 6-11 to, 12-14 piece, 15-17 captured piece, 18-20 promotion,
+// 21 double pawn push, 22 en passant, 23 castling, 24 crazyhouse drop with
+// from equal to to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    data: u32,
+}
+
+// the null move, with NONE in the piece fields so it is neither a capture nor
+// a promotion
+impl Default for Move {
+    fn default() -> Move {
+        Move::new(Pieces::NONE as Piece, 0, 0, Pieces::NONE as Piece, Pieces::NONE as Piece)
+    }
+}
+
+pub struct Shift;
+impl Shift {
+    pub const FROM: u32 = 0;
+    pub const TO: u32 = 6;
+    pub const PIECE: u32 = 12;
+    pub const CAPTURED: u32 = 15;
+    pub const PROMOTION: u32 = 18;
+    pub const DOUBLE_STEP: u32 = 21;
+    pub const EN_PASSANT: u32 = 22;
+    pub const CASTLING: u32 = 23;
+    pub const DROP: u32 = 24;
+}
+
+impl Move {
+    pub fn new(piece: Piece, from: Square, to: Square, captured: Piece, promotion: Piece) -> Move {
+        Move {
+            data: (from as u32) << Shift::FROM
+                | (to as u32) << Shift::TO
+                | (piece as u32) << Shift::PIECE
+                | (captured as u32) << Shift::CAPTURED
+                | (promotion as u32) << Shift::PROMOTION,
+        }
+    }
+
+    pub fn with_double_step(mut self) -> Move {
+        self.data |= 1 << Shift::DOUBLE_STEP;
+        self
+    }
+
+    pub fn with_en_passant(mut self) -> Move {
+        self.data |= 1 << Shift::EN_PASSANT;
+        self
+    }
+
+    pub fn with_castling(mut self) -> Move {
+        self.data |= 1 << Shift::CASTLING;
+        self
+    }
+
+    pub fn with_drop(mut self) -> Move {
+        self.data |= 1 << Shift::DROP;
+        self
+    }
+
+    #[inline(always)]
+    pub fn from(&self) -> Square {
+        ((self.data >> Shift::FROM) & 0x3f) as Square
+    }
+
+    #[inline(always)]
+    pub fn to(&self) -> Square {
+        ((self.data >> Shift::TO) & 0x3f) as Square
+    }
+
+    #[inline(always)]
+    pub fn piece(&self) -> Piece {
+        ((self.data >> Shift::PIECE) & 0x7) as Piece
+    }
+
+    #[inline(always)]
+    pub fn captured(&self) -> Piece {
+        ((self.data >> Shift::CAPTURED) & 0x7) as Piece
+    }
+
+    #[inline(always)]
+    pub fn promoted(&self) -> Piece {
+        ((self.data >> Shift::PROMOTION) & 0x7) as Piece
+    }
+
+    #[inline(always)]
+    pub fn is_capture(&self) -> bool {
+        self.captured() != Pieces::NONE as Piece
+    }
+
+    #[inline(always)]
+    pub fn is_promotion(&self) -> bool {
+        self.promoted() != Pieces::NONE as Piece
+    }
+
+    #[inline(always)]
+    pub fn is_double_step(&self) -> bool {
+        (self.data >> Shift::DOUBLE_STEP) & 1 == 1
+    }
+
+    #[inline(always)]
+    pub fn is_en_passant(&self) -> bool {
+        (self.data >> Shift::EN_PASSANT) & 1 == 1
+    }
+
+    #[inline(always)]
+    pub fn is_castling(&self) -> bool {
+        (self.data >> Shift::CASTLING) & 1 == 1
+    }
+
+    #[inline(always)]
+    pub fn is_drop(&self) -> bool {
+        (self.data >> Shift::DROP) & 1 == 1
+    }
+
+    #[inline(always)]
+    pub fn is_null(&self) -> bool {
+        *self == Move::default()
+    }
+
+    // long algebraic notation as used by UCI, e.g. e2e4 or e7e8q, and P@e4 for drops
+    pub fn as_string(&self) -> String {
+        if self.is_drop() {
+            return format!("{}@{}", Pieces::from_index(self.piece()).as_char(), square_name(self.to()));
+        }
+        let mut s = format!("{}{}", square_name(self.from()), square_name(self.to()));
+        if self.is_promotion() {
+            s.push(Pieces::from_index(self.promoted()).as_char().to_ascii_lowercase());
+        }
+        s
+    }
+}
+
+pub fn square_name(sq: Square) -> &'static str {
+    ALL_SQUARES::iter().nth(sq).map(|s| s.as_string()).unwrap_or("-")
+}
+
+pub fn square_from_name(name: &str) -> Option<Square> {
+    ALL_SQUARES::iter().position(|s| s.as_string() == name)
+}
+
+pub struct MoveList {
+    list: [Move; MAX_LEGAL_MOVES],
+    count: usize,
+}
+
+impl MoveList {
+    pub fn new() -> MoveList {
+        MoveList {
+            list: [Move::default(); MAX_LEGAL_MOVES],
+            count: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn push(&mut self, m: Move) {
+        self.list[self.count] = m;
+        self.count += 1;
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    #[inline(always)]
+    pub fn get(&self, index: usize) -> Move {
+        self.list[index]
+    }
+
+    #[inline(always)]
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.list.swap(a, b);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Move> {
+        self.list[..self.count].iter()
+    }
+}
+
+// which moves the generator should produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveType {
+    All,
+    Captures,
+}
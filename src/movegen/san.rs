@@ -0,0 +1,274 @@
+use std::fmt::{self, Display};
+
+use crate::{
+    board::Board,
+    defs::{Piece, Pieces, Square},
+    movegen::{
+        defs::{square_from_name, square_name, Move, MoveList, MoveType},
+        generate_moves, in_check, left_in_check, legal_moves, parse_move,
+    },
+    moves::AttackTable,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    Syntax(String),
+    Illegal(String),
+    Ambiguous(String),
+}
+
+impl Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(s) => write!(f, "Error in move {s}: not a move"),
+            Self::Illegal(s) => write!(f, "Error in move {s}: illegal in this position"),
+            Self::Ambiguous(s) => write!(f, "Error in move {s}: matches more than one move"),
+        }
+    }
+}
+
+// standard algebraic notation of a legal move, with check and mate suffixes
+pub fn move_to_san(board: &Board, at: &AttackTable, m: Move) -> String {
+    let mut san = if m.is_drop() {
+        // pawn drops go without a letter, like pawn moves
+        let letter = if m.piece() == Pieces::PAWN as Piece { String::new() } else { String::from(Pieces::from_index(m.piece()).as_char()) };
+        format!("{letter}@{}", square_name(m.to()))
+    } else if m.is_castling() {
+        String::from(if m.to() % 8 == 6 { "O-O" } else { "O-O-O" })
+    } else if m.piece() == Pieces::PAWN as usize {
+        let mut s = String::new();
+        if m.is_capture() {
+            s.push(square_name(m.from()).as_bytes()[0] as char);
+            s.push('x');
+        }
+        s.push_str(square_name(m.to()));
+        if m.is_promotion() {
+            s.push('=');
+            s.push(Pieces::from_index(m.promoted()).as_char());
+        }
+        s
+    } else {
+        let mut s = String::from(Pieces::from_index(m.piece()).as_char());
+        s.push_str(&disambiguation(board, at, m));
+        if m.is_capture() {
+            s.push('x');
+        }
+        s.push_str(square_name(m.to()));
+        s
+    };
+
+    let mut after = *board;
+    after.make_move(m);
+    if in_check(&after, at) {
+        san.push(if legal_moves(&after, at).is_empty() { '#' } else { '+' });
+    }
+    san
+}
+
+// the file, rank or whole square of the origin, whichever is the least needed
+// to tell `m` apart from other moves of the same piece type to the same square
+fn disambiguation(board: &Board, at: &AttackTable, m: Move) -> String {
+    let rivals: Vec<Move> = legal_moves(board, at)
+        .iter()
+        .copied()
+        .filter(|o| o.piece() == m.piece() && o.to() == m.to() && o.from() != m.from() && !o.is_drop())
+        .collect();
+    if rivals.is_empty() {
+        return String::new();
+    }
+    let from = square_name(m.from());
+    if rivals.iter().all(|o| o.from() % 8 != m.from() % 8) {
+        from[..1].to_string()
+    } else if rivals.iter().all(|o| o.from() / 8 != m.from() / 8) {
+        from[1..].to_string()
+    } else {
+        from.to_string()
+    }
+}
+
+// the legal move a SAN string stands for. lenient about what PGN files get
+// wrong in practice: 0-0 for O-O, missing x, + and #, promotions without =,
+// annotation glyphs such as !? and UCI moves like e2e4. crazyhouse drops are
+// N@f3, with or without P for pawns
+pub fn parse_san(board: &Board, at: &AttackTable, san: &str) -> Result<Move, SanError> {
+    let syntax = || SanError::Syntax(san.to_string());
+    let s = san.trim_end_matches(['+', '#', '!', '?']).trim_end_matches("e.p.").trim();
+    if s.is_empty() {
+        return Err(syntax());
+    }
+    // only the moves matching the text get checked for legality
+    let mut pseudo = MoveList::new();
+    generate_moves(board, at, MoveType::All, &mut pseudo);
+    let is_legal = |m: &Move| {
+        let mut after = *board;
+        after.make_move(*m);
+        !left_in_check(&after, at)
+    };
+
+    if let Some(long) = match s.replace('0', "O").to_ascii_uppercase().as_str() {
+        "O-O" => Some(false),
+        "O-O-O" => Some(true),
+        _ => None,
+    } {
+        let target = if long { 2 } else { 6 };
+        return pseudo
+            .iter()
+            .copied()
+            .find(|m| m.is_castling() && m.to() % 8 == target && is_legal(m))
+            .ok_or_else(|| SanError::Illegal(san.to_string()));
+    }
+    if let Some((letter, square)) = s.split_once('@') {
+        let piece = match letter.chars().next() {
+            None => Pieces::PAWN as Piece,
+            Some(c) if letter.len() == 1 && "QRBNPqrbnp".contains(c) => piece_from_char(c.to_ascii_uppercase()),
+            _ => return Err(syntax()),
+        };
+        let to = square_from_name(square).ok_or_else(syntax)?;
+        return pseudo
+            .iter()
+            .copied()
+            .find(|m| m.is_drop() && m.piece() == piece && m.to() == to && is_legal(m))
+            .ok_or_else(|| SanError::Illegal(san.to_string()));
+    }
+    // uci moves are rare, so they are only tried once san has failed
+    piece_move(&pseudo, is_legal, san, s).or_else(|e| parse_move(board, at, s).ok_or(e))
+}
+
+// a san move other than castling, `s` being `san` without its suffixes
+fn piece_move(pseudo: &MoveList, is_legal: impl Fn(&Move) -> bool, san: &str, s: &str) -> Result<Move, SanError> {
+    let syntax = || SanError::Syntax(san.to_string());
+    let mut chars: Vec<char> = s.chars().filter(|c| !matches!(c, 'x' | ':' | '-' | '=')).collect();
+    let piece = match chars.first() {
+        Some(&c) if "KQRBNP".contains(c) => {
+            chars.remove(0);
+            piece_from_char(c)
+        }
+        Some(c) if ('a'..='h').contains(c) => Pieces::PAWN as Piece,
+        // lowercase piece letters, except b which is a file
+        Some(&c) if "kqrn".contains(c) => {
+            chars.remove(0);
+            piece_from_char(c.to_ascii_uppercase())
+        }
+        _ => return Err(syntax()),
+    };
+
+    // a trailing piece letter after the destination is a promotion
+    let mut promotion = None;
+    if piece == Pieces::PAWN as Piece && chars.last().is_some_and(|c| "QRBNqrbn".contains(*c)) {
+        promotion = chars.pop().map(|c| piece_from_char(c.to_ascii_uppercase()));
+    }
+
+    if chars.len() < 2 {
+        return Err(syntax());
+    }
+    let to_name: String = chars[chars.len() - 2..].iter().collect();
+    let to: Square = square_from_name(&to_name).ok_or_else(syntax)?;
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &c in &chars[..chars.len() - 2] {
+        match c {
+            'a'..='h' if from_file.is_none() => from_file = Some(c as usize - 'a' as usize),
+            '1'..='8' if from_rank.is_none() => from_rank = Some(c as usize - '1' as usize),
+            _ => return Err(syntax()),
+        }
+    }
+
+    let mut found = pseudo.iter().copied().filter(|m| {
+        m.piece() == piece
+            && m.to() == to
+            && !m.is_castling()
+            && !m.is_drop()
+            && from_file.is_none_or(|f| m.from() % 8 == f)
+            && from_rank.is_none_or(|r| m.from() / 8 == r)
+            && (!m.is_promotion() || Some(m.promoted()) == promotion.or(Some(Pieces::QUEEN as Piece)))
+            && (m.is_promotion() || promotion.is_none())
+            && is_legal(m)
+    });
+    match (found.next(), found.next()) {
+        (Some(m), None) => Ok(m),
+        (Some(_), Some(_)) => Err(SanError::Ambiguous(san.to_string())),
+        (None, _) => Err(SanError::Illegal(san.to_string())),
+    }
+}
+
+fn piece_from_char(c: char) -> Piece {
+    (match c {
+        'K' => Pieces::KING,
+        'Q' => Pieces::QUEEN,
+        'R' => Pieces::ROOK,
+        'B' => Pieces::BISHOP,
+        'N' => Pieces::KNIGHT,
+        _ => Pieces::PAWN,
+    }) as Piece
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::with_table;
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        board
+    }
+
+    fn uci(board: &Board, at: &AttackTable, m: &str) -> Move {
+        parse_move(board, at, m).unwrap()
+    }
+
+    #[test]
+    fn writes_san() {
+        with_table(|at| {
+            let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+            assert_eq!(move_to_san(&start, at, uci(&start, at, "g1f3")), "Nf3");
+            assert_eq!(move_to_san(&start, at, uci(&start, at, "e2e4")), "e4");
+            let castling = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+            assert_eq!(move_to_san(&castling, at, uci(&castling, at, "e1g1")), "O-O");
+            assert_eq!(move_to_san(&castling, at, uci(&castling, at, "e1c1")), "O-O-O");
+            let knights = board("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+            assert_eq!(move_to_san(&knights, at, uci(&knights, at, "b1d2")), "Nbd2");
+            let rooks = board("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1");
+            assert_eq!(move_to_san(&rooks, at, uci(&rooks, at, "a1a3")), "R1a3");
+            let promotion = board("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+            assert_eq!(move_to_san(&promotion, at, uci(&promotion, at, "e7e8q")), "e8=Q+");
+            assert_eq!(move_to_san(&promotion, at, uci(&promotion, at, "e7e8n")), "e8=N");
+            let mate = board("rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2");
+            assert_eq!(move_to_san(&mate, at, uci(&mate, at, "d8h4")), "Qh4#");
+        });
+    }
+
+    #[test]
+    fn parses_lenient_san() {
+        with_table(|at| {
+            let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+            assert_eq!(parse_san(&start, at, "Nf3!?"), Ok(uci(&start, at, "g1f3")));
+            assert_eq!(parse_san(&start, at, "Ng1-f3"), Ok(uci(&start, at, "g1f3")));
+            assert_eq!(parse_san(&start, at, "e2e4"), Ok(uci(&start, at, "e2e4")));
+            let castling = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+            assert_eq!(parse_san(&castling, at, "0-0"), Ok(uci(&castling, at, "e1g1")));
+            assert_eq!(parse_san(&castling, at, "0-0-0+"), Ok(uci(&castling, at, "e1c1")));
+            let en_passant = board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+            let exd6 = uci(&en_passant, at, "e5d6");
+            assert!(exd6.is_en_passant());
+            assert_eq!(parse_san(&en_passant, at, "exd6 e.p."), Ok(exd6));
+            assert_eq!(parse_san(&en_passant, at, "ed6"), Ok(exd6));
+            let promotion = board("8/4P3/8/8/k7/8/8/4K3 w - - 0 1");
+            assert_eq!(parse_san(&promotion, at, "e8Q"), Ok(uci(&promotion, at, "e7e8q")));
+            assert_eq!(parse_san(&promotion, at, "e8N"), Ok(uci(&promotion, at, "e7e8n")));
+            assert_eq!(parse_san(&promotion, at, "e8"), Ok(uci(&promotion, at, "e7e8q")));
+        });
+    }
+
+    #[test]
+    fn rejects_bad_san() {
+        with_table(|at| {
+            let knights = board("4k3/8/8/8/8/8/8/1N2KN2 w - - 0 1");
+            assert_eq!(parse_san(&knights, at, "Nd2"), Err(SanError::Ambiguous(String::from("Nd2"))));
+            assert_eq!(parse_san(&knights, at, "Nfd2"), Ok(uci(&knights, at, "f1d2")));
+            assert_eq!(parse_san(&knights, at, "Nd4"), Err(SanError::Illegal(String::from("Nd4"))));
+            assert_eq!(parse_san(&knights, at, "O-O"), Err(SanError::Illegal(String::from("O-O"))));
+            assert_eq!(parse_san(&knights, at, "Zz9"), Err(SanError::Syntax(String::from("Zz9"))));
+        });
+    }
+}
@@ -1,7 +1,6 @@
 use crate::{
-    board::{Board},
-    board::bitboard::{Bitboard, get_bit, get_lsb, count_bits, print_bitboard, pop_bit},
-    defs::{Castling, NrOf, Piece, Square, EMPTY, Colors, Color, Pieces, BB_SQUARES},
+    board::bitboard::{Bitboard},
+    defs::{NrOf, Square, Colors, Color, BB_SQUARES},
     sorcery::{mask_bishop_attacks, mask_rook_attacks, BISHOP_RELEVANT_BITS, ROOK_RELEVANT_BITS, set_occupancy, bishop_attacks, rook_attacks, },
     magics::{BISHOP_MAGICS, ROOK_MAGICS},
 };
@@ -13,6 +12,45 @@ pub const NOT_AB_FILE: Bitboard = 0xfcfcfcfcfcfcfcfc;
 pub const NOT_FIRST_RANK: Bitboard = 0xffffffffffffff00;
 pub const NOT_EIGHTH_RANK: Bitboard = 0x00ffffffffffffff;
 pub const DARK_SQUARES: Bitboard = 0xaa55aa55aa55aa55;
+pub const A_FILE: Bitboard = 0x0101010101010101;
+pub const FIRST_RANK: Bitboard = 0x00000000000000ff;
+
+#[inline(always)]
+pub fn file_bb(file: usize) -> Bitboard {
+    A_FILE << file
+}
+
+#[inline(always)]
+pub fn rank_bb(rank: usize) -> Bitboard {
+    FIRST_RANK << (rank * 8)
+}
+
+// the files directly left and right of `file`
+#[inline(always)]
+pub fn adjacent_files_bb(file: usize) -> Bitboard {
+    let f = file_bb(file);
+    ((f & NOT_A_FILE) >> 1) | ((f & NOT_H_FILE) << 1)
+}
+
+// all ranks in front of `rank` from the point of view of `color`
+#[inline(always)]
+pub fn forward_ranks_bb(color: Color, rank: usize) -> Bitboard {
+    if color == Colors::WHITE as Color {
+        if rank >= 7 { 0 } else { !0u64 << ((rank + 1) * 8) }
+    } else {
+        (1u64 << (rank * 8)) - 1
+    }
+}
+
+// set-wise pawn attacks for all pawns in `pawns`
+#[inline(always)]
+pub fn pawn_attacks_bb(pawns: Bitboard, color: Color) -> Bitboard {
+    if color == Colors::WHITE as Color {
+        ((pawns & NOT_A_FILE) << 7) | ((pawns & NOT_H_FILE) << 9)
+    } else {
+        ((pawns & NOT_A_FILE) >> 9) | ((pawns & NOT_H_FILE) >> 7)
+    }
+}
 
 pub struct AttackTable{
     pub pawns: [[Bitboard; NrOf::SQUARES]; NrOf::COLORS],
@@ -44,7 +82,6 @@ impl AttackTable {
     }
 
     fn init_pawns(&mut self) {
-        let mut pawns: [[Bitboard; NrOf::SQUARES]; NrOf::COLORS] = [[0; NrOf::SQUARES]; NrOf::COLORS];
         for sq in 0..NrOf::SQUARES {
             self.pawns[Colors::WHITE as Color][sq] = pawn_attacks(Colors::WHITE as Color, sq);
             self.pawns[Colors::BLACK as Color ][sq] = pawn_attacks(Colors::BLACK as Color, sq);
@@ -68,9 +105,6 @@ impl AttackTable {
             self.bishop_masks[sq] = mask_bishop_attacks(sq);
             self.rook_masks[sq] = mask_rook_attacks(sq);
 
-            let attack_mask_bishop: Bitboard = self.bishop_masks[sq];
-            let attack_mask_rook: Bitboard = self.rook_masks[sq];
-
             let relevant_bits_bishop: i32 = BISHOP_RELEVANT_BITS[sq];
             let relevant_bits_rook: i32 = ROOK_RELEVANT_BITS[sq];
 
@@ -79,14 +113,14 @@ impl AttackTable {
 
             for i in 0..occupancy_index_bishop {
                 let occ = set_occupancy(i, relevant_bits_bishop as usize, self.bishop_masks[sq]);
-                let magic_index = occ * BISHOP_MAGICS[sq] >> (64 - relevant_bits_bishop);
+                let magic_index = occ.wrapping_mul(BISHOP_MAGICS[sq]) >> (64 - relevant_bits_bishop);
                 self.bishops[sq][magic_index as usize] = bishop_attacks(sq, occ);
 
             }
 
             for i in 0..occupancy_index_rook {
                 let occ = set_occupancy(i, relevant_bits_rook as usize, self.rook_masks[sq]);
-                let magic_index = occ * ROOK_MAGICS[sq] >> (64 - relevant_bits_rook);
+                let magic_index = occ.wrapping_mul(ROOK_MAGICS[sq]) >> (64 - relevant_bits_rook);
                 self.rooks[sq][magic_index as usize] = rook_attacks(sq, occ);
             }
         }
@@ -96,7 +130,7 @@ impl AttackTable {
     pub fn get_bishop_attacks(&self, sq: Square, occ: Bitboard) -> Bitboard {
         let mut occ = occ;
         occ &= self.bishop_masks[sq];
-        occ *= BISHOP_MAGICS[sq];
+        occ = occ.wrapping_mul(BISHOP_MAGICS[sq]);
         occ >>= 64 - BISHOP_RELEVANT_BITS[sq];
         self.bishops[sq][occ as usize]
     }
@@ -105,14 +139,14 @@ impl AttackTable {
     pub fn get_rook_attacks(&self, sq: Square, occ: Bitboard) -> Bitboard {
         let mut occ = occ;
         occ &= self.rook_masks[sq];
-        occ *= ROOK_MAGICS[sq];
+        occ = occ.wrapping_mul(ROOK_MAGICS[sq]);
         occ >>= 64 - ROOK_RELEVANT_BITS[sq];
         self.rooks[sq][occ as usize]
     }
 }
 
 
-fn pawn_attacks(color: Color, sq: Square) -> Bitboard {
+pub fn pawn_attacks(color: Color, sq: Square) -> Bitboard {
     let b = BB_SQUARES[sq];
     let mut attacks: Bitboard = 0;
     if color == Colors::WHITE as Color {
@@ -155,7 +189,7 @@ fn knight_attacks(sq: Square) -> Bitboard {
     attacks
 }
 
-fn king_attacks(sq: Square) -> Bitboard {
+pub fn king_attacks(sq: Square) -> Bitboard {
     let b = BB_SQUARES[sq];
     let mut attacks: Bitboard = 0;
     if b & NOT_H_FILE != 0 {
@@ -185,3 +219,10 @@ fn king_attacks(sq: Square) -> Bitboard {
     attacks
 }
 
+
+// the attack table is too big for the default test thread stack in debug builds
+#[cfg(test)]
+pub fn with_table(test: fn(&AttackTable)) {
+    let thread = std::thread::Builder::new().stack_size(64 << 20).spawn(move || test(&AttackTable::new()));
+    thread.unwrap().join().unwrap();
+}
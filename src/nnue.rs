@@ -0,0 +1,24 @@
+pub mod accumulator;
+pub mod features;
+pub mod loader;
+pub mod network;
+mod simd;
+pub mod trainer;
+
+pub use self::{
+    accumulator::NnueState,
+    network::Network,
+};
+
+// feature transformer outputs per perspective
+pub const HIDDEN: usize = 128;
+pub const L1: usize = 32;
+pub const L2: usize = 32;
+
+// activations are clipped to [0, QA], hidden weights are scaled by QB
+pub const QA: i32 = 127;
+pub const QB: i32 = 64;
+pub const QB_SHIFT: u32 = 6;
+
+// the float network predicts centipawns / OUTPUT_SCALE
+pub const OUTPUT_SCALE: i32 = 400;
@@ -0,0 +1,199 @@
+use crate::{
+    board::Board,
+    board::playmove::captured_square,
+    defs::{Color, NrOf, Piece, Pieces, Variant},
+    movegen::defs::Move,
+    nnue::{
+        features::{active_features, feature_index, needs_refresh},
+        network::Network,
+        simd, HIDDEN,
+    },
+};
+
+// feature transformer output for both perspectives, indexed by Colors
+#[derive(Clone, Copy)]
+#[repr(C, align(32))]
+pub struct Accumulator {
+    pub values: [[i16; HIDDEN]; NrOf::COLORS],
+}
+
+impl Accumulator {
+    pub fn new(net: &Network, board: &Board) -> Accumulator {
+        let mut acc = Accumulator {
+            values: [[0; HIDDEN]; NrOf::COLORS],
+        };
+        for perspective in 0..NrOf::COLORS {
+            acc.refresh(net, board, perspective);
+        }
+        acc
+    }
+
+    pub fn refresh(&mut self, net: &Network, board: &Board, perspective: Color) {
+        let values = &mut self.values[perspective];
+        values.copy_from_slice(&net.ft_bias);
+        let mut features = Vec::with_capacity(32);
+        active_features(board, perspective, &mut features);
+        for &f in features.iter() {
+            simd::add_i16(values, net.ft_column(f));
+        }
+    }
+
+    // the accumulator after `m`, made by `mover`, given the board after the move
+    pub fn update(&self, net: &Network, board: &Board, m: Move, mover: Color) -> Accumulator {
+        let mut next = *self;
+        let them = mover ^ 1;
+        let king = Pieces::KING as Piece;
+        // an atomic explosion changes too many squares to follow
+        let exploded = board.variant == Variant::Atomic && m.is_capture();
+
+        for perspective in 0..NrOf::COLORS {
+            if exploded || (m.piece() == king && perspective == mover && needs_refresh(perspective, m.from(), m.to())) {
+                next.refresh(net, board, perspective);
+                continue;
+            }
+
+            let ksq = board.king_square(perspective);
+            let values = &mut next.values[perspective];
+            let placed = if m.is_promotion() { m.promoted() } else { m.piece() };
+            // a dropped piece comes from the pocket, which the network doesn't see
+            if !m.is_drop() {
+                simd::sub_i16(values, net.ft_column(feature_index(perspective, ksq, m.piece(), mover, m.from())));
+            }
+            simd::add_i16(values, net.ft_column(feature_index(perspective, ksq, placed, mover, m.to())));
+
+            if m.is_capture() {
+                let sq = captured_square(m, mover);
+                simd::sub_i16(values, net.ft_column(feature_index(perspective, ksq, m.captured(), them, sq)));
+            }
+
+            if m.is_castling() {
+                let rook = Pieces::ROOK as Piece;
+                let (rook_from, rook_to) = board.castling_rook(m.to());
+                simd::sub_i16(values, net.ft_column(feature_index(perspective, ksq, rook, mover, rook_from)));
+                simd::add_i16(values, net.ft_column(feature_index(perspective, ksq, rook, mover, rook_to)));
+            }
+        }
+        next
+    }
+}
+
+// a stack of accumulators that follows make/unmake during a search: push after
+// Board::make_move, pop together with Board::unmake_move
+pub struct NnueState {
+    stack: Vec<Accumulator>,
+}
+
+impl NnueState {
+    pub fn new(net: &Network, board: &Board) -> NnueState {
+        let mut stack = Vec::with_capacity(256);
+        stack.push(Accumulator::new(net, board));
+        NnueState { stack }
+    }
+
+    // `board` is the position after the move
+    pub fn make_move(&mut self, net: &Network, board: &Board, m: Move) {
+        let mover = board.side_to_move() ^ 1;
+        let next = self.current().update(net, board, m, mover);
+        self.stack.push(next);
+    }
+
+    pub fn unmake_move(&mut self) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    #[inline(always)]
+    pub fn current(&self) -> &Accumulator {
+        self.stack.last().expect("accumulator stack is never empty")
+    }
+
+    pub fn evaluate(&self, net: &Network, board: &Board) -> i32 {
+        net.evaluate(self.current(), board.side_to_move())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        moves::{with_table, AttackTable},
+        movegen::{legal_moves, parse_move},
+        nnue::features::king_bucket,
+    };
+
+    #[derive(Default)]
+    struct Seen {
+        captures: usize,
+        short_castles: usize,
+        long_castles: usize,
+        en_passant: usize,
+        promotions: usize,
+        bucket_changes: usize,
+    }
+
+    // every incremental update in the tree must match a fresh accumulator
+    fn check_tree(net: &Network, at: &AttackTable, board: &Board, acc: &Accumulator, depth: u8, seen: &mut Seen) {
+        if depth == 0 {
+            return;
+        }
+        let mover = board.side_to_move();
+        for &m in legal_moves(board, at).iter() {
+            let mut next = *board;
+            next.make_move(m);
+            let updated = acc.update(net, &next, m, mover);
+            let fresh = Accumulator::new(net, &next);
+            assert!(updated.values == fresh.values, "{} after {}", board.as_fen(), m.as_string());
+
+            seen.captures += m.is_capture() as usize;
+            seen.short_castles += (m.is_castling() && m.to() > m.from()) as usize;
+            seen.long_castles += (m.is_castling() && m.to() < m.from()) as usize;
+            seen.en_passant += m.is_en_passant() as usize;
+            seen.promotions += m.is_promotion() as usize;
+            seen.bucket_changes += (m.piece() == Pieces::KING as Piece && king_bucket(mover, m.from()) != king_bucket(mover, m.to())) as usize;
+            check_tree(net, at, &next, &updated, depth - 1, seen);
+        }
+    }
+
+    #[test]
+    fn incremental_update_matches_refresh() {
+        with_table(|at| {
+            let net = Network::random(1);
+            let mut seen = Seen::default();
+            for fen in [
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                "rnbqkb1r/ppp1pppp/5n2/3pP3/8/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 3",
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            ] {
+                let mut board = Board::new();
+                board.parse_fen(Some(fen)).unwrap();
+                check_tree(&net, at, &board, &Accumulator::new(&net, &board), 2, &mut seen);
+            }
+            assert!(seen.captures > 0 && seen.en_passant > 0 && seen.promotions > 0);
+            assert!(seen.short_castles > 0 && seen.long_castles > 0 && seen.bucket_changes > 0);
+        });
+    }
+
+    #[test]
+    fn state_follows_moves() {
+        with_table(|at| {
+            let net = Network::random(2);
+            let mut board = Board::new();
+            board.parse_fen(None).unwrap();
+            let start = board;
+            let mut state = NnueState::new(&net, &board);
+            let start_eval = state.evaluate(&net, &start);
+            for uci in ["e2e4", "d7d5", "e4d5", "d8d5", "e1e2"] {
+                let m = parse_move(&board, at, uci).unwrap();
+                board.make_move(m);
+                state.make_move(&net, &board, m);
+                assert_eq!(state.evaluate(&net, &board), net.evaluate(&Accumulator::new(&net, &board), board.side_to_move()));
+            }
+            for _ in 0..5 {
+                state.unmake_move();
+            }
+            assert_eq!(state.evaluate(&net, &start), start_eval);
+        });
+    }
+}
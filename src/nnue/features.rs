@@ -0,0 +1,59 @@
+use crate::{
+    board::Board,
+    board::bitboard::get_lsb,
+    defs::{Color, Colors, NrOf, Piece, Square, EMPTY},
+};
+
+// HalfKA: every piece including both kings, seen from one side's king bucket
+pub const KING_BUCKETS: usize = 4;
+pub const FEATURES_PER_BUCKET: usize = NrOf::PIECE_TYPES * NrOf::COLORS * NrOf::SQUARES;
+pub const INPUTS: usize = KING_BUCKETS * FEATURES_PER_BUCKET;
+
+// squares are flipped for black so both sides see their own pieces at the bottom,
+// and mirrored so the own king is always on files a-d
+#[inline(always)]
+pub fn orient(perspective: Color, king: Square, sq: Square) -> Square {
+    let flip = if perspective == Colors::WHITE as Color { 0 } else { 56 };
+    let mirror = if (king ^ flip) % 8 >= 4 { 7 } else { 0 };
+    sq ^ flip ^ mirror
+}
+
+// bucket of the oriented king square: back rank or not, wing or centre
+#[inline(always)]
+pub fn king_bucket(perspective: Color, king: Square) -> usize {
+    let k = orient(perspective, king, king);
+    let back_rank = if k / 8 == 0 { 0 } else { 2 };
+    let centre = if k % 8 < 2 { 0 } else { 1 };
+    back_rank + centre
+}
+
+#[inline(always)]
+pub fn feature_index(perspective: Color, king: Square, piece: Piece, color: Color, sq: Square) -> usize {
+    let side = (color != perspective) as usize;
+    king_bucket(perspective, king) * FEATURES_PER_BUCKET
+        + (piece * NrOf::COLORS + side) * NrOf::SQUARES
+        + orient(perspective, king, sq)
+}
+
+// a king move only changes the other features when the bucket or the mirroring changes
+#[inline(always)]
+pub fn needs_refresh(perspective: Color, from: Square, to: Square) -> bool {
+    king_bucket(perspective, from) != king_bucket(perspective, to)
+        || orient(perspective, from, 0) != orient(perspective, to, 0)
+}
+
+// every active feature of the position for one perspective
+pub fn active_features(board: &Board, perspective: Color, features: &mut Vec<usize>) {
+    features.clear();
+    let king = board.king_square(perspective);
+    for color in 0..NrOf::COLORS {
+        for piece in 0..NrOf::PIECE_TYPES {
+            let mut bb = board.get_pieces(piece, color);
+            while bb != EMPTY {
+                let sq = get_lsb(bb);
+                bb &= bb - 1;
+                features.push(feature_index(perspective, king, piece, color, sq));
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::fmt::{self, Display};
+use std::fs;
+
+use crate::nnue::{
+    features::{INPUTS, KING_BUCKETS},
+    network::Network,
+    HIDDEN, L1, L2, OUTPUT_SCALE, QA, QB,
+};
+
+// file layout, all integers little-endian:
+//   magic "RCNN", version u32, architecture hash u32,
+//   inputs, hidden, l1, l2 as u32, qa, qb, output scale as u32,
+//   ft weights i16, ft bias i16, l1 weights i8, l1 bias i32,
+//   l2 weights i8, l2 bias i32, output weights i8, output bias i32,
+//   checksum u32 (FNV-1a of everything before it)
+pub const MAGIC: &[u8; 4] = b"RCNN";
+pub const VERSION: u32 = 1;
+pub const ARCHITECTURE: &str = "HalfKA(4x768)->2x128->32->32->1";
+// keeps the description above honest when the layer sizes change
+const _: () = assert!(KING_BUCKETS == 4 && HIDDEN == 128 && L1 == 32 && L2 == 32);
+const HEADER_LEN: usize = 4 + 4 * 9;
+const FILE_LEN: usize = HEADER_LEN + 2 * (INPUTS * HIDDEN + HIDDEN) + L1 * 2 * HIDDEN + 4 * L1 + L2 * L1 + 4 * L2 + L2 + 4 + 4;
+
+pub const fn fnv1a(bytes: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(0x01000193);
+        i += 1;
+    }
+    hash
+}
+
+pub const ARCHITECTURE_HASH: u32 = fnv1a(ARCHITECTURE.as_bytes());
+
+// the embed-net feature compiles the file named by RUSTCHESS_NET into the binary
+#[cfg(feature = "embed-net")]
+const EMBEDDED: &[u8] = include_bytes!(env!("RUSTCHESS_NET", "the embed-net feature needs RUSTCHESS_NET set to a network file"));
+
+// the header checks of from_bytes, run by the compiler so a net for another
+// build fails early; hashing the file in a const takes far too long, so the
+// checksum is left to from_bytes at startup
+#[cfg(feature = "embed-net")]
+const _: () = {
+    assert!(EMBEDDED.len() >= HEADER_LEN && le_u32(EMBEDDED, 0) == u32::from_le_bytes(*MAGIC), "RUSTCHESS_NET is not a rustChess network");
+    assert!(le_u32(EMBEDDED, 4) == VERSION, "RUSTCHESS_NET has an unsupported format version");
+    assert!(le_u32(EMBEDDED, 8) == ARCHITECTURE_HASH, "RUSTCHESS_NET was trained for a different architecture");
+    assert!(EMBEDDED.len() == FILE_LEN, "RUSTCHESS_NET has the wrong size for this architecture");
+};
+
+#[cfg(feature = "embed-net")]
+const fn le_u32(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]])
+}
+
+#[derive(Debug)]
+pub enum NetworkError {
+    Io(String),
+    BadMagic,
+    UnsupportedVersion(u32),
+    ArchitectureMismatch(u32),
+    DimensionMismatch([u32; 4]),
+    QuantizationMismatch([u32; 3]),
+    Truncated,
+    TrailingData(usize),
+    ChecksumMismatch { stored: u32, computed: u32 },
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error reading network file: {e}"),
+            Self::BadMagic => write!(f, "Error in network file: not a rustChess network"),
+            Self::UnsupportedVersion(v) => {
+                write!(f, "Error in network file: format version {v}, this engine reads version {VERSION}")
+            }
+            Self::ArchitectureMismatch(h) => write!(
+                f,
+                "Error in network file: architecture hash {h:#010x} does not match this engine's {ARCHITECTURE_HASH:#010x} ({ARCHITECTURE})"
+            ),
+            Self::DimensionMismatch([i, h, l1, l2]) => write!(
+                f,
+                "Error in network file: layers {i}->{h}->{l1}->{l2}, this engine expects {INPUTS}->{HIDDEN}->{L1}->{L2}"
+            ),
+            Self::QuantizationMismatch([qa, qb, scale]) => write!(
+                f,
+                "Error in network file: quantization {qa}/{qb}/{scale}, this engine expects {QA}/{QB}/{OUTPUT_SCALE}"
+            ),
+            Self::Truncated => write!(f, "Error in network file: file is truncated"),
+            Self::TrailingData(n) => write!(f, "Error in network file: {n} unexpected bytes after the checksum"),
+            Self::ChecksumMismatch { stored, computed } => {
+                write!(f, "Error in network file: checksum {stored:#010x} does not match contents {computed:#010x}")
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], NetworkError> {
+        let end = self.pos.checked_add(n).ok_or(NetworkError::Truncated)?;
+        let slice = self.bytes.get(self.pos..end).ok_or(NetworkError::Truncated)?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, NetworkError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn i16s(&mut self, n: usize) -> Result<Vec<i16>, NetworkError> {
+        Ok(self.take(n * 2)?.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+    }
+
+    fn i8s(&mut self, n: usize) -> Result<Vec<i8>, NetworkError> {
+        Ok(self.take(n)?.iter().map(|&b| b as i8).collect())
+    }
+
+    fn i32s(&mut self, n: usize) -> Result<Vec<i32>, NetworkError> {
+        Ok(self.take(n * 4)?.chunks_exact(4).map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Network, NetworkError> {
+        let bytes = fs::read(path).map_err(|e| NetworkError::Io(format!("{path}: {e}")))?;
+        Network::from_bytes(&bytes)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), NetworkError> {
+        fs::write(path, self.to_bytes()).map_err(|e| NetworkError::Io(format!("{path}: {e}")))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Network, NetworkError> {
+        if bytes.len() < HEADER_LEN + 4 {
+            return Err(if bytes.starts_with(MAGIC) { NetworkError::Truncated } else { NetworkError::BadMagic });
+        }
+        let mut r = Reader { bytes, pos: 0 };
+        if r.take(4)? != MAGIC {
+            return Err(NetworkError::BadMagic);
+        }
+        let version = r.u32()?;
+        if version != VERSION {
+            return Err(NetworkError::UnsupportedVersion(version));
+        }
+        let hash = r.u32()?;
+        if hash != ARCHITECTURE_HASH {
+            return Err(NetworkError::ArchitectureMismatch(hash));
+        }
+        let dims = [r.u32()?, r.u32()?, r.u32()?, r.u32()?];
+        if dims != [INPUTS as u32, HIDDEN as u32, L1 as u32, L2 as u32] {
+            return Err(NetworkError::DimensionMismatch(dims));
+        }
+        let quant = [r.u32()?, r.u32()?, r.u32()?];
+        if quant != [QA as u32, QB as u32, OUTPUT_SCALE as u32] {
+            return Err(NetworkError::QuantizationMismatch(quant));
+        }
+
+        let net = Network {
+            ft_weights: r.i16s(INPUTS * HIDDEN)?,
+            ft_bias: r.i16s(HIDDEN)?,
+            l1_weights: r.i8s(L1 * 2 * HIDDEN)?,
+            l1_bias: r.i32s(L1)?,
+            l2_weights: r.i8s(L2 * L1)?,
+            l2_bias: r.i32s(L2)?,
+            out_weights: r.i8s(L2)?,
+            out_bias: r.i32s(1)?[0],
+        };
+
+        let computed = fnv1a(&bytes[..r.pos]);
+        let stored = r.u32()?;
+        if stored != computed {
+            return Err(NetworkError::ChecksumMismatch { stored, computed });
+        }
+        if r.pos != bytes.len() {
+            return Err(NetworkError::TrailingData(bytes.len() - r.pos));
+        }
+        Ok(net)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(FILE_LEN);
+        out.extend_from_slice(MAGIC);
+        for v in [
+            VERSION,
+            ARCHITECTURE_HASH,
+            INPUTS as u32,
+            HIDDEN as u32,
+            L1 as u32,
+            L2 as u32,
+            QA as u32,
+            QB as u32,
+            OUTPUT_SCALE as u32,
+        ] {
+            out.extend_from_slice(&v.to_le_bytes());
+        }
+        self.ft_weights.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        self.ft_bias.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        self.l1_weights.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        self.l1_bias.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        self.l2_weights.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        self.l2_bias.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        self.out_weights.iter().for_each(|w| out.extend_from_slice(&w.to_le_bytes()));
+        out.extend_from_slice(&self.out_bias.to_le_bytes());
+        let checksum = fnv1a(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    // the network compiled in with the embed-net feature, or an all-zero
+    // network that evaluates every position as equal
+    pub fn default_net() -> Network {
+        #[cfg(feature = "embed-net")]
+        {
+            Network::from_bytes(EMBEDDED).unwrap_or_else(|e| panic!("embedded network: {e}"))
+        }
+        #[cfg(not(feature = "embed-net"))]
+        {
+            Network::zeroed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let net = Network::random(3);
+        let bytes = net.to_bytes();
+        assert_eq!(bytes.len(), FILE_LEN);
+        let read = Network::from_bytes(&bytes).unwrap();
+        assert_eq!(read.to_bytes(), bytes);
+        assert_eq!(read.ft_weights, net.ft_weights);
+        assert_eq!(read.out_bias, net.out_bias);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut bytes = Network::random(4).to_bytes();
+        bytes[..4].copy_from_slice(b"NNUE");
+        assert!(matches!(Network::from_bytes(&bytes), Err(NetworkError::BadMagic)));
+        assert!(matches!(Network::from_bytes(b"short"), Err(NetworkError::BadMagic)));
+    }
+
+    #[test]
+    fn rejects_other_architectures() {
+        let mut bytes = Network::random(5).to_bytes();
+        let other = fnv1a(b"HalfKP(40960)->2x256->32->32->1");
+        bytes[8..12].copy_from_slice(&other.to_le_bytes());
+        assert!(matches!(Network::from_bytes(&bytes), Err(NetworkError::ArchitectureMismatch(h)) if h == other));
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = Network::random(6).to_bytes();
+        for len in [HEADER_LEN, HEADER_LEN + 100, bytes.len() - 1] {
+            assert!(matches!(Network::from_bytes(&bytes[..len]), Err(NetworkError::Truncated)), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_files() {
+        let mut bytes = Network::random(8).to_bytes();
+        bytes[HEADER_LEN + 1000] ^= 1;
+        assert!(matches!(Network::from_bytes(&bytes), Err(NetworkError::ChecksumMismatch { .. })));
+    }
+}
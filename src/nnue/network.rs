@@ -0,0 +1,130 @@
+use crate::nnue::{
+    accumulator::Accumulator,
+    features::INPUTS,
+    simd, HIDDEN, L1, L2, OUTPUT_SCALE, QA, QB, QB_SHIFT,
+};
+use crate::defs::Color;
+
+// quantized weights; the feature transformer is stored feature-major so one
+// feature's column is contiguous, the dense layers output-major
+pub struct Network {
+    pub ft_weights: Vec<i16>,
+    pub ft_bias: Vec<i16>,
+    pub l1_weights: Vec<i8>,
+    pub l1_bias: Vec<i32>,
+    pub l2_weights: Vec<i8>,
+    pub l2_bias: Vec<i32>,
+    pub out_weights: Vec<i8>,
+    pub out_bias: i32,
+}
+
+impl Network {
+    #[cfg_attr(feature = "embed-net", allow(dead_code))]
+    pub fn zeroed() -> Network {
+        Network {
+            ft_weights: vec![0; INPUTS * HIDDEN],
+            ft_bias: vec![0; HIDDEN],
+            l1_weights: vec![0; L1 * 2 * HIDDEN],
+            l1_bias: vec![0; L1],
+            l2_weights: vec![0; L2 * L1],
+            l2_bias: vec![0; L2],
+            out_weights: vec![0; L2],
+            out_bias: 0,
+        }
+    }
+
+    // small random weights that keep every layer away from its clipping bounds
+    #[cfg(test)]
+    pub fn random(seed: u64) -> Network {
+        let mut rng = crate::rng::Rng::new(seed);
+        let mut uniform = |n: usize, limit: i32| -> Vec<i32> { (0..n).map(|_| rng.below(2 * limit as usize + 1) as i32 - limit).collect() };
+        let to_i8 = |v: Vec<i32>| v.into_iter().map(|x| x as i8).collect();
+        Network {
+            ft_weights: uniform(INPUTS * HIDDEN, 24).into_iter().map(|x| x as i16).collect(),
+            ft_bias: uniform(HIDDEN, 64).into_iter().map(|x| x as i16 + 48).collect(),
+            l1_weights: to_i8(uniform(L1 * 2 * HIDDEN, 16)),
+            l1_bias: uniform(L1, 4000),
+            l2_weights: to_i8(uniform(L2 * L1, 48)),
+            l2_bias: uniform(L2, 4000),
+            out_weights: to_i8(uniform(L2, 127)),
+            out_bias: uniform(1, 8000)[0],
+        }
+    }
+
+    #[inline(always)]
+    pub fn ft_column(&self, feature: usize) -> &[i16] {
+        &self.ft_weights[feature * HIDDEN..(feature + 1) * HIDDEN]
+    }
+
+    // centipawns from the point of view of `stm`
+    pub fn evaluate(&self, acc: &Accumulator, stm: Color) -> i32 {
+        let mut input = [0u8; 2 * HIDDEN];
+        simd::clipped_relu_i16(&acc.values[stm], &mut input[..HIDDEN]);
+        simd::clipped_relu_i16(&acc.values[stm ^ 1], &mut input[HIDDEN..]);
+
+        let mut hidden1 = [0u8; L1];
+        for (o, out) in hidden1.iter_mut().enumerate() {
+            let weights = &self.l1_weights[o * 2 * HIDDEN..(o + 1) * 2 * HIDDEN];
+            let sum = self.l1_bias[o] + simd::dot_u8_i8(&input, weights);
+            *out = (sum >> QB_SHIFT).clamp(0, QA) as u8;
+        }
+
+        let mut hidden2 = [0u8; L2];
+        for (o, out) in hidden2.iter_mut().enumerate() {
+            let weights = &self.l2_weights[o * L1..(o + 1) * L1];
+            let sum = self.l2_bias[o] + simd::dot_u8_i8(&hidden1, weights);
+            *out = (sum >> QB_SHIFT).clamp(0, QA) as u8;
+        }
+
+        let out = self.out_bias + simd::dot_u8_i8(&hidden2, &self.out_weights);
+        out * OUTPUT_SCALE / (QA * QB)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::Board, moves::with_table, movegen::legal_moves};
+
+    // the forward pass written out with plain integer loops
+    fn scalar_evaluate(net: &Network, acc: &Accumulator, stm: Color) -> i32 {
+        let input: Vec<i32> = acc.values[stm].iter().chain(acc.values[stm ^ 1].iter()).map(|&v| (v as i32).clamp(0, QA)).collect();
+        let layer = |input: &[i32], weights: &[i8], bias: &[i32]| -> Vec<i32> {
+            bias.iter()
+                .enumerate()
+                .map(|(o, b)| {
+                    let sum: i32 = input.iter().zip(&weights[o * input.len()..]).map(|(&x, &w)| x * w as i32).sum();
+                    ((b + sum) >> QB_SHIFT).clamp(0, QA)
+                })
+                .collect()
+        };
+        let hidden1 = layer(&input, &net.l1_weights, &net.l1_bias);
+        let hidden2 = layer(&hidden1, &net.l2_weights, &net.l2_bias);
+        let out = net.out_bias + hidden2.iter().zip(&net.out_weights).map(|(&x, &w)| x * w as i32).sum::<i32>();
+        out * OUTPUT_SCALE / (QA * QB)
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        with_table(|at| {
+            let net = Network::random(7);
+            let mut board = Board::new();
+            board.parse_fen(Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")).unwrap();
+            let mut evals = Vec::new();
+            for &m in legal_moves(&board, at).iter() {
+                let mut b = board;
+                b.make_move(m);
+                let acc = Accumulator::new(&net, &b);
+                for stm in 0..2 {
+                    let v = net.evaluate(&acc, stm);
+                    assert_eq!(v, scalar_evaluate(&net, &acc, stm), "{}", m.as_string());
+                    evals.push(v);
+                }
+            }
+            // a network that ignores the position would pass trivially
+            evals.sort();
+            evals.dedup();
+            assert!(evals.len() > 10);
+        });
+    }
+}
@@ -0,0 +1,153 @@
+// vector kernels for the network, picking AVX2 or SSE4.1 at runtime on x86-64
+// and falling back to plain loops everywhere else
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::nnue::QA;
+
+pub fn add_i16(dst: &mut [i16], src: &[i16]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { add_i16_avx2(dst, src) };
+        }
+        if is_x86_feature_detected!("sse4.1") {
+            return unsafe { add_i16_sse(dst, src) };
+        }
+    }
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = d.wrapping_add(*s);
+    }
+}
+
+pub fn sub_i16(dst: &mut [i16], src: &[i16]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { sub_i16_avx2(dst, src) };
+        }
+        if is_x86_feature_detected!("sse4.1") {
+            return unsafe { sub_i16_sse(dst, src) };
+        }
+    }
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d = d.wrapping_sub(*s);
+    }
+}
+
+// dot product of activations in [0, 127] with signed weights
+pub fn dot_u8_i8(a: &[u8], b: &[i8]) -> i32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if a.len().is_multiple_of(32) && is_x86_feature_detected!("avx2") {
+            return unsafe { dot_u8_i8_avx2(a, b) };
+        }
+        if a.len().is_multiple_of(16) && is_x86_feature_detected!("sse4.1") {
+            return unsafe { dot_u8_i8_sse(a, b) };
+        }
+    }
+    a.iter().zip(b).map(|(&x, &w)| x as i32 * w as i32).sum()
+}
+
+pub fn clipped_relu_i16(src: &[i16], dst: &mut [u8]) {
+    for (d, &s) in dst.iter_mut().zip(src) {
+        *d = (s as i32).clamp(0, QA) as u8;
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn add_i16_avx2(dst: &mut [i16], src: &[i16]) {
+    let chunks = dst.len() / 16;
+    for i in 0..chunks {
+        let d = dst.as_mut_ptr().add(i * 16) as *mut __m256i;
+        let s = src.as_ptr().add(i * 16) as *const __m256i;
+        _mm256_storeu_si256(d, _mm256_add_epi16(_mm256_loadu_si256(d), _mm256_loadu_si256(s)));
+    }
+    for i in chunks * 16..dst.len() {
+        dst[i] = dst[i].wrapping_add(src[i]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn sub_i16_avx2(dst: &mut [i16], src: &[i16]) {
+    let chunks = dst.len() / 16;
+    for i in 0..chunks {
+        let d = dst.as_mut_ptr().add(i * 16) as *mut __m256i;
+        let s = src.as_ptr().add(i * 16) as *const __m256i;
+        _mm256_storeu_si256(d, _mm256_sub_epi16(_mm256_loadu_si256(d), _mm256_loadu_si256(s)));
+    }
+    for i in chunks * 16..dst.len() {
+        dst[i] = dst[i].wrapping_sub(src[i]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn add_i16_sse(dst: &mut [i16], src: &[i16]) {
+    let chunks = dst.len() / 8;
+    for i in 0..chunks {
+        let d = dst.as_mut_ptr().add(i * 8) as *mut __m128i;
+        let s = src.as_ptr().add(i * 8) as *const __m128i;
+        _mm_storeu_si128(d, _mm_add_epi16(_mm_loadu_si128(d), _mm_loadu_si128(s)));
+    }
+    for i in chunks * 8..dst.len() {
+        dst[i] = dst[i].wrapping_add(src[i]);
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn sub_i16_sse(dst: &mut [i16], src: &[i16]) {
+    let chunks = dst.len() / 8;
+    for i in 0..chunks {
+        let d = dst.as_mut_ptr().add(i * 8) as *mut __m128i;
+        let s = src.as_ptr().add(i * 8) as *const __m128i;
+        _mm_storeu_si128(d, _mm_sub_epi16(_mm_loadu_si128(d), _mm_loadu_si128(s)));
+    }
+    for i in chunks * 8..dst.len() {
+        dst[i] = dst[i].wrapping_sub(src[i]);
+    }
+}
+
+// maddubs adds neighbouring u8 * i8 products into i16 lanes; with activations
+// capped at 127 the pair sums can't saturate
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_u8_i8_avx2(a: &[u8], b: &[i8]) -> i32 {
+    let ones = _mm256_set1_epi16(1);
+    let mut sum = _mm256_setzero_si256();
+    for i in 0..a.len() / 32 {
+        let x = _mm256_loadu_si256(a.as_ptr().add(i * 32) as *const __m256i);
+        let w = _mm256_loadu_si256(b.as_ptr().add(i * 32) as *const __m256i);
+        let products = _mm256_madd_epi16(_mm256_maddubs_epi16(x, w), ones);
+        sum = _mm256_add_epi32(sum, products);
+    }
+    let lo = _mm256_castsi256_si128(sum);
+    let hi = _mm256_extracti128_si256(sum, 1);
+    horizontal_sum_sse(_mm_add_epi32(lo, hi))
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn dot_u8_i8_sse(a: &[u8], b: &[i8]) -> i32 {
+    let ones = _mm_set1_epi16(1);
+    let mut sum = _mm_setzero_si128();
+    for i in 0..a.len() / 16 {
+        let x = _mm_loadu_si128(a.as_ptr().add(i * 16) as *const __m128i);
+        let w = _mm_loadu_si128(b.as_ptr().add(i * 16) as *const __m128i);
+        let products = _mm_madd_epi16(_mm_maddubs_epi16(x, w), ones);
+        sum = _mm_add_epi32(sum, products);
+    }
+    horizontal_sum_sse(sum)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.1")]
+unsafe fn horizontal_sum_sse(v: __m128i) -> i32 {
+    let v = _mm_add_epi32(v, _mm_shuffle_epi32(v, 0b01_00_11_10));
+    let v = _mm_add_epi32(v, _mm_shuffle_epi32(v, 0b10_11_00_01));
+    _mm_cvtsi128_si32(v)
+}
@@ -0,0 +1,596 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::thread;
+use std::time::Instant;
+
+use crate::{
+    datagen::packed::{read_records, PackedError, Record},
+    defs::{Color, Colors, NrOf, Pieces},
+    nnue::{
+        features::{active_features, INPUTS},
+        loader::NetworkError,
+        network::Network,
+        HIDDEN, L1, L2, OUTPUT_SCALE, QA, QB,
+    },
+    rng::Rng,
+};
+
+// all float parameters live in one flat vector so the optimizer, the gradient
+// reduction and the checkpoints don't need to know about layers
+const FT_W: usize = 0;
+const FT_B: usize = FT_W + INPUTS * HIDDEN;
+const L1_W: usize = FT_B + HIDDEN;
+const L1_B: usize = L1_W + L1 * 2 * HIDDEN;
+const L2_W: usize = L1_B + L1;
+const L2_B: usize = L2_W + L2 * L1;
+const OUT_W: usize = L2_B + L2;
+const OUT_B: usize = OUT_W + L2;
+const PARAMS: usize = OUT_B + 1;
+
+// hidden weights are stored as i8 scaled by QB
+const WEIGHT_CLIP: f32 = 127.0 / QB as f32;
+const MAX_PIECES: usize = 32;
+
+const CHECKPOINT_MAGIC: &[u8; 4] = b"RCTC";
+const CHECKPOINT_VERSION: u32 = 1;
+
+const BETA1: f32 = 0.9;
+const BETA2: f32 = 0.999;
+const EPSILON: f32 = 1e-8;
+
+#[derive(Debug)]
+pub enum TrainError {
+    Io(String),
+    BadOption(String),
+    BadRecord(usize, String),
+    NoData,
+    BadCheckpoint(String),
+    Network(NetworkError),
+    Data(PackedError),
+}
+
+impl Display for TrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error reading training data: {e}"),
+            Self::BadOption(o) => write!(f, "Error in train options: {o}"),
+            Self::BadRecord(n, e) => write!(f, "Error in training data position {n}: {e}"),
+            Self::NoData => write!(f, "Error in training data: no positions"),
+            Self::BadCheckpoint(e) => write!(f, "Error in checkpoint: {e}"),
+            Self::Network(e) => write!(f, "{e}"),
+            Self::Data(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+pub struct TrainOptions {
+    pub data: String,
+    pub output: String,
+    pub checkpoint: String,
+    pub resume: Option<String>,
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f32,
+    // the learning rate is multiplied by 0.1 after this many epochs
+    pub lr_drop: usize,
+    // weight of the search score against the game result in the target
+    pub lambda: f32,
+    pub threads: usize,
+    pub save_every: usize,
+    pub seed: u64,
+}
+
+impl Default for TrainOptions {
+    fn default() -> TrainOptions {
+        TrainOptions {
+            data: String::new(),
+            output: String::from("net.nnue"),
+            checkpoint: String::from("net.ckpt"),
+            resume: None,
+            epochs: 30,
+            batch_size: 16384,
+            learning_rate: 0.001,
+            lr_drop: 20,
+            lambda: 0.75,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            save_every: 5,
+            seed: 1,
+        }
+    }
+}
+
+impl TrainOptions {
+    // rustChess train <data> [--epochs n] [--batch n] [--lr f] [--lr-drop n] [--lambda f]
+    //   [--threads n] [--out file] [--checkpoint file] [--resume file] [--save-every n] [--seed n]
+    pub fn parse(args: &[String]) -> Result<TrainOptions, TrainError> {
+        let mut options = TrainOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                options.data = arg.clone();
+                continue;
+            }
+            let value = iter.next().ok_or_else(|| TrainError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || TrainError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--epochs" => options.epochs = value.parse().map_err(|_| bad())?,
+                "--batch" => options.batch_size = value.parse().map_err(|_| bad())?,
+                "--lr" => options.learning_rate = value.parse().map_err(|_| bad())?,
+                "--lr-drop" => options.lr_drop = value.parse().map_err(|_| bad())?,
+                "--lambda" => options.lambda = value.parse().map_err(|_| bad())?,
+                "--threads" => options.threads = value.parse().map_err(|_| bad())?,
+                "--out" => options.output = value.clone(),
+                "--checkpoint" => options.checkpoint = value.clone(),
+                "--resume" => options.resume = Some(value.clone()),
+                "--save-every" => options.save_every = value.parse().map_err(|_| bad())?,
+                "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+                _ => return Err(TrainError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.data.is_empty() {
+            return Err(TrainError::BadOption(String::from("no training data given")));
+        }
+        if options.batch_size == 0 || options.threads == 0 || !(0.0..=1.0).contains(&options.lambda) {
+            return Err(TrainError::BadOption(String::from("batch and threads must be positive, lambda in 0..1")));
+        }
+        Ok(options)
+    }
+}
+
+// one position with its features already extracted for the side to move and
+// the other side; score and result are from the side to move's point of view
+struct Sample {
+    features: [[u16; MAX_PIECES]; 2],
+    count: u8,
+    score: f32,
+    result: f32,
+}
+
+fn sample(record: &Record) -> Result<Sample, String> {
+    let board = &record.board;
+    for color in 0..NrOf::COLORS {
+        if board.get_pieces(Pieces::KING as usize, color).count_ones() != 1 {
+            return Err(String::from("each side needs exactly one king"));
+        }
+    }
+    let count = board.get_all_pieces().count_ones() as usize;
+    if count > MAX_PIECES {
+        return Err(String::from("too many pieces"));
+    }
+
+    let stm = board.side_to_move();
+    let (score, result) = (record.score as f32, record.result);
+    let mut sample = Sample { features: [[0; MAX_PIECES]; 2], count: count as u8, score, result };
+    if stm == Colors::BLACK as Color {
+        sample.score = -score;
+        sample.result = 1.0 - result;
+    }
+    let mut features = Vec::with_capacity(MAX_PIECES);
+    for (side, perspective) in [stm, stm ^ 1].into_iter().enumerate() {
+        active_features(board, perspective, &mut features);
+        for (slot, &f) in sample.features[side].iter_mut().zip(features.iter()) {
+            *slot = f as u16;
+        }
+    }
+    Ok(sample)
+}
+
+// text or packed training data, see datagen::packed
+fn load_samples(path: &str) -> Result<Vec<Sample>, TrainError> {
+    let records = read_records(path).map_err(TrainError::Data)?;
+    let mut samples = Vec::with_capacity(records.len());
+    for (n, record) in records.iter().enumerate() {
+        samples.push(sample(record).map_err(|e| TrainError::BadRecord(n + 1, e))?);
+    }
+    if samples.is_empty() {
+        return Err(TrainError::NoData);
+    }
+    Ok(samples)
+}
+
+#[inline(always)]
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+#[inline(always)]
+fn clipped(x: f32) -> f32 {
+    x.clamp(0.0, 1.0)
+}
+
+// derivative of the clipped relu
+#[inline(always)]
+fn passes(x: f32) -> f32 {
+    if x > 0.0 && x < 1.0 { 1.0 } else { 0.0 }
+}
+
+// forward and backward pass for one sample, adding the gradient to `grads`;
+// returns the squared error
+fn backprop(params: &[f32], sample: &Sample, lambda: f32, grads: &mut [f32]) -> f32 {
+    let count = sample.count as usize;
+
+    let mut acc = [0f32; 2 * HIDDEN];
+    for side in 0..2 {
+        let acc = &mut acc[side * HIDDEN..(side + 1) * HIDDEN];
+        acc.copy_from_slice(&params[FT_B..FT_B + HIDDEN]);
+        for &f in &sample.features[side][..count] {
+            let column = &params[FT_W + f as usize * HIDDEN..FT_W + (f as usize + 1) * HIDDEN];
+            acc.iter_mut().zip(column).for_each(|(a, w)| *a += w);
+        }
+    }
+    let input = acc.map(clipped);
+
+    let mut z1 = [0f32; L1];
+    for (o, z) in z1.iter_mut().enumerate() {
+        let weights = &params[L1_W + o * 2 * HIDDEN..L1_W + (o + 1) * 2 * HIDDEN];
+        *z = params[L1_B + o] + weights.iter().zip(&input).map(|(w, x)| w * x).sum::<f32>();
+    }
+    let hidden1 = z1.map(clipped);
+
+    let mut z2 = [0f32; L2];
+    for (o, z) in z2.iter_mut().enumerate() {
+        let weights = &params[L2_W + o * L1..L2_W + (o + 1) * L1];
+        *z = params[L2_B + o] + weights.iter().zip(&hidden1).map(|(w, x)| w * x).sum::<f32>();
+    }
+    let hidden2 = z2.map(clipped);
+
+    let out = params[OUT_B] + params[OUT_W..OUT_W + L2].iter().zip(&hidden2).map(|(w, x)| w * x).sum::<f32>();
+
+    // the network output is in units of OUTPUT_SCALE centipawns, so the score
+    // is squashed with the same scale before mixing it with the result
+    let prediction = sigmoid(out);
+    let target = lambda * sigmoid(sample.score / OUTPUT_SCALE as f32) + (1.0 - lambda) * sample.result;
+    let error = prediction - target;
+
+    let g_out = 2.0 * error * prediction * (1.0 - prediction);
+    grads[OUT_B] += g_out;
+    let mut g_z2 = [0f32; L2];
+    for i in 0..L2 {
+        grads[OUT_W + i] += g_out * hidden2[i];
+        g_z2[i] = g_out * params[OUT_W + i] * passes(z2[i]);
+    }
+
+    let mut g_z1 = [0f32; L1];
+    for o in 0..L2 {
+        let g = g_z2[o];
+        if g == 0.0 {
+            continue;
+        }
+        grads[L2_B + o] += g;
+        for i in 0..L1 {
+            grads[L2_W + o * L1 + i] += g * hidden1[i];
+            g_z1[i] += g * params[L2_W + o * L1 + i];
+        }
+    }
+    for i in 0..L1 {
+        g_z1[i] *= passes(z1[i]);
+    }
+
+    let mut g_acc = [0f32; 2 * HIDDEN];
+    for o in 0..L1 {
+        let g = g_z1[o];
+        if g == 0.0 {
+            continue;
+        }
+        grads[L1_B + o] += g;
+        for i in 0..2 * HIDDEN {
+            grads[L1_W + o * 2 * HIDDEN + i] += g * input[i];
+            g_acc[i] += g * params[L1_W + o * 2 * HIDDEN + i];
+        }
+    }
+    for i in 0..2 * HIDDEN {
+        g_acc[i] *= passes(acc[i]);
+    }
+
+    for side in 0..2 {
+        let g_side = &g_acc[side * HIDDEN..(side + 1) * HIDDEN];
+        grads[FT_B..FT_B + HIDDEN].iter_mut().zip(g_side).for_each(|(g, d)| *g += d);
+        for &f in &sample.features[side][..count] {
+            let column = &mut grads[FT_W + f as usize * HIDDEN..FT_W + (f as usize + 1) * HIDDEN];
+            column.iter_mut().zip(g_side).for_each(|(g, d)| *g += d);
+        }
+    }
+
+    error * error
+}
+
+struct Adam {
+    m: Vec<f32>,
+    v: Vec<f32>,
+    step: u64,
+}
+
+impl Adam {
+    fn new() -> Adam {
+        Adam { m: vec![0.0; PARAMS], v: vec![0.0; PARAMS], step: 0 }
+    }
+
+    fn update(&mut self, params: &mut [f32], grads: &[f32], learning_rate: f32) {
+        self.step += 1;
+        let correction1 = 1.0 - BETA1.powi(self.step as i32);
+        let correction2 = 1.0 - BETA2.powi(self.step as i32);
+        for i in 0..PARAMS {
+            self.m[i] = BETA1 * self.m[i] + (1.0 - BETA1) * grads[i];
+            self.v[i] = BETA2 * self.v[i] + (1.0 - BETA2) * grads[i] * grads[i];
+            let m = self.m[i] / correction1;
+            let v = self.v[i] / correction2;
+            params[i] -= learning_rate * m / (v.sqrt() + EPSILON);
+        }
+        // keep the dense layer weights representable as i8, the biases are i32
+        for range in [L1_W..L1_B, L2_W..L2_B, OUT_W..OUT_B] {
+            for p in &mut params[range] {
+                *p = p.clamp(-WEIGHT_CLIP, WEIGHT_CLIP);
+            }
+        }
+    }
+}
+
+fn init_params(seed: u64) -> Vec<f32> {
+    let mut rng = Rng::new(seed);
+    let mut params = vec![0.0; PARAMS];
+    let mut uniform = |range: std::ops::Range<usize>, limit: f32| {
+        for p in &mut params[range] {
+            *p = (rng.next_f32() * 2.0 - 1.0) * limit;
+        }
+    };
+    uniform(FT_W..FT_B, 0.1);
+    uniform(L1_W..L1_B, 1.0 / ((2 * HIDDEN) as f32).sqrt());
+    uniform(L2_W..L2_B, 1.0 / (L1 as f32).sqrt());
+    uniform(OUT_W..OUT_B, 1.0 / (L2 as f32).sqrt());
+    params
+}
+
+// checkpoint layout: magic "RCTC", version u32, parameter count u32, epochs done u32,
+// adam step u64, then parameters, first and second moments as f32, little-endian
+fn save_checkpoint(path: &str, params: &[f32], adam: &Adam, epoch: usize) -> Result<(), TrainError> {
+    let mut out = Vec::with_capacity(24 + 12 * PARAMS);
+    out.extend_from_slice(CHECKPOINT_MAGIC);
+    out.extend_from_slice(&CHECKPOINT_VERSION.to_le_bytes());
+    out.extend_from_slice(&(PARAMS as u32).to_le_bytes());
+    out.extend_from_slice(&(epoch as u32).to_le_bytes());
+    out.extend_from_slice(&adam.step.to_le_bytes());
+    for values in [params, &adam.m, &adam.v] {
+        values.iter().for_each(|v| out.extend_from_slice(&v.to_le_bytes()));
+    }
+    fs::write(path, out).map_err(|e| TrainError::Io(format!("{path}: {e}")))
+}
+
+fn load_checkpoint(path: &str) -> Result<(Vec<f32>, Adam, usize), TrainError> {
+    let bytes = fs::read(path).map_err(|e| TrainError::Io(format!("{path}: {e}")))?;
+    if bytes.len() != 24 + 12 * PARAMS || &bytes[..4] != CHECKPOINT_MAGIC {
+        return Err(TrainError::BadCheckpoint(format!("{path} is not a checkpoint for this network")));
+    }
+    let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    if u32_at(4) != CHECKPOINT_VERSION || u32_at(8) != PARAMS as u32 {
+        return Err(TrainError::BadCheckpoint(format!("{path} has a different version or layout")));
+    }
+    let epoch = u32_at(12) as usize;
+    let mut step = [0u8; 8];
+    step.copy_from_slice(&bytes[16..24]);
+    let floats: Vec<f32> =
+        bytes[24..].chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+    let adam = Adam {
+        m: floats[PARAMS..2 * PARAMS].to_vec(),
+        v: floats[2 * PARAMS..].to_vec(),
+        step: u64::from_le_bytes(step),
+    };
+    Ok((floats[..PARAMS].to_vec(), adam, epoch))
+}
+
+// rounds the float parameters onto the engine's fixed point scales
+pub fn quantize(params: &[f32]) -> Network {
+    let scale_i16 = |values: &[f32]| -> Vec<i16> {
+        values.iter().map(|v| (v * QA as f32).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16).collect()
+    };
+    let scale_i8 = |values: &[f32]| -> Vec<i8> {
+        values.iter().map(|v| (v * QB as f32).round().clamp(-127.0, 127.0) as i8).collect()
+    };
+    let scale_i32 = |values: &[f32]| -> Vec<i32> {
+        values.iter().map(|v| (v * (QA * QB) as f32).round() as i32).collect()
+    };
+    Network {
+        ft_weights: scale_i16(&params[FT_W..FT_B]),
+        ft_bias: scale_i16(&params[FT_B..L1_W]),
+        l1_weights: scale_i8(&params[L1_W..L1_B]),
+        l1_bias: scale_i32(&params[L1_B..L2_W]),
+        l2_weights: scale_i8(&params[L2_W..L2_B]),
+        l2_bias: scale_i32(&params[L2_B..OUT_W]),
+        out_weights: scale_i8(&params[OUT_W..OUT_B]),
+        out_bias: scale_i32(&params[OUT_B..])[0],
+    }
+}
+
+pub fn train(options: &TrainOptions) -> Result<(), TrainError> {
+    let mut samples = load_samples(&options.data)?;
+    println!("Loaded {} positions from {}", samples.len(), options.data);
+
+    let (mut params, mut adam, first_epoch) = match &options.resume {
+        Some(path) => {
+            let resumed = load_checkpoint(path)?;
+            println!("Resuming from {path} after epoch {}", resumed.2);
+            resumed
+        }
+        None => (init_params(options.seed), Adam::new(), 0),
+    };
+
+    let mut rng = Rng::new(options.seed ^ first_epoch as u64);
+    let mut grads = vec![vec![0f32; PARAMS]; options.threads];
+
+    for epoch in first_epoch..options.epochs {
+        let started = Instant::now();
+        let learning_rate =
+            if epoch >= options.lr_drop { options.learning_rate * 0.1 } else { options.learning_rate };
+        rng.shuffle(&mut samples);
+
+        let mut total_error = 0.0f64;
+        for batch in samples.chunks(options.batch_size) {
+            let per_thread = batch.len().div_ceil(options.threads);
+            let params_ref = &params;
+            total_error += thread::scope(|s| {
+                let handles: Vec<_> = batch
+                    .chunks(per_thread)
+                    .zip(grads.iter_mut())
+                    .map(|(part, grad)| {
+                        s.spawn(move || {
+                            grad.fill(0.0);
+                            part.iter().map(|sample| backprop(params_ref, sample, options.lambda, grad) as f64).sum::<f64>()
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().expect("trainer thread panicked")).sum::<f64>()
+            });
+
+            let used = batch.len().div_ceil(per_thread);
+            let (first, rest) = grads.split_at_mut(1);
+            let sum = &mut first[0];
+            for grad in &rest[..used - 1] {
+                sum.iter_mut().zip(grad).for_each(|(s, g)| *s += g);
+            }
+            let scale = 1.0 / batch.len() as f32;
+            sum.iter_mut().for_each(|g| *g *= scale);
+            adam.update(&mut params, sum, learning_rate);
+        }
+
+        let seconds = started.elapsed().as_secs_f64();
+        println!(
+            "epoch {:>3}  loss {:.6}  lr {learning_rate}  {:.0} pos/s",
+            epoch + 1,
+            total_error / samples.len() as f64,
+            samples.len() as f64 / seconds.max(1e-9)
+        );
+
+        if (epoch + 1) % options.save_every.max(1) == 0 || epoch + 1 == options.epochs {
+            save_checkpoint(&options.checkpoint, &params, &adam, epoch + 1)?;
+            quantize(&params).save(&options.output).map_err(TrainError::Network)?;
+            println!("Saved {} and {}", options.checkpoint, options.output);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board::Board, nnue::accumulator::Accumulator};
+
+    const FENS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    fn board(fen: &str) -> Board {
+        let mut board = Board::new();
+        board.parse_fen(Some(fen)).unwrap();
+        board
+    }
+
+    fn samples() -> Vec<Sample> {
+        let results = [1.0, 0.5, 0.0, 0.5];
+        FENS.iter()
+            .zip(results)
+            .enumerate()
+            .map(|(i, (fen, result))| sample(&Record { board: board(fen), score: 150 * i as i16 - 200, result }).unwrap())
+            .collect()
+    }
+
+    // the initial parameters with random biases, so every layer has units on
+    // both sides of the clipping bounds
+    fn test_params(seed: u64) -> Vec<f32> {
+        let mut params = init_params(seed);
+        let mut rng = Rng::new(seed + 1);
+        for range in [FT_B..L1_W, L1_B..L2_W, L2_B..OUT_W, OUT_B..PARAMS] {
+            for p in &mut params[range] {
+                *p = rng.next_f32() - 0.3;
+            }
+        }
+        params
+    }
+
+    // the float network's output for the side to move, in centipawns
+    fn float_eval(params: &[f32], sample: &Sample) -> f32 {
+        let count = sample.count as usize;
+        let mut input = Vec::with_capacity(2 * HIDDEN);
+        for side in 0..2 {
+            for j in 0..HIDDEN {
+                let sum: f32 = sample.features[side][..count].iter().map(|&f| params[FT_W + f as usize * HIDDEN + j]).sum();
+                input.push(clipped(params[FT_B + j] + sum));
+            }
+        }
+        let layer = |input: &[f32], weights: usize, bias: usize, outputs: usize| -> Vec<f32> {
+            (0..outputs)
+                .map(|o| {
+                    let w = &params[weights + o * input.len()..weights + (o + 1) * input.len()];
+                    clipped(params[bias + o] + w.iter().zip(input).map(|(w, x)| w * x).sum::<f32>())
+                })
+                .collect()
+        };
+        let hidden1 = layer(&input, L1_W, L1_B, L1);
+        let hidden2 = layer(&hidden1, L2_W, L2_B, L2);
+        let out = params[OUT_B] + params[OUT_W..OUT_B].iter().zip(&hidden2).map(|(w, x)| w * x).sum::<f32>();
+        out * OUTPUT_SCALE as f32
+    }
+
+    #[test]
+    fn gradient_matches_finite_differences() {
+        let samples = samples();
+        let mut params = test_params(11);
+        let loss = |params: &[f32]| -> f32 {
+            let mut scratch = vec![0.0; PARAMS];
+            samples.iter().map(|s| backprop(params, s, 0.5, &mut scratch)).sum()
+        };
+        let mut grads = vec![0.0; PARAMS];
+        for s in &samples {
+            backprop(&params, s, 0.5, &mut grads);
+        }
+
+        // a few entries of every layer; feature weights only move for active features
+        let mut rng = Rng::new(5);
+        let feature = samples[1].features[1][3] as usize;
+        let mut indices: Vec<usize> = (0..8).map(|_| FT_W + feature * HIDDEN + rng.below(HIDDEN)).collect();
+        for range in [FT_B..L1_W, L1_W..L1_B, L1_B..L2_W, L2_W..L2_B, L2_B..OUT_W, OUT_W..OUT_B] {
+            indices.extend((0..8).map(|_| range.start + rng.below(range.len())));
+        }
+        indices.push(OUT_B);
+
+        let eps = 1e-3;
+        let mut nonzero = 0;
+        for i in indices {
+            let saved = params[i];
+            params[i] = saved + eps;
+            let up = loss(&params);
+            params[i] = saved - eps;
+            let down = loss(&params);
+            params[i] = saved;
+            let numeric = (up - down) / (2.0 * eps);
+            assert!((numeric - grads[i]).abs() <= 2e-4 + 0.02 * grads[i].abs(), "parameter {i}: analytic {} numeric {numeric}", grads[i]);
+            nonzero += (grads[i].abs() > 1e-4) as usize;
+        }
+        assert!(nonzero > 20, "only {nonzero} gradients were checked away from zero");
+    }
+
+    #[test]
+    fn quantized_network_tracks_the_float_one() {
+        let params = test_params(12);
+        let net = quantize(&params);
+        for (fen, s) in FENS.iter().zip(samples()) {
+            let board = board(fen);
+            let float = float_eval(&params, &s);
+            let quantized = net.evaluate(&Accumulator::new(&net, &board), board.side_to_move()) as f32;
+            assert!((float - quantized).abs() <= 10.0 + 0.05 * float.abs(), "{fen}: float {float} quantized {quantized}");
+        }
+    }
+
+    #[test]
+    fn adam_leaves_biases_unclipped() {
+        let mut params = vec![0.0; PARAMS];
+        params[L1_B] = 5.0;
+        params[L1_W] = 5.0;
+        params[OUT_B] = -5.0;
+        Adam::new().update(&mut params, &vec![0.0; PARAMS], 0.001);
+        assert_eq!(params[L1_B], 5.0);
+        assert_eq!(params[OUT_B], -5.0);
+        assert_eq!(params[L1_W], WEIGHT_CLIP);
+    }
+}
@@ -0,0 +1,59 @@
+pub mod reader;
+pub mod writer;
+
+use crate::{
+    board::Board,
+    movegen::defs::Move,
+};
+
+// a move with everything PGN can attach to it. variations are alternatives
+// to this move, each starting from the position before it
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub mv: Move,
+    // comments between the previous move and this one
+    pub before: Vec<String>,
+    pub comments: Vec<String>,
+    pub nags: Vec<u8>,
+    pub variations: Vec<Vec<Node>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Game {
+    // in the order they were read
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<Node>,
+    // comments of a game without moves
+    pub comments: Vec<String>,
+    pub result: String,
+}
+
+impl Game {
+    pub fn new(start: Board) -> Game {
+        Game { tags: Vec::new(), start, moves: Vec::new(), comments: Vec::new(), result: String::from("*") }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    // replaces the value of an existing tag or adds a new one
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // every position of the main line with the move played from it
+    pub fn mainline(&self) -> Vec<(Board, Move)> {
+        let mut board = self.start;
+        let mut positions = Vec::with_capacity(self.moves.len());
+        for node in &self.moves {
+            positions.push((board, node.mv));
+            board.make_move(node.mv);
+        }
+        positions
+    }
+}
@@ -0,0 +1,504 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::mem;
+
+use crate::{
+    board::Board,
+    movegen::san::parse_san,
+    moves::AttackTable,
+    pgn::{Game, Node},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error in PGN line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+enum Token {
+    // a tag pair starts, which ends any movetext before it. not consumed
+    TagStart,
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Nag(u8),
+    MoveNumber,
+    Result(String),
+    San(String),
+    End,
+}
+
+// one level of the movetext: the main line or a variation being read
+struct Line {
+    nodes: Vec<Node>,
+    board: Board,
+    // position before the last move, where a variation on it starts
+    before_last: Board,
+    // comments waiting for the next move
+    pending: Vec<String>,
+}
+
+impl Line {
+    fn new(board: Board) -> Line {
+        Line { nodes: Vec::new(), board, before_last: board, pending: Vec::new() }
+    }
+}
+
+// reads games one at a time from any buffered input, so files of any size
+// can be walked without holding more than the current game in memory. after
+// an error the rest of the broken game is skipped and reading carries on
+// with the next one
+pub struct PgnReader<'a, R: BufRead> {
+    input: R,
+    at: &'a AttackTable,
+    line: usize,
+    column: usize,
+    // whether the game being read got past its tags
+    in_movetext: bool,
+}
+
+impl<'a> PgnReader<'a, BufReader<File>> {
+    pub fn open(path: &str, at: &'a AttackTable) -> io::Result<Self> {
+        Ok(PgnReader::new(BufReader::new(File::open(path)?), at))
+    }
+}
+
+impl<'a, R: BufRead> PgnReader<'a, R> {
+    pub fn new(input: R, at: &'a AttackTable) -> Self {
+        PgnReader { input, at, line: 1, column: 1, in_movetext: false }
+    }
+
+    fn error(&self, line: usize, column: usize, message: String) -> PgnError {
+        PgnError { line, column, message }
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>, PgnError> {
+        loop {
+            match self.input.fill_buf() {
+                Ok(buf) => return Ok(buf.first().copied()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(self.error(self.line, self.column, e.to_string())),
+            }
+        }
+    }
+
+    fn bump(&mut self) -> Result<Option<u8>, PgnError> {
+        let byte = self.peek()?;
+        if let Some(b) = byte {
+            self.input.consume(1);
+            self.advance(&[b]);
+        }
+        Ok(byte)
+    }
+
+    // columns count characters, so utf-8 continuation bytes are not counted
+    fn advance(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if b == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            } else if b & 0xc0 != 0x80 {
+                self.column += 1;
+            }
+        }
+    }
+
+    fn skip_line(&mut self) -> Result<(), PgnError> {
+        while let Some(b) = self.bump()? {
+            if b == b'\n' {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // whitespace, a byte order mark and % escape lines
+    fn skip_space(&mut self) -> Result<(), PgnError> {
+        while let Some(b) = self.peek()? {
+            if b == b'%' && self.column == 1 {
+                self.skip_line()?;
+            } else if b.is_ascii_whitespace() || (self.line == 1 && matches!(b, 0xef | 0xbb | 0xbf)) {
+                self.bump()?;
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // works on whole buffers rather than byte by byte, as most of the input
+    // goes through here
+    fn read_while(&mut self, keep: impl Fn(u8) -> bool) -> Result<Vec<u8>, PgnError> {
+        let mut bytes = Vec::new();
+        loop {
+            let buf = match self.input.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(self.error(self.line, self.column, e.to_string())),
+            };
+            let n = buf.iter().position(|&b| !keep(b)).unwrap_or(buf.len());
+            let done = n < buf.len() || buf.is_empty();
+            bytes.extend_from_slice(&buf[..n]);
+            self.input.consume(n);
+            self.advance(&bytes[bytes.len() - n..]);
+            if done {
+                return Ok(bytes);
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<(Token, usize, usize), PgnError> {
+        self.skip_space()?;
+        let (line, column) = (self.line, self.column);
+        let Some(b) = self.peek()? else {
+            return Ok((Token::End, line, column));
+        };
+        let token = match b {
+            b'[' => Token::TagStart,
+            b'{' => {
+                self.bump()?;
+                let text = self.read_while(|b| b != b'}')?;
+                if self.bump()?.is_none() {
+                    return Err(self.error(line, column, String::from("unterminated comment")));
+                }
+                // line breaks in a comment are only wrapping
+                Token::Comment(text_of(&text).split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            b';' => {
+                self.bump()?;
+                let text = self.read_while(|b| b != b'\n')?;
+                Token::Comment(text_of(&text).trim().to_string())
+            }
+            b'(' => {
+                self.bump()?;
+                Token::VariationStart
+            }
+            b')' => {
+                self.bump()?;
+                Token::VariationEnd
+            }
+            b'*' => {
+                self.bump()?;
+                Token::Result(String::from("*"))
+            }
+            b'$' => {
+                self.bump()?;
+                let digits = text_of(&self.read_while(|b| b.is_ascii_digit())?);
+                match digits.parse() {
+                    Ok(nag) => Token::Nag(nag),
+                    Err(_) => return Err(self.error(line, column, format!("bad NAG ${digits}"))),
+                }
+            }
+            b'0'..=b'9' => {
+                // a move number such as 12. or 12... right before its move,
+                // otherwise a result or castling written with zeros
+                let mut symbol = self.read_while(|b| b.is_ascii_digit())?;
+                if self.peek()? == Some(b'.') {
+                    self.read_while(|b| b == b'.')?;
+                    Token::MoveNumber
+                } else {
+                    symbol.extend(self.read_while(is_symbol)?);
+                    let symbol = text_of(&symbol);
+                    match symbol.as_str() {
+                        "1-0" | "0-1" | "1/2-1/2" => Token::Result(symbol),
+                        _ => Token::San(symbol),
+                    }
+                }
+            }
+            b if is_symbol(b) => Token::San(text_of(&self.read_while(is_symbol)?)),
+            _ => {
+                let found = text_of(&self.read_while(|b| !b.is_ascii_whitespace())?);
+                return Err(self.error(line, column, format!("unexpected {found}")));
+            }
+        };
+        Ok((token, line, column))
+    }
+
+    // [Name "value"], with \" and \\ escaped in the value
+    fn read_tag(&mut self) -> Result<(String, String), PgnError> {
+        let (line, column) = (self.line, self.column);
+        let bad = |reader: &Self, message: &str| reader.error(line, column, format!("bad tag pair: {message}"));
+        self.bump()?;
+        self.skip_space()?;
+        let name = text_of(&self.read_while(|b| b.is_ascii_alphanumeric() || b == b'_')?);
+        if name.is_empty() {
+            return Err(bad(self, "missing name"));
+        }
+        self.skip_space()?;
+        if self.bump()? != Some(b'"') {
+            return Err(bad(self, "value must be quoted"));
+        }
+        let mut value = Vec::new();
+        loop {
+            match self.bump()? {
+                Some(b'\\') => match self.bump()? {
+                    Some(b) if b != b'\n' => value.push(b),
+                    _ => return Err(bad(self, "unterminated value")),
+                },
+                Some(b'"') => break,
+                Some(b'\n') | None => return Err(bad(self, "unterminated value")),
+                Some(b) => value.push(b),
+            }
+        }
+        self.skip_space()?;
+        if self.bump()? != Some(b']') {
+            return Err(bad(self, "missing ]"));
+        }
+        Ok((name, text_of(&value)))
+    }
+
+    fn read_game(&mut self) -> Result<Game, PgnError> {
+        self.in_movetext = false;
+        let mut tags = Vec::new();
+        let mut fen_at = (0, 0);
+        loop {
+            self.skip_space()?;
+            match self.peek()? {
+                Some(b'[') => {
+                    let position = (self.line, self.column);
+                    let (name, value) = self.read_tag()?;
+                    if name == "FEN" {
+                        fen_at = position;
+                    }
+                    tags.push((name, value));
+                }
+                // comments before the tags belong to no game
+                Some(b'{' | b';') if tags.is_empty() => {
+                    self.next_token()?;
+                }
+                _ => break,
+            }
+        }
+
+        // SetUp is often missing when FEN is there, so FEN alone is enough
+        let mut start = Board::new();
+        let fen = tags.iter().find(|(n, _)| n == "FEN").map(|(_, v)| v.as_str());
+        if let Err(e) = start.parse_fen(fen) {
+            return Err(self.error(fen_at.0, fen_at.1, e.to_string()));
+        }
+        let mut game = Game::new(start);
+        game.tags = tags;
+        if let Some(result) = game.tag("Result") {
+            game.result = result.to_string();
+        }
+
+        self.in_movetext = true;
+        let mut stack = vec![Line::new(start)];
+        loop {
+            let (token, line, column) = self.next_token()?;
+            let top = stack.last_mut().expect("the main line is never popped");
+            match token {
+                Token::TagStart | Token::End => {
+                    if stack.len() > 1 {
+                        return Err(self.error(line, column, String::from("unterminated variation")));
+                    }
+                    break;
+                }
+                Token::Comment(c) => match top.nodes.last_mut() {
+                    Some(node) => node.comments.push(c),
+                    None => top.pending.push(c),
+                },
+                Token::Nag(nag) => match top.nodes.last_mut() {
+                    Some(node) => node.nags.push(nag),
+                    None => return Err(self.error(line, column, format!("${nag} before any move"))),
+                },
+                Token::VariationStart => {
+                    if top.nodes.is_empty() {
+                        return Err(self.error(line, column, String::from("variation before any move")));
+                    }
+                    let board = top.before_last;
+                    stack.push(Line::new(board));
+                }
+                Token::VariationEnd => {
+                    if stack.len() == 1 {
+                        return Err(self.error(line, column, String::from("unmatched )")));
+                    }
+                    let variation = stack.pop().expect("checked above");
+                    let parent = stack.last_mut().expect("checked above");
+                    if !variation.nodes.is_empty() {
+                        parent.nodes.last_mut().expect("checked on (").variations.push(variation.nodes);
+                    }
+                }
+                Token::MoveNumber => (),
+                Token::Result(result) => {
+                    if stack.len() > 1 {
+                        return Err(self.error(line, column, String::from("result inside a variation")));
+                    }
+                    game.result = result;
+                    break;
+                }
+                // e.p. after an en passant capture separated by a space
+                Token::San(san) if san == "e.p." => (),
+                Token::San(san) => {
+                    let glyphs = san.len() - san.trim_end_matches(['!', '?']).len();
+                    let (san, glyph) = san.split_at(san.len() - glyphs);
+                    if !san.is_empty() {
+                        let m = parse_san(&top.board, self.at, san).map_err(|e| self.error(line, column, e.to_string()))?;
+                        top.before_last = top.board;
+                        top.board.make_move(m);
+                        let before = mem::take(&mut top.pending);
+                        top.nodes.push(Node { mv: m, before, ..Node::default() });
+                    }
+                    if let Some(nag) = glyph_nag(glyph) {
+                        match top.nodes.last_mut() {
+                            Some(node) => node.nags.push(nag),
+                            None => return Err(self.error(line, column, format!("{glyph} before any move"))),
+                        }
+                    }
+                }
+            }
+        }
+
+        let main = stack.pop().expect("the main line is never popped");
+        match main.nodes.is_empty() {
+            true => game.comments = main.pending,
+            false => game.moves = main.nodes,
+        }
+        Ok(game)
+    }
+
+    // skips to the tags of the next game. a line starting with [ only counts
+    // once some movetext has gone by, so a bad tag does not split its game
+    fn skip_game(&mut self, mut seen_movetext: bool) -> Result<(), PgnError> {
+        if self.column != 1 {
+            self.skip_line()?;
+        }
+        loop {
+            match self.peek()? {
+                None => return Ok(()),
+                Some(b'[') if seen_movetext => return Ok(()),
+                Some(b'[') => (),
+                Some(_) => {
+                    let text = self.read_while(|b| b != b'\n')?;
+                    seen_movetext |= text.iter().any(|b| !b.is_ascii_whitespace());
+                }
+            }
+            self.skip_line()?;
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<'_, R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.skip_space() {
+            return Some(Err(e));
+        }
+        match self.peek() {
+            Ok(None) => return None,
+            Ok(Some(_)) => (),
+            Err(e) => return Some(Err(e)),
+        }
+        let game = self.read_game();
+        if game.is_err() {
+            if let Err(e) = self.skip_game(self.in_movetext) {
+                return Some(Err(e));
+            }
+        }
+        Some(game)
+    }
+}
+
+fn is_symbol(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"_+#=:-/!?.@".contains(&b)
+}
+
+fn text_of(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+// the numeric annotation glyph for a suffix such as !? after a move
+fn glyph_nag(glyph: &str) -> Option<u8> {
+    match glyph {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::with_table;
+
+    fn read_all(text: &str, at: &AttackTable) -> Vec<Result<Game, PgnError>> {
+        PgnReader::new(text.as_bytes(), at).collect()
+    }
+
+    fn error_at(text: &str, at: &AttackTable) -> (usize, usize) {
+        let error = read_all(text, at).remove(0).unwrap_err();
+        (error.line, error.column)
+    }
+
+    #[test]
+    fn reads_a_game_tree() {
+        with_table(|at| {
+            let text = "[Event \"Test\"]\n[White \"A\"]\n[Result \"1-0\"]\n\n\
+                        {opening} 1. e4 e5 (1... c5 2. Nf3 $14 (2. c3) ; open sicilian\n2... d6) 2. Nf3!? Nc6 $2 1-0\n";
+            let game = read_all(text, at).remove(0).unwrap();
+            assert_eq!(game.tag("White"), Some("A"));
+            assert_eq!(game.result, "1-0");
+            assert_eq!(game.moves.len(), 4);
+            assert_eq!(game.moves[0].before, vec![String::from("opening")]);
+            let sicilian = &game.moves[1].variations[0];
+            assert_eq!(sicilian.len(), 3);
+            assert_eq!(sicilian[1].nags, vec![14]);
+            assert_eq!(sicilian[1].variations[0].len(), 1);
+            assert_eq!(sicilian[1].comments, vec![String::from("open sicilian")]);
+            assert_eq!(game.moves[2].nags, vec![5]);
+            assert_eq!(game.moves[3].nags, vec![2]);
+        });
+    }
+
+    #[test]
+    fn reads_a_fen_start() {
+        with_table(|at| {
+            let text = "[SetUp \"1\"]\n[FEN \"4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1\"]\n\n1. exd6 e.p. Kd7 *\n";
+            let game = read_all(text, at).remove(0).unwrap();
+            assert_eq!(game.moves.len(), 2);
+            assert!(game.moves[0].mv.is_en_passant());
+            assert_eq!(game.result, "*");
+        });
+    }
+
+    #[test]
+    fn errors_carry_line_and_column() {
+        with_table(|at| {
+            assert_eq!(error_at("[Event \"a\"]\n\n1. e4 e5 2. Ke3 *\n", at), (3, 13));
+            assert_eq!(error_at("[Event \"a]\n\n1. e4 *\n", at), (1, 1));
+            assert_eq!(error_at("\n  [Event \"a\"]\n[FEN \"8/8 w\"]\n\n*\n", at), (3, 1));
+            assert_eq!(error_at("1. e4 e5\n2. Nf3 {never closed\n", at), (2, 8));
+            assert_eq!(error_at("1. e4 e5 ) *\n", at), (1, 10));
+            assert_eq!(error_at("1. e4 (1. d4 *\n", at), (1, 14));
+            assert_eq!(error_at("1. e4 e5 2. Nf3 $x *\n", at), (1, 17));
+            let error = read_all("1. e4 e5 2. Ke3 *\n", at).remove(0).unwrap_err();
+            assert_eq!(error.to_string(), "Error in PGN line 1, column 13: Error in move Ke3: illegal in this position");
+        });
+    }
+
+    #[test]
+    fn carries_on_after_a_broken_game() {
+        with_table(|at| {
+            let text = "[Event \"1\"]\n\n1. e4 e5 2. Ke3 Nc6 *\n\n[Event \"2\"]\n\n1. d4 d5 *\n";
+            let games = read_all(text, at);
+            assert_eq!(games.len(), 2);
+            assert!(games[0].is_err());
+            let second = games[1].as_ref().unwrap();
+            assert_eq!(second.tag("Event"), Some("2"));
+            assert_eq!(second.moves.len(), 2);
+        });
+    }
+}
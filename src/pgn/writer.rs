@@ -0,0 +1,309 @@
+use std::io::{self, Write};
+
+use crate::{
+    board::Board,
+    defs::FEN_START_POSITION,
+    movegen::san::move_to_san,
+    moves::AttackTable,
+    pgn::{Game, Node},
+};
+
+pub const LINE_LENGTH: usize = 80;
+
+// the seven tag roster, always written first and in this order
+pub const ROSTER: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+// what goes into the movetext besides the moves
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    pub variations: bool,
+    pub comments: bool,
+    pub nags: bool,
+    // commands such as [%clk 0:01:00] and [%eval 0.25] inside comments
+    pub annotations: bool,
+    // only the seven tag roster, and SetUp/FEN when needed
+    pub reduced_tags: bool,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions { variations: true, comments: true, nags: true, annotations: true, reduced_tags: false }
+    }
+}
+
+impl ExportOptions {
+    // the reduced export format: roster tags and bare moves. SetUp and FEN are
+    // kept for games not starting from the initial position, which would be
+    // unreadable without them
+    pub fn reduced() -> ExportOptions {
+        ExportOptions { variations: false, comments: false, nags: false, annotations: false, reduced_tags: true }
+    }
+
+    // "export" or "reduced", as given to --pgn-format
+    pub fn from_name(name: &str) -> Option<ExportOptions> {
+        match name {
+            "export" => Some(ExportOptions::default()),
+            "reduced" => Some(ExportOptions::reduced()),
+            _ => None,
+        }
+    }
+}
+
+// writes a game in export format: roster tags with ? for missing ones, SetUp
+// and FEN, then the rest sorted by name, and movetext wrapped at 80 columns with SAN made
+// from the position rather than copied from the input
+pub fn write_game(out: &mut impl Write, game: &Game, at: &AttackTable, options: &ExportOptions) -> io::Result<()> {
+    for name in ROSTER {
+        let value = match (name, game.tag(name)) {
+            ("Result", _) => game.result.as_str(),
+            (_, Some(value)) => value,
+            ("Date", None) => "????.??.??",
+            (_, None) => "?",
+        };
+        write_tag(out, name, value)?;
+    }
+
+    // SetUp and FEN come from the start position, whatever the tags said
+    let fen = game.start.as_fen();
+    if fen != FEN_START_POSITION {
+        write_tag(out, "SetUp", "1")?;
+        write_tag(out, "FEN", &fen)?;
+    }
+    if !options.reduced_tags {
+        let mut others: Vec<&(String, String)> =
+            game.tags.iter().filter(|(n, _)| !ROSTER.contains(&n.as_str()) && n != "SetUp" && n != "FEN").collect();
+        others.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, value) in others {
+            write_tag(out, name, value)?;
+        }
+    }
+    writeln!(out)?;
+
+    let mut tokens = Tokens::default();
+    write_line(&mut tokens, game.start, &game.moves, at, options);
+    for comment in &game.comments {
+        tokens.comment(comment, options);
+    }
+    tokens.push(game.result.clone());
+
+    let mut line = String::new();
+    for token in tokens.list {
+        if !line.is_empty() && line.len() + 1 + token.len() > LINE_LENGTH {
+            writeln!(out, "{line}")?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(out, "{line}")?;
+    writeln!(out)
+}
+
+fn write_tag(out: &mut impl Write, name: &str, value: &str) -> io::Result<()> {
+    writeln!(out, "[{name} \"{}\"]", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// movetext split where a line may be broken
+#[derive(Default)]
+struct Tokens {
+    list: Vec<String>,
+    // a variation was opened, so the next token starts with (
+    open: bool,
+}
+
+impl Tokens {
+    fn push(&mut self, token: String) {
+        if self.open {
+            self.list.push(format!("({token}"));
+            self.open = false;
+        } else {
+            self.list.push(token);
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(last) = self.list.last_mut() {
+            last.push(')');
+        }
+    }
+
+    // false if nothing was left of the comment to write
+    fn comment(&mut self, text: &str, options: &ExportOptions) -> bool {
+        let text = comment_text(text, options);
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.is_empty() {
+            return false;
+        }
+        // braces cannot be escaped inside a comment
+        let last = words.len() - 1;
+        for (i, word) in words.iter().enumerate() {
+            let mut token = word.replace('}', ")");
+            if i == 0 {
+                token.insert(0, '{');
+            }
+            if i == last {
+                token.push('}');
+            }
+            self.push(token);
+        }
+        true
+    }
+}
+
+// a comment with its text or its [%...] commands left out as asked
+fn comment_text(text: &str, options: &ExportOptions) -> String {
+    let mut kept = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("[%") {
+        let end = rest[start..].find(']').map_or(rest.len(), |e| start + e + 1);
+        if options.comments {
+            kept.push_str(&rest[..start]);
+        }
+        if options.annotations {
+            kept.push(' ');
+            kept.push_str(&rest[start..end]);
+            kept.push(' ');
+        }
+        rest = &rest[end..];
+    }
+    if options.comments {
+        kept.push_str(rest);
+    }
+    kept
+}
+
+// a line of moves from `board`. black's moves carry their number when they
+// open the line or follow a comment or variation
+fn write_line(tokens: &mut Tokens, mut board: Board, nodes: &[Node], at: &AttackTable, options: &ExportOptions) {
+    let mut numbered = true;
+    for node in nodes {
+        for comment in &node.before {
+            numbered |= tokens.comment(comment, options);
+        }
+        let san = move_to_san(&board, at, node.mv);
+        match (board.turn, numbered) {
+            (true, _) => tokens.push(format!("{}. {san}", board.full_move)),
+            (false, true) => tokens.push(format!("{}... {san}", board.full_move)),
+            (false, false) => tokens.push(san),
+        }
+        numbered = false;
+
+        if options.nags {
+            for nag in &node.nags {
+                tokens.push(format!("${nag}"));
+            }
+        }
+        for comment in &node.comments {
+            numbered |= tokens.comment(comment, options);
+        }
+        if options.variations {
+            for variation in node.variations.iter().filter(|v| !v.is_empty()) {
+                tokens.open = true;
+                write_line(tokens, board, variation, at, options);
+                tokens.close();
+                numbered = true;
+            }
+        }
+        board.make_move(node.mv);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{moves::with_table, pgn::reader::PgnReader};
+
+    const GAMES: &str = "[Event \"Round trip\"]\n[Annotator \"Someone\"]\n[White \"A\"]\n[Black \"B\"]\n[Result \"1-0\"]\n\n\
+        1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. 0-0 {castles [%clk 0:01:00]} Nf6!? (4... d6 5. c3 $1 (5. d4) 5... Bg4)\n\
+        5. d4 exd4 6. e5 d5 7. exd6 e.p. {a comment long enough that the movetext has to be wrapped more than once \
+        to fit in eighty columns} Bxd6 8. Re1+ Be6 9. Ng5 Qd7?? 1-0\n\n\
+        [SetUp \"1\"]\n[FEN \"8/P7/8/8/8/8/k7/4K3 w - - 0 1\"]\n\n1. a8Q+ Kb2 *\n";
+
+    fn read(text: &str, at: &AttackTable) -> Vec<Game> {
+        PgnReader::new(text.as_bytes(), at).map(|game| game.unwrap()).collect()
+    }
+
+    fn write(games: &[Game], at: &AttackTable, options: &ExportOptions) -> String {
+        let mut out = Vec::new();
+        for game in games {
+            write_game(&mut out, game, at, options).unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_the_reader() {
+        with_table(|at| {
+            let games = read(GAMES, at);
+            let text = write(&games, at, &ExportOptions::default());
+            let movetext = "1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. O-O {castles [%clk 0:01:00]} 4... Nf6 $5\n\
+                            (4... d6 5. c3 $1 (5. d4) 5... Bg4) 5. d4 exd4 6. e5 d5 7. exd6 {a comment long\n\
+                            enough that the movetext has to be wrapped more than once to fit in eighty\n\
+                            columns} 7... Bxd6 8. Re1+ Be6 9. Ng5 Qd7 $4 1-0\n";
+            assert!(text.contains(movetext));
+            assert!(text.lines().all(|line| line.len() <= LINE_LENGTH));
+            assert!(text.contains("[FEN \"8/P7/8/8/8/8/k7/4K3 w - - 0 1\"]\n\n1. a8=Q+ Kb2 *\n"));
+
+            let again = read(&text, at);
+            assert_eq!(write(&again, at, &ExportOptions::default()), text);
+            assert_eq!(again[0].moves.len(), games[0].moves.len());
+            assert!(again[0].moves[12].mv.is_en_passant());
+            assert_eq!(again[0].moves[7].variations[0][1].variations.len(), 1);
+        });
+    }
+
+    #[test]
+    fn round_trips_crazyhouse_drops() {
+        with_table(|at| {
+            let text = "[SetUp \"1\"]\n[FEN \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1\"]\n\n\
+                        1. e4 d5 2. exd5 Qxd5 3. Nc3 Qa5 4. Bc4 Qxc3 5. bxc3 N@d4 6. @d5 @e4 *\n";
+            let games = read(text, at);
+            assert_eq!(games[0].moves.len(), 12);
+            assert!(games[0].moves[9..].iter().all(|node| node.mv.is_drop()));
+
+            let written = write(&games, at, &ExportOptions::default());
+            assert!(written.contains("5. bxc3 N@d4 6. @d5 @e4 *\n"));
+            assert_eq!(write(&read(&written, at), at, &ExportOptions::default()), written);
+        });
+    }
+
+    #[test]
+    fn writes_tags_in_export_order() {
+        with_table(|at| {
+            let text = write(&read(GAMES, at)[..1], at, &ExportOptions::default());
+            let tags: Vec<&str> = text.lines().take_while(|line| line.starts_with('[')).collect();
+            assert_eq!(
+                tags,
+                [
+                    "[Event \"Round trip\"]",
+                    "[Site \"?\"]",
+                    "[Date \"????.??.??\"]",
+                    "[Round \"?\"]",
+                    "[White \"A\"]",
+                    "[Black \"B\"]",
+                    "[Result \"1-0\"]",
+                    "[Annotator \"Someone\"]",
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn writes_reduced_export() {
+        with_table(|at| {
+            let games = read(GAMES, at);
+            let text = write(&games, at, &ExportOptions::reduced());
+            assert!(!text.contains("Annotator") && !text.contains('{') && !text.contains('(') && !text.contains('$'));
+            assert!(text.contains("[SetUp \"1\"]"));
+            let again = read(&text, at);
+            assert_eq!(again[0].moves.len(), games[0].moves.len());
+            assert!(again[0].moves.iter().all(|node| node.variations.is_empty() && node.nags.is_empty()));
+
+            let clocks_only = ExportOptions { comments: false, ..ExportOptions::default() };
+            let text = write(&games, at, &clocks_only);
+            assert!(text.contains("4. O-O {[%clk 0:01:00]}") && !text.contains("castles") && !text.contains("eighty"));
+        });
+    }
+}
@@ -0,0 +1,536 @@
+pub mod defs;
+pub mod tt;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::{
+    board::Board,
+    defs::{NrOf, Pieces, EMPTY},
+    evaluation::{evaluate_with, params::{EvalParams, DEFAULT_PARAMS}},
+    movegen::{
+        defs::{Move, MoveList, MoveType, MAX_LEGAL_MOVES},
+        generate_moves, in_check, left_in_check, legal_moves, move_to_uci,
+    },
+    moves::AttackTable,
+    nnue::{Network, NnueState},
+};
+
+use self::{
+    defs::{Iteration, SearchLimits, SearchParams, SearchResult, INF, MATE, MATE_BOUND, MAX_PLY},
+    tt::{Bound, TranspositionTable},
+};
+
+// piece values used only for move ordering and delta pruning
+const ORDER_VALUES: [i32; NrOf::PIECE_TYPES + 1] = [0, 900, 500, 330, 320, 100, 0];
+const TT_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const KILLER_SCORE: i32 = 90_000;
+const HISTORY_MAX: i32 = 80_000;
+// limits are checked every this many nodes
+const CHECK_INTERVAL: u64 = 1024;
+
+pub struct Search<'a> {
+    at: &'a AttackTable,
+    net: Option<Arc<Network>>,
+    nnue: Option<NnueState>,
+    eval_params: Arc<EvalParams>,
+    pub params: SearchParams,
+    pub tt: TranspositionTable,
+    // print UCI info lines after every iteration
+    pub verbose: bool,
+    limits: SearchLimits,
+    started: Instant,
+    stopped: bool,
+    // set from another thread to end the search, as UCI stop does
+    stop: Arc<AtomicBool>,
+    nodes: u64,
+    // keys of every position before the current one, game history included
+    keys: Vec<u64>,
+    killers: Vec<[Move; 2]>,
+    history: Vec<i32>,
+    pv: Vec<[Move; MAX_PLY]>,
+    pv_length: [usize; MAX_PLY],
+    reductions: Vec<[i32; 64]>,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(at: &'a AttackTable, hash_mb: usize) -> Search<'a> {
+        Search {
+            at,
+            net: None,
+            nnue: None,
+            eval_params: Arc::new(DEFAULT_PARAMS),
+            params: SearchParams::default(),
+            tt: TranspositionTable::new(hash_mb),
+            verbose: false,
+            limits: SearchLimits::default(),
+            started: Instant::now(),
+            stopped: false,
+            stop: Arc::new(AtomicBool::new(false)),
+            nodes: 0,
+            keys: Vec::with_capacity(1024),
+            killers: vec![[Move::default(); 2]; MAX_PLY],
+            history: vec![0; NrOf::COLORS * NrOf::SQUARES * NrOf::SQUARES],
+            pv: vec![[Move::default(); MAX_PLY]; MAX_PLY],
+            pv_length: [0; MAX_PLY],
+            reductions: vec![[0; 64]; 64],
+        }
+    }
+
+    // evaluate with this network instead of the handcrafted evaluation
+    pub fn set_network(&mut self, net: Option<Arc<Network>>) {
+        self.net = net;
+    }
+
+    // the owner of the flag clears it before each search
+    pub fn set_stop_flag(&mut self, stop: Arc<AtomicBool>) {
+        self.stop = stop;
+    }
+
+    // weights for the handcrafted evaluation
+    pub fn set_eval_params(&mut self, params: Arc<EvalParams>) {
+        self.eval_params = params;
+    }
+
+    // forget everything learned in earlier games
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.history.fill(0);
+    }
+
+    // `history` holds the keys of the positions played before `board`, so
+    // repetitions of earlier game positions are scored as draws
+    pub fn go(&mut self, board: &Board, history: &[u64], limits: SearchLimits) -> SearchResult {
+        self.limits = limits;
+        self.started = Instant::now();
+        self.stopped = false;
+        self.nodes = 0;
+        self.keys.clear();
+        self.keys.extend_from_slice(history);
+        self.killers.fill([Move::default(); 2]);
+        self.nnue = self.net.as_deref().map(|net| NnueState::new(net, board));
+        for depth in 1..64 {
+            for moves in 1..64 {
+                let r = self.params.lmr_base as f64 / 100.0
+                    + (depth as f64).ln() * (moves as f64).ln() / (self.params.lmr_divisor as f64 / 100.0);
+                self.reductions[depth][moves] = r as i32;
+            }
+        }
+
+        let mut result = SearchResult::default();
+        let max_depth = limits.depth.unwrap_or(MAX_PLY as i32 - 1).min(MAX_PLY as i32 - 1);
+        for depth in 1..=max_depth {
+            let score = self.aspiration(board, depth, result.score);
+            if self.stopped && depth > 1 {
+                break;
+            }
+
+            result.depth = depth;
+            result.score = score;
+            result.pv = self.pv[0][..self.pv_length[0]].to_vec();
+            result.best_move = result.pv.first().copied().unwrap_or_default();
+            result.nodes = self.nodes;
+            result.iterations.push(Iteration {
+                depth,
+                best_move: result.best_move,
+                time: self.started.elapsed(),
+            });
+            if self.verbose {
+                self.print_info(board, &result);
+            }
+
+            // mated or stalemated at the root, or out of time: nothing more to find
+            if result.best_move.is_null() || self.stopped || self.soft_limit_reached() {
+                break;
+            }
+        }
+
+        // a limit hit before the first iteration had a move still needs an answer
+        if result.best_move.is_null() {
+            if let Some(&m) = legal_moves(board, self.at).iter().next() {
+                result.best_move = m;
+                result.pv = vec![m];
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn print_info(&self, board: &Board, result: &SearchResult) {
+        let elapsed = self.started.elapsed().as_millis();
+        let nps = self.nodes as u128 * 1000 / elapsed.max(1);
+        let pv: Vec<String> = result.pv.iter().map(|&m| move_to_uci(board, m)).collect();
+        println!(
+            "info depth {} score {} nodes {} nps {nps} time {elapsed} pv {}",
+            result.depth,
+            result.score_string(),
+            self.nodes,
+            pv.join(" ")
+        );
+    }
+
+    // an iteration is unlikely to finish when half the time is already gone
+    fn soft_limit_reached(&self) -> bool {
+        self.limits.time.is_some_and(|t| self.started.elapsed() * 2 >= t)
+    }
+
+    fn check_limits(&mut self) {
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            let out_of_time = self.limits.time.is_some_and(|t| self.started.elapsed() >= t);
+            self.stopped |= out_of_time || self.stop.load(Ordering::Relaxed);
+        }
+        if self.limits.nodes.is_some_and(|n| self.nodes >= n) {
+            self.stopped = true;
+        }
+    }
+
+    fn aspiration(&mut self, board: &Board, depth: i32, previous: i32) -> i32 {
+        let mut window = self.params.aspiration_window;
+        let (mut alpha, mut beta) = if depth >= 4 { (previous - window, previous + window) } else { (-INF, INF) };
+        loop {
+            let score = self.negamax(board, depth, alpha, beta, 0, true);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha {
+                alpha = (alpha - window).max(-INF);
+            } else if score >= beta {
+                beta = (beta + window).min(INF);
+            } else {
+                return score;
+            }
+            window *= 2;
+        }
+    }
+
+    fn evaluate(&self, board: &Board) -> i32 {
+        match (self.net.as_deref(), &self.nnue) {
+            (Some(net), Some(state)) => state.evaluate(net, board),
+            _ => evaluate_with(&self.eval_params, board, self.at),
+        }
+    }
+
+    fn is_repetition(&self, board: &Board) -> bool {
+        // only positions with the same side to move since the last irreversible move
+        self.keys
+            .iter()
+            .rev()
+            .take(board.half_move as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&k| k == board.key)
+    }
+
+    fn make(&mut self, board: &Board, next: &Board, m: Move) {
+        self.keys.push(board.key);
+        if let (Some(net), Some(state)) = (self.net.as_deref(), self.nnue.as_mut()) {
+            state.make_move(net, next, m);
+        }
+    }
+
+    fn unmake(&mut self) {
+        self.keys.pop();
+        if let Some(state) = self.nnue.as_mut() {
+            state.unmake_move();
+        }
+    }
+
+    fn negamax(&mut self, board: &Board, mut depth: i32, mut alpha: i32, beta: i32, ply: usize, allow_null: bool) -> i32 {
+        self.pv_length[ply] = ply;
+        let checked = in_check(board, self.at);
+        if checked {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+
+        self.nodes += 1;
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+
+        let root = ply == 0;
+        if !root {
+            if self.is_repetition(board) || board.half_move >= 100 {
+                return 0;
+            }
+            if ply >= MAX_PLY - 1 {
+                return self.evaluate(board);
+            }
+            // no line from here can beat a mate already found closer to the root
+            let mate_alpha = alpha.max(-MATE + ply as i32);
+            let mate_beta = beta.min(MATE - ply as i32 - 1);
+            if mate_alpha >= mate_beta {
+                return mate_alpha;
+            }
+        }
+
+        let pv_node = beta - alpha > 1;
+        let entry = self.tt.probe(board.key, ply);
+        let tt_move = entry.map(|e| e.best_move).unwrap_or_default();
+        if let Some(e) = entry {
+            let usable = match e.bound {
+                Bound::Exact => true,
+                Bound::Lower => e.score >= beta,
+                Bound::Upper => e.score <= alpha,
+            };
+            if !pv_node && !root && e.depth >= depth && usable {
+                return e.score;
+            }
+        }
+
+        let static_eval = if checked { -INF } else { self.evaluate(board) };
+        if !pv_node && !checked {
+            // reverse futility pruning
+            if depth <= 6 && static_eval - self.params.rfp_margin * depth >= beta && static_eval.abs() < MATE_BOUND {
+                return static_eval;
+            }
+
+            // null move pruning, skipped without pieces where zugzwang is likely
+            let us = board.side_to_move();
+            let pieces = board.color[us] & !board.pieces[Pieces::PAWN as usize] & !board.pieces[Pieces::KING as usize];
+            if allow_null && depth >= 3 && static_eval >= beta && pieces != EMPTY {
+                let r = self.params.null_move_base + depth / self.params.null_move_divisor.max(1);
+                let mut next = *board;
+                next.make_null_move();
+                self.keys.push(board.key);
+                let score = -self.negamax(&next, depth - 1 - r, -beta, -beta + 1, ply + 1, false);
+                self.keys.pop();
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    return if score >= MATE_BOUND { beta } else { score };
+                }
+            }
+        }
+
+        let mut list = MoveList::new();
+        generate_moves(board, self.at, MoveType::All, &mut list);
+        let mut scores = self.score_moves(board, &list, tt_move, ply);
+
+        let original_alpha = alpha;
+        let mut best_score = -INF;
+        let mut best_move = Move::default();
+        let mut legal = 0;
+        for i in 0..list.len() {
+            let m = pick_move(&mut list, &mut scores, i);
+            let mut next = *board;
+            next.make_move(m);
+            if left_in_check(&next, self.at) {
+                continue;
+            }
+            legal += 1;
+            if next.king_exploded() {
+                self.pv_length[ply + 1] = ply + 1;
+                self.update_pv(ply, m);
+                return MATE - ply as i32 - 1;
+            }
+
+            let quiet = !m.is_capture() && !m.is_promotion();
+            if !pv_node
+                && !checked
+                && quiet
+                && legal > 1
+                && depth <= 3
+                && best_score > -MATE_BOUND
+                && static_eval + self.params.futility_margin * depth <= alpha
+            {
+                continue;
+            }
+
+            self.make(board, &next, m);
+            let score = if legal == 1 {
+                -self.negamax(&next, depth - 1, -beta, -alpha, ply + 1, true)
+            } else {
+                let reduction = if depth >= 3 && quiet && !checked {
+                    self.reductions[depth.min(63) as usize][legal.min(63)].clamp(0, depth - 2)
+                } else {
+                    0
+                };
+                let mut score = -self.negamax(&next, depth - 1 - reduction, -alpha - 1, -alpha, ply + 1, true);
+                if score > alpha && reduction > 0 {
+                    score = -self.negamax(&next, depth - 1, -alpha - 1, -alpha, ply + 1, true);
+                }
+                if score > alpha && score < beta {
+                    score = -self.negamax(&next, depth - 1, -beta, -alpha, ply + 1, true);
+                }
+                score
+            };
+            self.unmake();
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = m;
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, m);
+                if score >= beta {
+                    if quiet {
+                        self.update_quiet_stats(board, m, depth, ply);
+                    }
+                    break;
+                }
+            }
+        }
+
+        if legal == 0 {
+            return if checked { -MATE + ply as i32 } else { 0 };
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(board.key, best_move, best_score, depth, bound, ply);
+        best_score
+    }
+
+    fn quiescence(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.pv_length[ply] = ply;
+        self.nodes += 1;
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+
+        let stand_pat = self.evaluate(board);
+        if ply >= MAX_PLY - 1 || stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut list = MoveList::new();
+        generate_moves(board, self.at, MoveType::Captures, &mut list);
+        let mut scores = self.score_moves(board, &list, Move::default(), ply);
+        let mut best_score = stand_pat;
+        for i in 0..list.len() {
+            let m = pick_move(&mut list, &mut scores, i);
+            // delta pruning: even winning the piece can't lift the score to alpha
+            if !m.is_promotion() && stand_pat + ORDER_VALUES[m.captured()] + self.params.delta_margin <= alpha {
+                continue;
+            }
+            let mut next = *board;
+            next.make_move(m);
+            if left_in_check(&next, self.at) {
+                continue;
+            }
+            if next.king_exploded() {
+                self.pv_length[ply + 1] = ply + 1;
+                self.update_pv(ply, m);
+                return MATE - ply as i32 - 1;
+            }
+
+            self.make(board, &next, m);
+            let score = -self.quiescence(&next, -beta, -alpha, ply + 1);
+            self.unmake();
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+            }
+            if score > alpha {
+                alpha = score;
+                self.update_pv(ply, m);
+                if score >= beta {
+                    break;
+                }
+            }
+        }
+        best_score
+    }
+
+    fn update_pv(&mut self, ply: usize, m: Move) {
+        self.pv[ply][ply] = m;
+        let child_length = self.pv_length[ply + 1].max(ply + 1);
+        for i in ply + 1..child_length {
+            self.pv[ply][i] = self.pv[ply + 1][i];
+        }
+        self.pv_length[ply] = child_length;
+    }
+
+    fn update_quiet_stats(&mut self, board: &Board, m: Move, depth: i32, ply: usize) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != m {
+            killers[1] = killers[0];
+            killers[0] = m;
+        }
+        let index = history_index(board, m);
+        self.history[index] = (self.history[index] + depth * depth).min(HISTORY_MAX);
+    }
+
+    fn score_moves(&self, board: &Board, list: &MoveList, tt_move: Move, ply: usize) -> [i32; MAX_LEGAL_MOVES] {
+        let mut scores = [0; MAX_LEGAL_MOVES];
+        for (i, &m) in list.iter().enumerate() {
+            scores[i] = if m == tt_move {
+                TT_MOVE_SCORE
+            } else if m.is_capture() || m.is_promotion() {
+                // most valuable victim, least valuable attacker
+                CAPTURE_SCORE + ORDER_VALUES[m.captured()] * 10 + ORDER_VALUES[m.promoted()] * 10 - ORDER_VALUES[m.piece()] / 10
+            } else if self.killers[ply].contains(&m) {
+                KILLER_SCORE
+            } else {
+                self.history[history_index(board, m)]
+            };
+        }
+        scores
+    }
+}
+
+#[inline(always)]
+fn history_index(board: &Board, m: Move) -> usize {
+    (board.side_to_move() * NrOf::SQUARES + m.from()) * NrOf::SQUARES + m.to()
+}
+
+// selection sort step: brings the best remaining move to `index`
+fn pick_move(list: &mut MoveList, scores: &mut [i32; MAX_LEGAL_MOVES], index: usize) -> Move {
+    let mut best = index;
+    for i in index + 1..list.len() {
+        if scores[i] > scores[best] {
+            best = i;
+        }
+    }
+    list.swap(index, best);
+    scores.swap(index, best);
+    list.get(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::with_table;
+
+    #[test]
+    fn fixed_node_search_is_deterministic() {
+        with_table(|at| {
+            let mut board = Board::new();
+            board.parse_fen(Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")).unwrap();
+            let run = |search: &mut Search| {
+                let result = search.go(&board, &[], SearchLimits::nodes(20_000));
+                (result.best_move, result.score, result.depth, result.nodes, result.pv)
+            };
+            let first = run(&mut Search::new(at, 1));
+            assert!(!first.0.is_null() && first.2 > 1);
+            assert_eq!(run(&mut Search::new(at, 1)), first);
+
+            // a cleared search forgets the table and history the first run left
+            let mut search = Search::new(at, 1);
+            run(&mut search);
+            search.clear();
+            assert_eq!(run(&mut search), first);
+        });
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use crate::movegen::defs::Move;
+
+pub const INF: i32 = 32000;
+pub const MATE: i32 = 31000;
+pub const MAX_PLY: usize = 128;
+// scores beyond this are mates, counted in plies from the root
+pub const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+// any combination may be set; the search stops at whichever is reached first
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchLimits {
+    pub depth: Option<i32>,
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+impl SearchLimits {
+    pub fn nodes(nodes: u64) -> SearchLimits {
+        SearchLimits { nodes: Some(nodes), ..Default::default() }
+    }
+
+    pub fn time(time: Duration) -> SearchLimits {
+        SearchLimits { time: Some(time), ..Default::default() }
+    }
+}
+
+// tunable pruning and reduction parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchParams {
+    pub aspiration_window: i32,
+    pub null_move_base: i32,
+    pub null_move_divisor: i32,
+    pub rfp_margin: i32,
+    pub futility_margin: i32,
+    // late move reductions are base + ln(depth) * ln(moves) / divisor, both in hundredths
+    pub lmr_base: i32,
+    pub lmr_divisor: i32,
+    pub delta_margin: i32,
+}
+
+impl Default for SearchParams {
+    fn default() -> SearchParams {
+        SearchParams {
+            aspiration_window: 25,
+            null_move_base: 3,
+            null_move_divisor: 4,
+            rfp_margin: 80,
+            futility_margin: 100,
+            lmr_base: 75,
+            lmr_divisor: 225,
+            delta_margin: 200,
+        }
+    }
+}
+
+// UCI option name and range of every search parameter, in field order; step
+// is the perturbation SPSA ends with
+pub struct SearchParamSpec {
+    pub name: &'static str,
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+}
+
+pub const SEARCH_PARAM_SPECS: [SearchParamSpec; 8] = [
+    SearchParamSpec { name: "AspirationWindow", min: 5, max: 100, step: 4 },
+    SearchParamSpec { name: "NullMoveBase", min: 1, max: 6, step: 1 },
+    SearchParamSpec { name: "NullMoveDivisor", min: 2, max: 8, step: 1 },
+    SearchParamSpec { name: "RfpMargin", min: 20, max: 200, step: 8 },
+    SearchParamSpec { name: "FutilityMargin", min: 20, max: 300, step: 10 },
+    SearchParamSpec { name: "LmrBase", min: 0, max: 200, step: 8 },
+    SearchParamSpec { name: "LmrDivisor", min: 100, max: 400, step: 15 },
+    SearchParamSpec { name: "DeltaMargin", min: 50, max: 500, step: 20 },
+];
+
+impl SearchParams {
+    pub fn values(&self) -> [i32; 8] {
+        [
+            self.aspiration_window,
+            self.null_move_base,
+            self.null_move_divisor,
+            self.rfp_margin,
+            self.futility_margin,
+            self.lmr_base,
+            self.lmr_divisor,
+            self.delta_margin,
+        ]
+    }
+
+    // values out of range are clamped to the spec
+    pub fn from_values(values: &[i32; 8]) -> SearchParams {
+        let v: Vec<i32> = values.iter().zip(&SEARCH_PARAM_SPECS).map(|(&v, spec)| v.clamp(spec.min, spec.max)).collect();
+        SearchParams {
+            aspiration_window: v[0],
+            null_move_base: v[1],
+            null_move_divisor: v[2],
+            rfp_margin: v[3],
+            futility_margin: v[4],
+            lmr_base: v[5],
+            lmr_divisor: v[6],
+            delta_margin: v[7],
+        }
+    }
+
+    // false if no parameter has this UCI name
+    pub fn set(&mut self, name: &str, value: i32) -> bool {
+        let Some(i) = SEARCH_PARAM_SPECS.iter().position(|spec| spec.name.eq_ignore_ascii_case(name)) else {
+            return false;
+        };
+        let mut values = self.values();
+        values[i] = value;
+        *self = SearchParams::from_values(&values);
+        true
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchResult {
+    pub best_move: Move,
+    // from the side to move's point of view
+    pub score: i32,
+    pub depth: i32,
+    pub nodes: u64,
+    pub pv: Vec<Move>,
+    // every completed iteration in order, for seeing when a move was found
+    pub iterations: Vec<Iteration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Iteration {
+    pub depth: i32,
+    pub best_move: Move,
+    pub time: Duration,
+}
+
+impl SearchResult {
+    // UCI score field: "cp <n>" or "mate <moves>"
+    pub fn score_string(&self) -> String {
+        if self.score.abs() >= MATE_BOUND {
+            let plies = MATE - self.score.abs();
+            let moves = (plies + 1) / 2;
+            format!("mate {}", if self.score > 0 { moves } else { -moves })
+        } else {
+            format!("cp {}", self.score)
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{
+    movegen::defs::Move,
+    search::defs::MATE_BOUND,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Entry {
+    pub key: u64,
+    pub best_move: Move,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+// always-replace hash table indexed by the low bits of the key
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl TranspositionTable {
+    pub fn new(megabytes: usize) -> TranspositionTable {
+        let count = (megabytes.max(1) << 20) / std::mem::size_of::<Option<Entry>>();
+        TranspositionTable { entries: vec![None; count] }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    #[inline(always)]
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    pub fn probe(&self, key: u64, ply: usize) -> Option<Entry> {
+        let mut entry = self.entries[self.index(key)].filter(|e| e.key == key)?;
+        entry.score = score_from_tt(entry.score, ply);
+        Some(entry)
+    }
+
+    pub fn store(&mut self, key: u64, best_move: Move, score: i32, depth: i32, bound: Bound, ply: usize) {
+        let index = self.index(key);
+        // keep the old move when this search didn't find one
+        let best_move = match self.entries[index] {
+            Some(old) if old.key == key && best_move.is_null() => old.best_move,
+            _ => best_move,
+        };
+        self.entries[index] = Some(Entry { key, best_move, score: score_to_tt(score, ply), depth, bound });
+    }
+}
+
+// mate scores are stored relative to the node rather than the root
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{defs::Pieces, search::defs::MATE};
+
+    #[test]
+    fn store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        let m = Move::new(Pieces::KNIGHT as usize, 6, 21, Pieces::NONE as usize, Pieces::NONE as usize);
+        tt.store(0x1234, m, 37, 5, Bound::Lower, 3);
+        let entry = tt.probe(0x1234, 7).unwrap();
+        assert_eq!((entry.best_move, entry.score, entry.depth, entry.bound), (m, 37, 5, Bound::Lower));
+        assert!(tt.probe(0x1235, 3).is_none());
+
+        // a search that finds no move keeps the stored one
+        tt.store(0x1234, Move::default(), 12, 6, Bound::Upper, 3);
+        assert_eq!(tt.probe(0x1234, 3).unwrap().best_move, m);
+
+        tt.clear();
+        assert!(tt.probe(0x1234, 3).is_none());
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_node() {
+        let mut tt = TranspositionTable::new(1);
+        // mate in 5 plies from the root found at ply 2 is mate in 3 from the node
+        tt.store(1, Move::default(), MATE - 5, 8, Bound::Exact, 2);
+        assert_eq!(tt.entries[tt.index(1)].unwrap().score, MATE - 3);
+        assert_eq!(tt.probe(1, 2).unwrap().score, MATE - 5);
+        // the same node reached at ply 6 is mate in 9 from the root
+        assert_eq!(tt.probe(1, 6).unwrap().score, MATE - 9);
+
+        tt.store(2, Move::default(), -(MATE - 4), 8, Bound::Upper, 4);
+        assert_eq!(tt.entries[tt.index(2)].unwrap().score, -MATE);
+        assert_eq!(tt.probe(2, 1).unwrap().score, -(MATE - 1));
+
+        // ordinary scores are stored as they are
+        tt.store(3, Move::default(), -250, 8, Bound::Exact, 9);
+        assert_eq!(tt.probe(3, 1).unwrap().score, -250);
+    }
+}
@@ -1,7 +1,6 @@
 use crate::{
-    board::{Board},
-    board::bitboard::{Bitboard, get_bit, get_lsb, count_bits, print_bitboard, pop_bit},
-    defs::{Castling, Colors, Color, ALL_SQUARES, Pieces, BB_SQUARES, NrOf, Piece, Square, EMPTY},
+    board::bitboard::{Bitboard, get_bit, get_lsb, count_bits, pop_bit},
+    defs::{ALL_SQUARES, Square},
 };
 
 pub const BISHOP_RELEVANT_BITS: [i32; 64] = [
@@ -29,12 +28,14 @@ pub const ROOK_RELEVANT_BITS: [i32; 64] = [
 // pub const rook_magic_numbers: [u64;
 
 // sorcerer creates magic
+#[allow(dead_code)]
 pub struct Sorcerer {
     pub state: u32,
     pub bishop_magic_numbers: [u64; 64],
     pub rook_magic_numbers: [u64; 64],
 }
 
+#[allow(dead_code)]
 impl Sorcerer {
     pub fn new() -> Sorcerer {
         Sorcerer {
@@ -81,8 +82,7 @@ impl Sorcerer {
         for _ in 0..100000000 {
             let magic = self.generate_magic_num();
 
-            // this will cause an overflow
-            if count_bits((attack_mask * magic) & 0xFF00000000000000) < 6 {
+            if count_bits(attack_mask.wrapping_mul(magic) & 0xFF00000000000000) < 6 {
                 continue;
             }
 
@@ -90,7 +90,7 @@ impl Sorcerer {
             let mut used_attacks: [Bitboard; 4096] = [0; 4096];
 
             for i in 0..occupancy_index {
-                let index = (occupancies[i as usize] * magic) >> (64 - relevant_bits);
+                let index = occupancies[i as usize].wrapping_mul(magic) >> (64 - relevant_bits);
                 if used_attacks[index as usize] == 0 {
                     used_attacks[index as usize] = attacks[i as usize];
                 } else if used_attacks[index as usize] != attacks[i as usize] {
@@ -108,15 +108,15 @@ impl Sorcerer {
     }
 
     pub fn init_magic_numbers(&mut self) {
-        for sq in 0..NrOf::SQUARES {
-            self.rook_magic_numbers[sq] = self.find_magic_number(sq, ROOK_RELEVANT_BITS[sq], false);
+        for (sq, &bits) in ROOK_RELEVANT_BITS.iter().enumerate() {
+            self.rook_magic_numbers[sq] = self.find_magic_number(sq, bits, false);
             println!("0x{:x},", self.rook_magic_numbers[sq]);
         }
 
         println!();
 
-        for sq in 0..NrOf::SQUARES {
-            self.bishop_magic_numbers[sq] = self.find_magic_number(sq, BISHOP_RELEVANT_BITS[sq], true);
+        for (sq, &bits) in BISHOP_RELEVANT_BITS.iter().enumerate() {
+            self.bishop_magic_numbers[sq] = self.find_magic_number(sq, bits, true);
             println!("0x{:x},", self.bishop_magic_numbers[sq]);
         }
     }
@@ -199,7 +199,7 @@ pub fn rook_attacks(sq: Square, blocks: Bitboard) -> Bitboard {
 
 pub fn set_occupancy(index: Bitboard, bits_in_mask: usize, attack_mask: Bitboard) -> Bitboard {
     let mut occ: Bitboard = 0;
-    let mut atm = attack_mask.clone();
+    let mut atm = attack_mask;
 
     for i in 0..bits_in_mask {
         let sq = get_lsb(atm);
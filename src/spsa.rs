@@ -0,0 +1,259 @@
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::{
+    board::Board,
+    datagen::{load_book, play_opening, DatagenError},
+    defs::Colors,
+    moves::AttackTable,
+    rng::Rng,
+    search::{
+        defs::{SearchLimits, SearchParams, SEARCH_PARAM_SPECS},
+        Search,
+    },
+};
+
+// learning rate at the last iteration, relative to step squared
+pub const DEFAULT_R_END: f64 = 0.002;
+const SPSA_HASH_MB: usize = 4;
+const PARAM_COUNT: usize = SEARCH_PARAM_SPECS.len();
+
+// games are adjudicated once both engines agree for long enough
+const WIN_SCORE: i32 = 1000;
+const WIN_PLIES: usize = 4;
+const DRAW_AFTER: usize = 80;
+const DRAW_SCORE: i32 = 10;
+const DRAW_PLIES: usize = 8;
+const MAX_PLIES: usize = 300;
+
+#[derive(Debug)]
+pub enum SpsaError {
+    Io(String),
+    BadOption(String),
+    Book(DatagenError),
+}
+
+impl Display for SpsaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error writing SPSA log: {e}"),
+            Self::BadOption(o) => write!(f, "Error in spsa options: {o}"),
+            Self::Book(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+pub struct SpsaOptions {
+    pub output: String,
+    pub iterations: usize,
+    // every iteration plays this many openings twice, with colours swapped
+    pub pairs: usize,
+    pub nodes: u64,
+    pub book: Option<String>,
+    pub random_plies: usize,
+    pub threads: usize,
+    pub seed: u64,
+    pub r_end: f64,
+    // decay exponents of the learning rate and the perturbation
+    pub alpha: f64,
+    pub gamma: f64,
+}
+
+impl Default for SpsaOptions {
+    fn default() -> SpsaOptions {
+        SpsaOptions {
+            output: String::from("spsa.csv"),
+            iterations: 500,
+            pairs: 8,
+            nodes: 3000,
+            book: None,
+            random_plies: 8,
+            threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            seed: 1,
+            r_end: DEFAULT_R_END,
+            alpha: 0.602,
+            gamma: 0.101,
+        }
+    }
+}
+
+impl SpsaOptions {
+    // rustChess spsa [--out file.csv] [--iterations n] [--pairs n] [--nodes n] [--book file.epd]
+    //   [--random-plies n] [--threads n] [--seed n] [--r-end f] [--alpha f] [--gamma f]
+    pub fn parse(args: &[String]) -> Result<SpsaOptions, SpsaError> {
+        let mut options = SpsaOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = iter.next().ok_or_else(|| SpsaError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || SpsaError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--out" => options.output = value.clone(),
+                "--iterations" => options.iterations = value.parse().map_err(|_| bad())?,
+                "--pairs" => options.pairs = value.parse().map_err(|_| bad())?,
+                "--nodes" => options.nodes = value.parse().map_err(|_| bad())?,
+                "--book" => options.book = Some(value.clone()),
+                "--random-plies" => options.random_plies = value.parse().map_err(|_| bad())?,
+                "--threads" => options.threads = value.parse().map_err(|_| bad())?,
+                "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+                "--r-end" => options.r_end = value.parse().map_err(|_| bad())?,
+                "--alpha" => options.alpha = value.parse().map_err(|_| bad())?,
+                "--gamma" => options.gamma = value.parse().map_err(|_| bad())?,
+                _ => return Err(SpsaError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.threads == 0 || options.nodes == 0 || options.pairs == 0 || options.iterations == 0 {
+            return Err(SpsaError::BadOption(String::from("threads, nodes, pairs and iterations must be positive")));
+        }
+        Ok(options)
+    }
+}
+
+// plays one game at fixed nodes and returns the result from white's point of view
+fn play_game<'a>(
+    at: &AttackTable,
+    white: &mut Search<'a>,
+    black: &mut Search<'a>,
+    nodes: u64,
+    mut board: Board,
+    mut keys: Vec<u64>,
+) -> f64 {
+    let limits = SearchLimits::nodes(nodes);
+    let (mut white_wins, mut black_wins, mut quiet_plies) = (0, 0, 0);
+
+    for ply in 0..MAX_PLIES {
+        let white_to_move = board.side_to_move() == Colors::WHITE as usize;
+        if let Some(result) = board.status(at, &keys).result() {
+            return result;
+        }
+
+        let search = if white_to_move { &mut *white } else { &mut *black };
+        let found = search.go(&board, &keys, limits);
+        let white_score = if white_to_move { found.score } else { -found.score };
+        white_wins = if white_score >= WIN_SCORE { white_wins + 1 } else { 0 };
+        black_wins = if white_score <= -WIN_SCORE { black_wins + 1 } else { 0 };
+        quiet_plies = if ply >= DRAW_AFTER && white_score.abs() <= DRAW_SCORE { quiet_plies + 1 } else { 0 };
+        if white_wins >= WIN_PLIES {
+            return 1.0;
+        }
+        if black_wins >= WIN_PLIES {
+            return 0.0;
+        }
+        if quiet_plies >= DRAW_PLIES {
+            return 0.5;
+        }
+
+        keys.push(board.key);
+        board.make_move(found.best_move);
+    }
+    0.5
+}
+
+// wins minus losses of `plus` against `minus` over every opening pair
+fn play_iteration(
+    at: &AttackTable,
+    options: &SpsaOptions,
+    book: &[Board],
+    iteration: usize,
+    plus: SearchParams,
+    minus: SearchParams,
+) -> f64 {
+    let next_game = AtomicUsize::new(0);
+    let total = Mutex::new(0.0);
+    thread::scope(|s| {
+        for _ in 0..options.threads.min(options.pairs * 2) {
+            s.spawn(|| {
+                let mut plus_search = Search::new(at, SPSA_HASH_MB);
+                let mut minus_search = Search::new(at, SPSA_HASH_MB);
+                plus_search.params = plus;
+                minus_search.params = minus;
+                loop {
+                    let index = next_game.fetch_add(1, Ordering::Relaxed);
+                    if index >= options.pairs * 2 {
+                        return;
+                    }
+                    // both games of a pair start from the same opening
+                    let pair = (iteration * options.pairs + index / 2) as u64;
+                    let mut rng = Rng::new(options.seed.wrapping_mul(0x9e3779b97f4a7c15) ^ pair);
+                    let Some((board, keys)) = play_opening(at, book, options.random_plies, &mut rng) else { continue };
+                    plus_search.clear();
+                    minus_search.clear();
+                    let plus_score = if index.is_multiple_of(2) {
+                        play_game(at, &mut plus_search, &mut minus_search, options.nodes, board, keys)
+                    } else {
+                        1.0 - play_game(at, &mut minus_search, &mut plus_search, options.nodes, board, keys)
+                    };
+                    *total.lock().expect("spsa result lock poisoned") += 2.0 * plus_score - 1.0;
+                }
+            });
+        }
+    });
+    total.into_inner().expect("spsa result lock poisoned")
+}
+
+// simultaneous perturbation stochastic approximation: each iteration moves
+// every parameter by ±c at once, plays the two resulting engines against each
+// other and steps along the perturbation in proportion to the result. the
+// gain sequences follow the usual a / (A + k)^alpha and c / k^gamma schedule,
+// scaled so that c ends at each parameter's step and a at r_end * step^2
+pub fn spsa(at: &AttackTable, options: &SpsaOptions) -> Result<(), SpsaError> {
+    let book = match &options.book {
+        Some(path) => load_book(path).map_err(SpsaError::Book)?,
+        None => Vec::new(),
+    };
+    let file = File::create(&options.output).map_err(|e| SpsaError::Io(format!("{}: {e}", options.output)))?;
+    let mut csv = BufWriter::new(file);
+    let io_error = |e: std::io::Error| SpsaError::Io(e.to_string());
+
+    let names: Vec<&str> = SEARCH_PARAM_SPECS.iter().map(|spec| spec.name).collect();
+    writeln!(csv, "iteration,result,{}", names.join(",")).map_err(io_error)?;
+
+    let n = options.iterations as f64;
+    let big_a = n / 10.0;
+    let mut theta: Vec<f64> = SearchParams::default().values().iter().map(|&v| v as f64).collect();
+    let mut rng = Rng::new(options.seed);
+    let started = Instant::now();
+
+    for k in 1..=options.iterations {
+        let c_scale = (n / k as f64).powf(options.gamma);
+        let a_scale = ((big_a + n) / (big_a + k as f64)).powf(options.alpha);
+        let c: Vec<f64> = SEARCH_PARAM_SPECS.iter().map(|spec| spec.step as f64 * c_scale).collect();
+        let delta: Vec<f64> = (0..PARAM_COUNT).map(|_| if rng.next_u64() & 1 == 0 { 1.0 } else { -1.0 }).collect();
+
+        let mut plus = [0; PARAM_COUNT];
+        let mut minus = [0; PARAM_COUNT];
+        for i in 0..PARAM_COUNT {
+            plus[i] = (theta[i] + c[i] * delta[i]).round() as i32;
+            minus[i] = (theta[i] - c[i] * delta[i]).round() as i32;
+        }
+        let result = play_iteration(
+            at,
+            options,
+            &book,
+            k,
+            SearchParams::from_values(&plus),
+            SearchParams::from_values(&minus),
+        );
+
+        for (i, spec) in SEARCH_PARAM_SPECS.iter().enumerate() {
+            // a_k / c_k^2 * c_k = r_end * step^2 * a_scale / c_k
+            let r = options.r_end * (spec.step as f64).powi(2) * a_scale / c[i];
+            theta[i] = (theta[i] + r * result * delta[i]).clamp(spec.min as f64, spec.max as f64);
+        }
+
+        let values: Vec<String> = theta.iter().map(|v| format!("{v:.2}")).collect();
+        writeln!(csv, "{k},{result},{}", values.join(",")).map_err(io_error)?;
+        csv.flush().map_err(io_error)?;
+        println!("Iteration {k}/{}: result {result:+}, {}s", options.iterations, started.elapsed().as_secs());
+    }
+
+    println!("Final parameters:");
+    for (name, value) in names.iter().zip(&theta) {
+        println!("setoption name {name} value {}", value.round() as i32);
+    }
+    Ok(())
+}
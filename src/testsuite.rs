@@ -0,0 +1,361 @@
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::{
+    board::fen::Epd,
+    movegen::{defs::Move, san::move_to_san},
+    moves::AttackTable,
+    search::{
+        defs::{SearchLimits, SearchResult},
+        Search,
+    },
+    uci::UciOptions,
+};
+
+#[derive(Debug)]
+pub enum TestSuiteError {
+    Io(String),
+    BadOption(String),
+    BadPosition(usize, String),
+    Engine(String),
+}
+
+impl Display for TestSuiteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error in test suite files: {e}"),
+            Self::BadOption(o) => write!(f, "Error in testsuite options: {o}"),
+            Self::BadPosition(line, e) => write!(f, "Error in test suite line {line}: {e}"),
+            Self::Engine(e) => write!(f, "Error in engine options: {e}"),
+        }
+    }
+}
+
+pub struct TestSuiteOptions {
+    pub suite: String,
+    // per position, in milliseconds; nodes take over when given
+    pub time: u64,
+    pub nodes: Option<u64>,
+    pub engine: Vec<(String, String)>,
+    pub csv: String,
+    // the suite written back with this run's acd, ce and pv
+    pub epd: Option<String>,
+    // positions searched at the same time, one search thread each
+    pub threads: usize,
+}
+
+impl Default for TestSuiteOptions {
+    fn default() -> TestSuiteOptions {
+        TestSuiteOptions {
+            suite: String::new(),
+            time: 1000,
+            nodes: None,
+            engine: Vec::new(),
+            csv: String::from("testsuite.csv"),
+            epd: None,
+            threads: 1,
+        }
+    }
+}
+
+impl TestSuiteOptions {
+    // rustChess testsuite <file.epd> [--time ms] [--nodes n] [--option Name=Value]... [--csv file] [--epd file] [--threads n]
+    pub fn parse(args: &[String]) -> Result<TestSuiteOptions, TestSuiteError> {
+        let mut options = TestSuiteOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                options.suite = arg.clone();
+                continue;
+            }
+            let value = iter.next().ok_or_else(|| TestSuiteError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || TestSuiteError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--time" => options.time = value.parse().map_err(|_| bad())?,
+                "--nodes" => options.nodes = Some(value.parse().map_err(|_| bad())?),
+                "--option" => {
+                    let (name, v) = value.split_once('=').ok_or_else(bad)?;
+                    options.engine.push((name.to_string(), v.to_string()));
+                }
+                "--csv" => options.csv = value.clone(),
+                "--epd" => options.epd = Some(value.clone()),
+                "--threads" => options.threads = value.parse().map_err(|_| bad())?,
+                _ => return Err(TestSuiteError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.suite.is_empty() {
+            return Err(TestSuiteError::BadOption(String::from("no test suite given")));
+        }
+        if options.threads == 0 || options.time == 0 || options.nodes == Some(0) {
+            return Err(TestSuiteError::BadOption(String::from("threads, time and nodes must be positive")));
+        }
+        Ok(options)
+    }
+
+    fn limits(&self) -> SearchLimits {
+        match self.nodes {
+            Some(nodes) => SearchLimits::nodes(nodes),
+            None => SearchLimits::time(Duration::from_millis(self.time)),
+        }
+    }
+}
+
+// a position with the moves it wants played and avoided
+struct Problem {
+    id: String,
+    epd: Epd,
+    best: Vec<Move>,
+    avoid: Vec<Move>,
+}
+
+impl Problem {
+    fn accepts(&self, m: Move) -> bool {
+        (self.best.is_empty() || self.best.contains(&m)) && !self.avoid.contains(&m)
+    }
+
+    fn expected(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(bm) = self.epd.operands("bm") {
+            parts.push(format!("bm {}", bm.join(" ")));
+        }
+        if let Some(am) = self.epd.operands("am") {
+            parts.push(format!("am {}", am.join(" ")));
+        }
+        parts.join(" ")
+    }
+}
+
+fn load_problems(at: &AttackTable, path: &str) -> Result<Vec<Problem>, TestSuiteError> {
+    let text = fs::read_to_string(path).map_err(|e| TestSuiteError::Io(format!("{path}: {e}")))?;
+    let mut problems = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let bad = |e: String| TestSuiteError::BadPosition(n + 1, e);
+        let epd = Epd::parse(line).map_err(|e| bad(e.to_string()))?;
+        let best = epd.best_moves(at).map_err(|e| bad(e.to_string()))?;
+        let avoid = epd.avoid_moves(at).map_err(|e| bad(e.to_string()))?;
+        if best.is_empty() && avoid.is_empty() {
+            return Err(bad(String::from("no bm or am")));
+        }
+        let id = epd.id().map_or_else(|| format!("line {}", n + 1), String::from);
+        problems.push(Problem { id, epd, best, avoid });
+    }
+    if problems.is_empty() {
+        return Err(TestSuiteError::BadPosition(0, String::from("no positions")));
+    }
+    Ok(problems)
+}
+
+struct Outcome {
+    played: Move,
+    solved: bool,
+    // iteration from which every later one had an accepted move
+    solved_at: Option<(i32, Duration)>,
+    result: SearchResult,
+}
+
+fn outcome(problem: &Problem, result: SearchResult) -> Outcome {
+    let played = result.best_move;
+    let solved = problem.accepts(played);
+    let solved_at = match solved {
+        true => {
+            let first = result.iterations.iter().rposition(|i| !problem.accepts(i.best_move)).map_or(0, |i| i + 1);
+            // a move played without a finished iteration counts from the end
+            result.iterations.get(first).map_or(Some((result.depth, Duration::ZERO)), |i| Some((i.depth, i.time)))
+        }
+        false => None,
+    };
+    Outcome { played, solved, solved_at, result }
+}
+
+// searches every position of an EPD suite and checks the move found against
+// its bm and am opcodes. a position counts as solved from the iteration that
+// found an accepted move and kept it to the end
+pub fn testsuite(at: &AttackTable, options: &TestSuiteOptions) -> Result<(), TestSuiteError> {
+    let mut engine = UciOptions::new();
+    for (name, value) in &options.engine {
+        engine.set(name, value).map_err(TestSuiteError::Engine)?;
+    }
+    let problems = load_problems(at, &options.suite)?;
+    let outcomes: Mutex<Vec<Option<Outcome>>> = Mutex::new((0..problems.len()).map(|_| None).collect());
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..options.threads.min(problems.len()) {
+            s.spawn(|| {
+                let mut search = Search::new(at, engine.hash_mb);
+                engine.configure(&mut search);
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(problem) = problems.get(index) else { return };
+                    search.clear();
+                    let result = outcome(problem, search.go(&problem.epd.board, &[], options.limits()));
+                    let san = move_to_san(&problem.epd.board, at, result.played);
+                    match result.solved_at {
+                        Some((depth, time)) => println!(
+                            "{}: solved in {:.3}s at depth {depth} ({san})",
+                            problem.id,
+                            time.as_secs_f64()
+                        ),
+                        None => println!("{}: unsolved, played {san} ({})", problem.id, problem.expected()),
+                    }
+                    outcomes.lock().expect("test suite lock poisoned")[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    let outcomes: Vec<Outcome> = outcomes.into_inner().expect("test suite lock poisoned").into_iter().flatten().collect();
+    write_csv(at, &options.csv, &problems, &outcomes)?;
+    if let Some(path) = &options.epd {
+        write_epd(at, path, &problems, &outcomes)?;
+    }
+
+    println!("{}", summary(&outcomes));
+    println!("Results written to {}", options.csv);
+    if let Some(path) = &options.epd {
+        println!("Annotated suite written to {path}");
+    }
+    Ok(())
+}
+
+// solved count, search time and, when anything was solved, the averages
+fn summary(outcomes: &[Outcome]) -> String {
+    let solved: Vec<&Outcome> = outcomes.iter().filter(|o| o.solved).collect();
+    let total_time: f64 = outcomes.iter().filter_map(|o| o.result.iterations.last()).map(|i| i.time.as_secs_f64()).sum();
+    let solve_time: f64 = solved.iter().filter_map(|o| o.solved_at).map(|(_, t)| t.as_secs_f64()).sum();
+    let mut summary = format!(
+        "Solved {}/{} ({:.1}%), searched for {total_time:.1}s",
+        solved.len(),
+        outcomes.len(),
+        100.0 * solved.len() as f64 / outcomes.len() as f64
+    );
+    if !solved.is_empty() {
+        let depth: i32 = solved.iter().filter_map(|o| o.solved_at).map(|(d, _)| d).sum();
+        summary.push_str(&format!(
+            "\nAverage solve time {:.3}s, average solve depth {:.1}",
+            solve_time / solved.len() as f64,
+            depth as f64 / solved.len() as f64
+        ));
+    }
+    summary
+}
+
+fn write_csv(at: &AttackTable, path: &str, problems: &[Problem], outcomes: &[Outcome]) -> Result<(), TestSuiteError> {
+    let io = |e: std::io::Error| TestSuiteError::Io(format!("{path}: {e}"));
+    let mut out = BufWriter::new(File::create(path).map_err(io)?);
+    writeln!(out, "id,fen,expected,played,solved,solve_depth,solve_ms,depth,nodes,score").map_err(io)?;
+    for (problem, outcome) in problems.iter().zip(outcomes) {
+        let (solve_depth, solve_ms) = match outcome.solved_at {
+            Some((depth, time)) => (depth.to_string(), time.as_millis().to_string()),
+            None => (String::new(), String::new()),
+        };
+        let fields = [
+            csv_field(&problem.id),
+            problem.epd.board.as_epd(),
+            csv_field(&problem.expected()),
+            move_to_san(&problem.epd.board, at, outcome.played),
+            outcome.solved.to_string(),
+            solve_depth,
+            solve_ms,
+            outcome.result.depth.to_string(),
+            outcome.result.nodes.to_string(),
+            csv_field(&outcome.result.score_string()),
+        ];
+        writeln!(out, "{}", fields.join(",")).map_err(io)?;
+    }
+    out.flush().map_err(io)
+}
+
+// bm and am are rewritten in this engine's SAN, and every position gets an id
+fn write_epd(at: &AttackTable, path: &str, problems: &[Problem], outcomes: &[Outcome]) -> Result<(), TestSuiteError> {
+    let io = |e: std::io::Error| TestSuiteError::Io(format!("{path}: {e}"));
+    let mut out = BufWriter::new(File::create(path).map_err(io)?);
+    for (problem, outcome) in problems.iter().zip(outcomes) {
+        let mut epd = problem.epd.clone();
+        if !problem.best.is_empty() {
+            epd.set_best_moves(at, &problem.best);
+        }
+        if !problem.avoid.is_empty() {
+            epd.set_avoid_moves(at, &problem.avoid);
+        }
+        epd.set_id(&problem.id);
+        epd.set_depth(outcome.result.depth.max(0) as u32);
+        epd.set_eval(outcome.result.score);
+        epd.set_pv(at, &outcome.result.pv);
+        writeln!(out, "{}", epd.as_epd()).map_err(io)?;
+    }
+    out.flush().map_err(io)
+}
+
+// quoted when it holds a comma or a quote
+fn csv_field(s: &str) -> String {
+    match s.contains([',', '"']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::with_table;
+
+    const MATE_IN_ONE: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq -";
+
+    #[test]
+    fn checks_moves_against_bm_and_am() {
+        with_table(|at| {
+            let dir = std::env::temp_dir().join(format!("rustchess-testsuite-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let suite = dir.join("suite.epd");
+            let suite_text = [
+                format!("{MATE_IN_ONE} bm Qxf7#; id \"mate\";"),
+                format!("{MATE_IN_ONE} am Qxf7#; id \"avoid mate\";"),
+                format!("{MATE_IN_ONE} bm Qh5 Qg3; id \"wrong\";"),
+                format!("{MATE_IN_ONE} am Qh5 Nh3;"),
+            ];
+            fs::write(&suite, suite_text.join("\n")).unwrap();
+
+            let problems = load_problems(at, suite.to_str().unwrap()).unwrap();
+            assert_eq!(problems[3].id, "line 4");
+            assert_eq!(problems[2].expected(), "bm Qh5 Qg3");
+
+            let mut search = Search::new(at, 1);
+            let outcomes: Vec<Outcome> = problems
+                .iter()
+                .map(|problem| {
+                    search.clear();
+                    outcome(problem, search.go(&problem.epd.board, &[], SearchLimits::nodes(5000)))
+                })
+                .collect();
+            assert!(outcomes.iter().all(|o| move_to_san(&problems[0].epd.board, at, o.played) == "Qxf7#"));
+            assert_eq!(outcomes.iter().map(|o| o.solved).collect::<Vec<bool>>(), [true, false, false, true]);
+            assert_eq!(outcomes[0].solved_at.map(|(depth, _)| depth), Some(1));
+            assert!(outcomes[1].solved_at.is_none());
+
+            let summary = summary(&outcomes);
+            assert!(summary.starts_with("Solved 2/4 (50.0%)"), "{summary}");
+            assert!(summary.ends_with("average solve depth 1.0"), "{summary}");
+
+            let csv = dir.join("results.csv");
+            write_csv(at, csv.to_str().unwrap(), &problems, &outcomes).unwrap();
+            let rows: Vec<Vec<String>> = fs::read_to_string(&csv)
+                .unwrap()
+                .lines()
+                .skip(1)
+                .map(|line| line.split(',').map(String::from).collect())
+                .collect();
+            assert_eq!(rows[1][..5], ["avoid mate", MATE_IN_ONE, "am Qxf7#", "Qxf7#", "false"]);
+            assert_eq!(rows[3][4], "true");
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -0,0 +1,405 @@
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use self::engine::{EngineFailure, EngineSpec, UciEngine};
+use crate::{
+    arena::{
+        load_openings, result_string,
+        stats::Tally,
+        today, write_pgn, GameRecord, MatchError, TimeControl,
+    },
+    board::Board,
+    datagen::play_opening,
+    defs::Colors,
+    movegen::{defs::Move, parse_move},
+    moves::AttackTable,
+    pgn::writer::ExportOptions,
+    rng::Rng,
+};
+
+pub mod engine;
+
+#[derive(Debug)]
+pub enum TournamentError {
+    Io(String),
+    BadOption(String),
+    Openings(MatchError),
+}
+
+impl Display for TournamentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "Error writing tournament results: {e}"),
+            Self::BadOption(o) => write!(f, "Error in tournament options: {o}"),
+            Self::Openings(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schedule {
+    // every engine against every other
+    RoundRobin,
+    // the first engine against each of the others
+    Gauntlet,
+}
+
+pub struct TournamentOptions {
+    pub engines: Vec<EngineSpec>,
+    pub schedule: Schedule,
+    // per pairing, in pairs with colours swapped
+    pub games: usize,
+    pub time_control: TimeControl,
+    // milliseconds an engine may overstep its clock before it forfeits
+    pub margin: u64,
+    pub openings: Option<String>,
+    // random plies after the opening; 0 with an opening file, 8 without
+    pub random_plies: Option<usize>,
+    pub max_plies: usize,
+    pub concurrency: usize,
+    pub pgn: String,
+    pub pgn_format: ExportOptions,
+    pub crosstable: Option<String>,
+    pub seed: u64,
+}
+
+impl Default for TournamentOptions {
+    fn default() -> TournamentOptions {
+        TournamentOptions {
+            engines: Vec::new(),
+            schedule: Schedule::RoundRobin,
+            games: 2,
+            time_control: TimeControl { base: 10_000, inc: 100 },
+            margin: 100,
+            openings: None,
+            random_plies: None,
+            max_plies: 400,
+            concurrency: 1,
+            pgn: String::from("tournament.pgn"),
+            pgn_format: ExportOptions::default(),
+            crosstable: None,
+            seed: 1,
+        }
+    }
+}
+
+impl TournamentOptions {
+    // rustChess tournament --engine cmd=path[,name=s][,arg=s][,option.Name=value] --engine ...
+    //   [--schedule round-robin|gauntlet] [--games n] [--tc seconds+inc] [--margin ms]
+    //   [--openings file] [--random-plies n] [--max-plies n] [--concurrency n]
+    //   [--pgn file] [--pgn-format export|reduced] [--crosstable file] [--seed n]
+    pub fn parse(args: &[String]) -> Result<TournamentOptions, TournamentError> {
+        let mut options = TournamentOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let value = iter.next().ok_or_else(|| TournamentError::BadOption(format!("{arg} needs a value")))?;
+            let bad = || TournamentError::BadOption(format!("{arg} {value}"));
+            match arg.as_str() {
+                "--engine" => options.engines.push(EngineSpec::parse(value).map_err(TournamentError::BadOption)?),
+                "--schedule" => {
+                    options.schedule = match value.as_str() {
+                        "round-robin" => Schedule::RoundRobin,
+                        "gauntlet" => Schedule::Gauntlet,
+                        _ => return Err(bad()),
+                    }
+                }
+                "--games" => options.games = value.parse().map_err(|_| bad())?,
+                "--tc" => options.time_control = TimeControl::parse(value).ok_or_else(bad)?,
+                "--margin" => options.margin = value.parse().map_err(|_| bad())?,
+                "--openings" => options.openings = Some(value.clone()),
+                "--random-plies" => options.random_plies = Some(value.parse().map_err(|_| bad())?),
+                "--max-plies" => options.max_plies = value.parse().map_err(|_| bad())?,
+                "--concurrency" => options.concurrency = value.parse().map_err(|_| bad())?,
+                "--pgn" => options.pgn = value.clone(),
+                "--pgn-format" => options.pgn_format = ExportOptions::from_name(value).ok_or_else(bad)?,
+                "--crosstable" => options.crosstable = Some(value.clone()),
+                "--seed" => options.seed = value.parse().map_err(|_| bad())?,
+                _ => return Err(TournamentError::BadOption(format!("unknown option {arg}"))),
+            }
+        }
+        if options.engines.len() < 2 {
+            return Err(TournamentError::BadOption(String::from("at least two engines are needed")));
+        }
+        if options.concurrency == 0 || options.games == 0 {
+            return Err(TournamentError::BadOption(String::from("concurrency and games must be positive")));
+        }
+        Ok(options)
+    }
+}
+
+// one scheduled game: engine indices and the opening it starts from
+struct Pairing {
+    white: usize,
+    black: usize,
+    opening: usize,
+}
+
+fn schedule(options: &TournamentOptions) -> Vec<Pairing> {
+    let n = options.engines.len();
+    let opponents: Vec<(usize, usize)> = match options.schedule {
+        Schedule::RoundRobin => (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).collect(),
+        Schedule::Gauntlet => (1..n).map(|j| (0, j)).collect(),
+    };
+    let mut games = Vec::new();
+    for (i, j) in opponents {
+        for g in 0..options.games {
+            let (white, black) = if g.is_multiple_of(2) { (i, j) } else { (j, i) };
+            games.push(Pairing { white, black, opening: g / 2 });
+        }
+    }
+    games
+}
+
+struct Outcome {
+    moves: Vec<Move>,
+    result: f64,
+    termination: &'static str,
+    reason: String,
+}
+
+impl Outcome {
+    fn forfeit(moves: Vec<Move>, white_loses: bool, failure: EngineFailure) -> Outcome {
+        let side = if white_loses { "White" } else { "Black" };
+        let (termination, reason) = match failure {
+            EngineFailure::Crash(e) => ("abandoned", format!("{side} disconnects: {e}")),
+            EngineFailure::Timeout => ("time forfeit", format!("{side} loses on time")),
+        };
+        Outcome { moves, result: if white_loses { 0.0 } else { 1.0 }, termination, reason }
+    }
+}
+
+fn play_game(at: &AttackTable, options: &TournamentOptions, specs: [&EngineSpec; 2], mut board: Board, mut keys: Vec<u64>) -> Outcome {
+    let mut engines = Vec::new();
+    for (side, spec) in specs.iter().enumerate() {
+        match UciEngine::start(spec).and_then(|mut e| e.new_game().map(|_| e)) {
+            Ok(engine) => engines.push(engine),
+            Err(failure) => return Outcome::forfeit(Vec::new(), side == 0, failure),
+        }
+    }
+
+    let tc = options.time_control;
+    let mut clocks = [tc.base as i64; 2];
+    let position = format!("position fen {}", board.as_fen());
+    let mut uci_moves: Vec<String> = Vec::new();
+    let mut moves = Vec::new();
+
+    for _ in 0..options.max_plies {
+        let side = if board.side_to_move() == Colors::WHITE as usize { 0 } else { 1 };
+        // claimable draws are claimed straight away
+        let status = board.status(at, &keys);
+        if let Some(result) = status.result() {
+            return Outcome { moves, result, termination: "normal", reason: String::from(status.as_string()) };
+        }
+
+        let command = if uci_moves.is_empty() { position.clone() } else { format!("{position} moves {}", uci_moves.join(" ")) };
+        let go = format!("go wtime {} btime {} winc {} binc {}", clocks[0].max(1), clocks[1].max(1), tc.inc, tc.inc);
+        let timeout = Duration::from_millis(clocks[side].max(0) as u64 + options.margin);
+        let answer = match engines[side].go(&command, &go, timeout) {
+            Ok(answer) => answer,
+            Err(failure) => return Outcome::forfeit(moves, side == 0, failure),
+        };
+        clocks[side] -= answer.elapsed.as_millis() as i64;
+        if clocks[side] < -(options.margin as i64) {
+            return Outcome::forfeit(moves, side == 0, EngineFailure::Timeout);
+        }
+        clocks[side] = clocks[side].max(0) + tc.inc as i64;
+
+        // only moves in the board's own legal move list are accepted
+        let Some(m) = parse_move(&board, at, &answer.best_move) else {
+            let name = if side == 0 { "White" } else { "Black" };
+            let reason = format!("{name} plays an illegal move: {}", answer.best_move);
+            return Outcome { moves, result: if side == 0 { 0.0 } else { 1.0 }, termination: "rules infraction", reason };
+        };
+        moves.push(m);
+        uci_moves.push(answer.best_move);
+        keys.push(board.key);
+        board.make_move(m);
+    }
+    Outcome { moves, result: 0.5, termination: "adjudication", reason: String::from("Draw by move limit") }
+}
+
+struct Standings {
+    names: Vec<String>,
+    // points[i][j]: what engine i scored against engine j
+    points: Vec<Vec<f64>>,
+    games: Vec<Vec<u32>>,
+    tallies: Vec<Tally>,
+}
+
+impl Standings {
+    fn new(names: Vec<String>) -> Standings {
+        let n = names.len();
+        Standings { names, points: vec![vec![0.0; n]; n], games: vec![vec![0; n]; n], tallies: vec![Tally::default(); n] }
+    }
+
+    fn add(&mut self, white: usize, black: usize, result: f64) {
+        self.points[white][black] += result;
+        self.points[black][white] += 1.0 - result;
+        self.games[white][black] += 1;
+        self.games[black][white] += 1;
+        self.tallies[white].add(result);
+        self.tallies[black].add(1.0 - result);
+    }
+
+    // ranked by points, with each pairing's score and an Elo estimate against the field
+    fn crosstable(&self) -> String {
+        let n = self.names.len();
+        let total = |i: usize| self.points[i].iter().sum::<f64>();
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| total(b).total_cmp(&total(a)));
+        let width = self.names.iter().map(String::len).max().unwrap_or(4).max(4);
+
+        let mut text = format!("{:<4} {:<width$} {:>7} {:>5} {:>16}", "Rank", "Name", "Points", "Games", "Elo");
+        for (k, _) in order.iter().enumerate() {
+            text.push_str(&format!(" {:>7}", k + 1));
+        }
+        text.push('\n');
+        for (rank, &i) in order.iter().enumerate() {
+            let elo = match self.tallies[i].elo() {
+                // rounded first so a result just under even shows +0, not -0
+                Some((elo, margin)) => format!("{:+.0} +/- {margin:.0}", elo.round() + 0.0),
+                None => String::from("-"),
+            };
+            text.push_str(&format!(
+                "{:<4} {:<width$} {:>7.1} {:>5} {:>16}",
+                rank + 1,
+                self.names[i],
+                total(i),
+                self.tallies[i].games(),
+                elo
+            ));
+            for &j in &order {
+                let cell = if i == j || self.games[i][j] == 0 {
+                    String::from("-")
+                } else {
+                    format!("{}/{}", self.points[i][j], self.games[i][j])
+                };
+                text.push_str(&format!(" {cell:>7}"));
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+pub fn tournament(at: &AttackTable, options: &TournamentOptions) -> Result<(), TournamentError> {
+    let openings = match &options.openings {
+        Some(path) => load_openings(path).map_err(TournamentError::Openings)?,
+        None => Vec::new(),
+    };
+    let random_plies = options.random_plies.unwrap_or(if openings.is_empty() { 8 } else { 0 });
+    let games = schedule(options);
+    let file = File::create(&options.pgn).map_err(|e| TournamentError::Io(format!("{}: {e}", options.pgn)))?;
+    let names = options.engines.iter().map(|e| e.name.clone()).collect();
+    let output = Mutex::new((BufWriter::new(file), Standings::new(names)));
+    let next_game = AtomicUsize::new(0);
+    let date = today();
+
+    thread::scope(|s| -> Result<(), TournamentError> {
+        let workers: Vec<_> = (0..options.concurrency.min(games.len()))
+            .map(|_| {
+                s.spawn(|| -> Result<(), TournamentError> {
+                    loop {
+                        let index = next_game.fetch_add(1, Ordering::Relaxed);
+                        let Some(pairing) = games.get(index) else { return Ok(()) };
+                        let mut rng = Rng::new(options.seed.wrapping_mul(0x9e3779b97f4a7c15) ^ pairing.opening as u64);
+                        let book = if openings.is_empty() { &openings[..] } else { &openings[pairing.opening % openings.len()..][..1] };
+                        let Some((start, keys)) = play_opening(at, book, random_plies, &mut rng) else { continue };
+
+                        let white = &options.engines[pairing.white];
+                        let black = &options.engines[pairing.black];
+                        let outcome = play_game(at, options, [white, black], start, keys);
+                        let game = GameRecord {
+                            event: String::from("rustChess tournament"),
+                            round: index + 1,
+                            white: white.name.clone(),
+                            black: black.name.clone(),
+                            start,
+                            moves: outcome.moves,
+                            result: outcome.result,
+                            time_control: Some(options.time_control),
+                            termination: outcome.termination,
+                            reason: outcome.reason,
+                        };
+
+                        let mut output = output.lock().expect("tournament output lock poisoned");
+                        let (pgn, standings) = &mut *output;
+                        write_pgn(pgn, &game, &date, at, &options.pgn_format).and_then(|_| pgn.flush()).map_err(|e| TournamentError::Io(e.to_string()))?;
+                        standings.add(pairing.white, pairing.black, game.result);
+                        println!(
+                            "Game {}/{}: {} - {} {} ({})",
+                            index + 1,
+                            games.len(),
+                            game.white,
+                            game.black,
+                            result_string(game.result),
+                            game.reason
+                        );
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().expect("tournament thread panicked")?;
+        }
+        Ok(())
+    })?;
+
+    let (_, standings) = output.into_inner().expect("tournament output lock poisoned");
+    let table = standings.crosstable();
+    println!();
+    print!("{table}");
+    if let Some(path) = &options.crosstable {
+        fs::write(path, &table).map_err(|e| TournamentError::Io(format!("{path}: {e}")))?;
+    }
+    println!("Games written to {}", options.pgn);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::with_table;
+
+    // a shell stand-in that answers the handshake and does `on_go` when asked to move
+    #[cfg(unix)]
+    fn stub(name: &str, on_go: &str) -> EngineSpec {
+        let script = format!("while read cmd rest; do case $cmd in uci) echo uciok;; isready) echo readyok;; go) {on_go};; quit) exit;; esac; done");
+        EngineSpec { name: name.to_string(), command: String::from("sh"), args: vec![String::from("-c"), script], options: Vec::new() }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_engines_lose() {
+        with_table(|at| {
+            let options = TournamentOptions { time_control: TimeControl { base: 200, inc: 0 }, margin: 100, ..Default::default() };
+            let mut start = Board::new();
+            start.parse_fen(None).unwrap();
+            let good = stub("good", "echo bestmove e2e4");
+
+            let crash = stub("crash", "exit 1");
+            let outcome = play_game(at, &options, [&crash, &good], start, Vec::new());
+            assert_eq!((outcome.result, outcome.termination), (0.0, "abandoned"));
+            assert!(outcome.moves.is_empty());
+
+            let stall = stub("stall", "exec sleep 5");
+            let outcome = play_game(at, &options, [&good, &stall], start, Vec::new());
+            assert_eq!((outcome.result, outcome.termination), (1.0, "time forfeit"));
+            assert_eq!(outcome.reason, "Black loses on time");
+            assert_eq!(outcome.moves.len(), 1);
+
+            let illegal = stub("illegal", "echo bestmove a1a1");
+            let outcome = play_game(at, &options, [&illegal, &good], start, Vec::new());
+            assert_eq!((outcome.result, outcome.termination), (0.0, "rules infraction"));
+
+            let missing = EngineSpec::parse("cmd=/nonexistent/engine").unwrap();
+            let outcome = play_game(at, &options, [&good, &missing], start, Vec::new());
+            assert_eq!((outcome.result, outcome.termination), (1.0, "abandoned"));
+        });
+    }
+}
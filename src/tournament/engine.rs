@@ -0,0 +1,182 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long an engine may take to answer uci and isready
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+const QUIT_GRACE: Duration = Duration::from_millis(200);
+
+// how to start an engine: its command line and the options to set after uci
+#[derive(Debug, Clone)]
+pub struct EngineSpec {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineSpec {
+    // comma separated key=value pairs: cmd=path, name=s, arg=s (repeatable), option.<Name>=value
+    pub fn parse(s: &str) -> Result<EngineSpec, String> {
+        let mut spec = EngineSpec { name: String::new(), command: String::new(), args: Vec::new(), options: Vec::new() };
+        for part in s.split(',') {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("expected key=value, got {part}"))?;
+            match key {
+                "cmd" => spec.command = value.to_string(),
+                "name" => spec.name = value.to_string(),
+                "arg" => spec.args.push(value.to_string()),
+                _ => match key.strip_prefix("option.") {
+                    Some(option) => spec.options.push((option.to_string(), value.to_string())),
+                    None => return Err(format!("unknown engine key {key}")),
+                },
+            }
+        }
+        if spec.command.is_empty() {
+            return Err(String::from("engine needs cmd=<path>"));
+        }
+        if spec.name.is_empty() {
+            spec.name = spec.command.rsplit('/').next().unwrap_or(&spec.command).to_string();
+        }
+        Ok(spec)
+    }
+}
+
+// why an engine lost its game without the position deciding it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineFailure {
+    // the process could not be started, exited or closed its output
+    Crash(String),
+    Timeout,
+}
+
+// the answer to a go command
+pub struct EngineMove {
+    pub best_move: String,
+    pub elapsed: Duration,
+}
+
+// an external engine speaking UCI on its standard input and output. stdout
+// is read on its own thread so every wait can time out
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    pub fn start(spec: &EngineSpec) -> Result<UciEngine, EngineFailure> {
+        let mut child = Command::new(&spec.command)
+            .args(&spec.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| EngineFailure::Crash(format!("{}: {e}", spec.command)))?;
+        let stdin = child.stdin.take().expect("engine stdin is piped");
+        let stdout = child.stdout.take().expect("engine stdout is piped");
+
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine { child, stdin, lines };
+        engine.send("uci")?;
+        engine.wait_for("uciok", HANDSHAKE_TIMEOUT)?;
+        for (name, value) in &spec.options {
+            engine.send(&format!("setoption name {name} value {value}"))?;
+        }
+        engine.ready()?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, command: &str) -> Result<(), EngineFailure> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| EngineFailure::Crash(format!("write failed: {e}")))
+    }
+
+    pub fn ready(&mut self) -> Result<(), EngineFailure> {
+        self.send("isready")?;
+        self.wait_for("readyok", HANDSHAKE_TIMEOUT)
+    }
+
+    pub fn new_game(&mut self) -> Result<(), EngineFailure> {
+        self.send("ucinewgame")?;
+        self.ready()
+    }
+
+    // sends position and go, then waits at most `timeout` for bestmove
+    pub fn go(&mut self, position: &str, go: &str, timeout: Duration) -> Result<EngineMove, EngineFailure> {
+        self.send(position)?;
+        self.send(go)?;
+        let started = Instant::now();
+        loop {
+            let line = self.next_line(timeout.saturating_sub(started.elapsed()))?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.first() == Some(&"bestmove") {
+                let best_move = tokens.get(1).map(|m| m.to_string()).unwrap_or_default();
+                return Ok(EngineMove { best_move, elapsed: started.elapsed() });
+            }
+        }
+    }
+
+    fn wait_for(&mut self, expected: &str, timeout: Duration) -> Result<(), EngineFailure> {
+        let started = Instant::now();
+        while self.next_line(timeout.saturating_sub(started.elapsed()))?.trim() != expected {}
+        Ok(())
+    }
+
+    fn next_line(&mut self, timeout: Duration) -> Result<String, EngineFailure> {
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => EngineFailure::Timeout,
+            RecvTimeoutError::Disconnected => EngineFailure::Crash(String::from("engine exited")),
+        })
+    }
+}
+
+impl Drop for UciEngine {
+    // asks politely, then kills whatever did not listen
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let started = Instant::now();
+        while started.elapsed() < QUIT_GRACE {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_engine_specs() {
+        let spec = EngineSpec::parse("cmd=/usr/bin/stockfish,arg=--threads,arg=2,option.Hash=64,option.Skill Level=5").unwrap();
+        assert_eq!(spec.name, "stockfish");
+        assert_eq!(spec.command, "/usr/bin/stockfish");
+        assert_eq!(spec.args, ["--threads", "2"]);
+        assert_eq!(spec.options, [("Hash".to_string(), "64".to_string()), ("Skill Level".to_string(), "5".to_string())]);
+        assert_eq!(EngineSpec::parse("name=sf,cmd=sf").unwrap().name, "sf");
+    }
+
+    #[test]
+    fn rejects_bad_engine_specs() {
+        assert_eq!(EngineSpec::parse("cmd=sf,depth=3").unwrap_err(), "unknown engine key depth");
+        assert_eq!(EngineSpec::parse("name=sf,arg=x").unwrap_err(), "engine needs cmd=<path>");
+        assert_eq!(EngineSpec::parse("cmd=sf,threads").unwrap_err(), "expected key=value, got threads");
+        assert!(EngineSpec::parse("cmd=").is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, ScopedJoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{
+    board::Board,
+    book::Book,
+    defs::Variant,
+    evaluation::{evaluate_with, params::{EvalParams, DEFAULT_PARAMS}, trace::print_trace},
+    movegen::{move_to_uci, parse_move},
+    moves::AttackTable,
+    nnue::{Network, NnueState},
+    rng::Rng,
+    spsa::DEFAULT_R_END,
+    search::{
+        defs::{SearchLimits, SearchParams, SEARCH_PARAM_SPECS},
+        tt::TranspositionTable,
+        Search,
+    },
+};
+
+const ENGINE_NAME: &str = "rustChess";
+const ENGINE_AUTHOR: &str = "zchown";
+
+const DEFAULT_HASH_MB: usize = 16;
+const MAX_HASH_MB: usize = 4096;
+// time kept in reserve for communication overhead
+const MOVE_OVERHEAD_MS: u64 = 30;
+
+pub struct UciOptions {
+    pub use_nnue: bool,
+    pub eval_file: String,
+    pub eval_params: String,
+    pub hash_mb: usize,
+    pub search_params: SearchParams,
+    pub net: Arc<Network>,
+    pub params: Arc<EvalParams>,
+    pub own_book: bool,
+    pub book_file: String,
+    // the heaviest book move instead of a weighted random one
+    pub book_best: bool,
+    pub book: Option<Arc<Book>>,
+    pub chess960: bool,
+    pub variant: Variant,
+}
+
+impl UciOptions {
+    pub fn new() -> UciOptions {
+        UciOptions {
+            use_nnue: false,
+            eval_file: String::from("<default>"),
+            eval_params: String::from("<default>"),
+            hash_mb: DEFAULT_HASH_MB,
+            search_params: SearchParams::default(),
+            net: Arc::new(Network::default_net()),
+            params: Arc::new(DEFAULT_PARAMS),
+            own_book: false,
+            book_file: String::from("<empty>"),
+            book_best: false,
+            book: None,
+            chess960: false,
+            variant: Variant::Standard,
+        }
+    }
+
+    // a file that fails to load keeps the current network or parameters
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "UseNNUE" => self.use_nnue = value == "true",
+            "EvalFile" => {
+                let loaded = if value == "<default>" { Network::default_net() } else { Network::load(value).map_err(|e| e.to_string())? };
+                self.net = Arc::new(loaded);
+                self.eval_file = value.to_string();
+            }
+            // weights for the handcrafted evaluation, as written by the tune command
+            "EvalParams" => {
+                let loaded = if value == "<default>" { DEFAULT_PARAMS } else { EvalParams::load(value).map_err(|e| e.to_string())? };
+                self.params = Arc::new(loaded);
+                self.eval_params = value.to_string();
+            }
+            "OwnBook" => self.own_book = value == "true",
+            "BookFile" => {
+                self.book = match value {
+                    "" | "<empty>" => None,
+                    path => Some(Arc::new(Book::load(path).map_err(|e| e.to_string())?)),
+                };
+                self.book_file = value.to_string();
+            }
+            "BookBestMove" => self.book_best = value == "true",
+            "UCI_Chess960" => self.chess960 = value == "true",
+            "UCI_Variant" => self.variant = Variant::from_name(value).ok_or(format!("Unknown variant: {value}"))?,
+            "Hash" => {
+                let mb: usize = value.parse().map_err(|_| format!("Bad Hash value: {value}"))?;
+                self.hash_mb = mb.clamp(1, MAX_HASH_MB);
+            }
+            _ => {
+                if !SEARCH_PARAM_SPECS.iter().any(|spec| spec.name == name) {
+                    return Err(format!("Unknown option: {name}"));
+                }
+                let v: i32 = value.parse().map_err(|_| format!("Bad {name} value: {value}"))?;
+                self.search_params.set(name, v);
+            }
+        }
+        Ok(())
+    }
+
+    // hands everything but the hash size to a search
+    pub fn configure(&self, search: &mut Search) {
+        search.set_network(self.use_nnue.then(|| Arc::clone(&self.net)));
+        search.set_eval_params(Arc::clone(&self.params));
+        search.params = self.search_params;
+    }
+}
+
+pub fn uci_loop(at: &AttackTable) {
+    let mut board = Board::new();
+    let _ = board.parse_fen(None);
+    let mut history: Vec<u64> = Vec::new();
+    let mut options = UciOptions::new();
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    let mut rng = Rng::new(seed);
+
+    // the search runs on its own thread so stop, isready and quit are read
+    // while it thinks. commands that change the search or the position stop
+    // it first, as the worker holds the search lock until it answers
+    let stop = Arc::new(AtomicBool::new(false));
+    let mut search = Search::new(at, options.hash_mb);
+    search.set_stop_flag(Arc::clone(&stop));
+    let search = Mutex::new(search);
+    thread::scope(|s| {
+        let mut worker: Option<ScopedJoinHandle<()>> = None;
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.first() {
+                Some(&"uci") => {
+                    println!("id name {ENGINE_NAME}");
+                    println!("id author {ENGINE_AUTHOR}");
+                    println!("option name UseNNUE type check default false");
+                    println!("option name EvalFile type string default <default>");
+                    println!("option name EvalParams type string default <default>");
+                    println!("option name OwnBook type check default false");
+                    println!("option name BookFile type string default <empty>");
+                    println!("option name BookBestMove type check default false");
+                    println!("option name UCI_Chess960 type check default false");
+                    let variants: Vec<String> = Variant::ALL.iter().map(|v| format!("var {}", v.as_string())).collect();
+                    println!("option name UCI_Variant type combo default chess {}", variants.join(" "));
+                    println!("option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}");
+                    let defaults = SearchParams::default().values();
+                    for (spec, default) in SEARCH_PARAM_SPECS.iter().zip(defaults) {
+                        println!("option name {} type spin default {default} min {} max {}", spec.name, spec.min, spec.max);
+                    }
+                    println!("uciok");
+                }
+                Some(&"isready") => println!("readyok"),
+                Some(&"ucinewgame") => {
+                    stop_search(&mut worker, &stop);
+                    let _ = board.parse_fen(None);
+                    history.clear();
+                    lock(&search).clear();
+                }
+                Some(&"setoption") => {
+                    stop_search(&mut worker, &stop);
+                    let hash_mb = options.hash_mb;
+                    if let Err(e) = set_option(&mut options, &tokens[1..]) {
+                        println!("info string {e}");
+                    }
+                    if options.hash_mb != hash_mb {
+                        lock(&search).tt = TranspositionTable::new(options.hash_mb);
+                    }
+                }
+                Some(&"position") => {
+                    stop_search(&mut worker, &stop);
+                    if let Err(e) = position(&mut board, &mut history, at, &options, &tokens[1..]) {
+                        println!("info string {e}");
+                    }
+                }
+                Some(&"go") => {
+                    let book_move = match (&options.book, options.own_book) {
+                        (Some(book), true) if board.variant == Variant::Standard => book.probe(&board, at, options.book_best, &mut rng),
+                        _ => None,
+                    };
+                    if let Some(m) = book_move {
+                        println!("bestmove {}", move_to_uci(&board, m));
+                        continue;
+                    }
+                    // a search still running is stopped rather than queued behind
+                    stop_search(&mut worker, &stop);
+                    stop.store(false, Ordering::Relaxed);
+                    options.configure(&mut lock(&search));
+                    let (board, history) = (board, history.clone());
+                    let limits = go_limits(&board, &tokens[1..]);
+                    // go infinite holds its bestmove until stop even when the search ends early
+                    let infinite = tokens.contains(&"infinite");
+                    let (search, stop) = (&search, &stop);
+                    worker = Some(s.spawn(move || {
+                        let mut search = lock(search);
+                        search.verbose = true;
+                        let result = search.go(&board, &history, limits);
+                        while infinite && !stop.load(Ordering::Relaxed) {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        println!("bestmove {}", if result.best_move.is_null() { String::from("0000") } else { move_to_uci(&board, result.best_move) });
+                    }));
+                }
+                Some(&"stop") => stop.store(true, Ordering::Relaxed),
+                // not part of UCI: search parameters as SPSA input, name, type, value, min, max, step
+                Some(&"spsa") => {
+                    for (spec, value) in SEARCH_PARAM_SPECS.iter().zip(options.search_params.values()) {
+                        println!("{}, int, {value}, {}, {}, {}, {DEFAULT_R_END}", spec.name, spec.min, spec.max, spec.step);
+                    }
+                }
+                // not part of UCI: static evaluation breakdown for the current position
+                Some(&"eval") => {
+                    if options.use_nnue {
+                        let state = NnueState::new(&options.net, &board);
+                        println!("NNUE evaluation: {} (side to move)", state.evaluate(&options.net, &board));
+                    } else {
+                        print_trace(&options.params, &board, at);
+                        println!("Static evaluation: {} (side to move)", evaluate_with(&options.params, &board, at));
+                    }
+                }
+                Some(&"quit") => {
+                    stop_search(&mut worker, &stop);
+                    break;
+                }
+                Some(cmd) => println!("info string Unknown command: {cmd}"),
+                None => (),
+            }
+        }
+        // quit or the end of input: the scope waits for the search to answer
+        stop.store(true, Ordering::Relaxed);
+    });
+}
+
+// ends a running search and waits for its bestmove
+fn stop_search(worker: &mut Option<ScopedJoinHandle<()>>, stop: &AtomicBool) {
+    if let Some(running) = worker.take() {
+        stop.store(true, Ordering::Relaxed);
+        let _ = running.join();
+    }
+}
+
+// a search thread that panicked leaves the search usable for the next go
+fn lock<'a, 'b>(search: &'b Mutex<Search<'a>>) -> MutexGuard<'b, Search<'a>> {
+    search.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// setoption name <name> value <value>
+fn set_option(options: &mut UciOptions, args: &[&str]) -> Result<(), String> {
+    let value_at = args.iter().position(|&t| t == "value");
+    let name = match (args.first(), args.get(1..value_at.unwrap_or(args.len()))) {
+        (Some(&"name"), Some(name)) if !name.is_empty() => name.join(" "),
+        _ => return Err(String::from("setoption needs name <id> [value <x>]")),
+    };
+    let value = value_at.map(|i| args[i + 1..].join(" ")).unwrap_or_default();
+    options.set(&name, &value)?;
+    match name.as_str() {
+        "EvalFile" => println!("info string Loaded network {}", options.eval_file),
+        "EvalParams" => println!("info string Loaded evaluation parameters {}", options.eval_params),
+        "BookFile" => match &options.book {
+            Some(book) => println!("info string Loaded book {} with {} entries", options.book_file, book.entries.len()),
+            None => println!("info string No book"),
+        },
+        _ => (),
+    }
+    Ok(())
+}
+
+// position [startpos | fen <fen>] [moves <move> ...]. with UCI_Chess960 on,
+// castling is read and written as the king taking its rook even from the usual
+// start, and UCI_Variant applies even when the FEN has no pockets
+fn position(board: &mut Board, history: &mut Vec<u64>, at: &AttackTable, options: &UciOptions, args: &[&str]) -> Result<(), String> {
+    let moves_at = args.iter().position(|&t| t == "moves").unwrap_or(args.len());
+    match args.first() {
+        Some(&"startpos") => board.parse_fen(None).map_err(|e| e.to_string())?,
+        Some(&"fen") => board.parse_fen(Some(&args[1..moves_at].join(" "))).map_err(|e| e.to_string())?,
+        _ => return Err(String::from("position needs startpos or fen")),
+    }
+    board.chess960 |= options.chess960;
+    if options.variant != Variant::Standard {
+        board.variant = options.variant;
+    }
+    history.clear();
+    for &s in args.iter().skip(moves_at + 1) {
+        let m = parse_move(board, at, s).ok_or(format!("Illegal move: {s}"))?;
+        history.push(board.key);
+        board.make_move(m);
+    }
+    Ok(())
+}
+
+// go [depth n] [nodes n] [movetime ms] [wtime ms] [btime ms] [winc ms] [binc ms] [movestogo n] [infinite]
+fn go_limits(board: &Board, args: &[&str]) -> SearchLimits {
+    let value = |name: &str| -> Option<u64> {
+        let i = args.iter().position(|&t| t == name)?;
+        args.get(i + 1)?.parse().ok()
+    };
+    let mut limits = SearchLimits {
+        depth: value("depth").map(|d| d as i32),
+        nodes: value("nodes"),
+        time: value("movetime").map(|ms| Duration::from_millis(ms.saturating_sub(MOVE_OVERHEAD_MS).max(1))),
+    };
+
+    let (time, inc) = if board.turn { (value("wtime"), value("winc")) } else { (value("btime"), value("binc")) };
+    if let Some(time) = time {
+        limits.time = Some(time_budget(time, inc.unwrap_or(0), value("movestogo")));
+    }
+    limits
+}
+
+// thinking time for one move with `time` ms left on the clock
+pub fn time_budget(time: u64, inc: u64, moves_to_go: Option<u64>) -> Duration {
+    let moves_to_go = moves_to_go.unwrap_or(30).max(1);
+    let budget = time / moves_to_go + inc * 3 / 4;
+    let cap = time.saturating_sub(MOVE_OVERHEAD_MS).max(1);
+    Duration::from_millis(budget.min(cap).max(1))
+}